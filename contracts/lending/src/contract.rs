@@ -1,12 +1,17 @@
 #![no_std]
 
 use soroban_sdk::{
-    contract, contractimpl, map, symbol_short, token, Address, Env, Map, String, Symbol, Vec,
-}; // contracterror, panic_with_error, vec
+    contract, contractimpl, map, panic_with_error, symbol_short, token, xdr::ToXdr, Address, Bytes,
+    BytesN, Env, Map, String, Symbol, Vec,
+}; // contracterror, vec
 
-use core::ops::{Add, Div, Mul};
+use core::ops::{Div, Mul};
 use rust_decimal::prelude::{Decimal, MathematicalOps, ToPrimitive};
 
+use crate::errors::LendingError;
+use crate::events;
+use crate::flash_loan::FlashLoanReceiverClient;
+use crate::mm_token::MMTokenClient;
 use crate::storage::*;
 use crate::utilities::*;
 
@@ -28,630 +33,643 @@ impl LendingContract {
         collateral_vault_contract: Address,
     ) {
         if has_admin(&env) {
-            panic!("already initialized")
+            panic_with_error!(&env, LendingError::AlreadyInitialized)
         }
         set_admin(&env, &admin);
         set_liquidator(&env, &liquidator);
         set_vault_contract(&env, &collateral_vault_contract);
+        set_storage_version(&env, STORAGE_VERSION);
     }
 
-    pub fn deposit(env: Env, user_address: Address, denom: Symbol, deposited_token_amount: u128) {
-        user_address.require_auth();
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
 
-        let token_address: Address = get_token_address(env.clone(), denom.clone());
-        move_token(
-            &env,
-            &token_address,
-            &user_address,
-            &env.current_contract_address(),
-            deposited_token_amount.clone() as i128,
-        );
+        env.deployer()
+            .update_current_contract_wasm(new_wasm_hash.clone());
 
-        execute_update_liquidity_index_data(env.clone(), denom.clone());
+        events::upgrade(&env, new_wasm_hash);
+    }
 
-        let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
-        let mm_token_price: u128 = get_mm_token_price(env.clone(), denom.clone());
+    pub fn migrate(env: Env) {
+        // Admin only, rewrites the storage written by older contract versions
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
 
-        let deposited_mm_token_amount =
-            Decimal::from_i128_with_scale(deposited_token_amount as i128, token_decimals)
-                .div(Decimal::from_i128_with_scale(
-                    mm_token_price as i128,
-                    token_decimals,
-                ))
-                .to_u128_with_decimals(token_decimals)
-                .unwrap();
+        let storage_version: u32 = get_storage_version(&env);
 
-        let user_current_mm_token_balance: u128 = env
-            .storage()
-            .persistent()
-            .get(&DataKey::UserMMTokenBalance(user_address.clone()))
-            .unwrap_or(Map::new(&env))
-            .get(denom.clone())
-            .unwrap_or(0_u128);
+        if storage_version >= STORAGE_VERSION {
+            panic_with_error!(&env, LendingError::StorageUpToDate);
+        }
 
-        let new_user_mm_token_balance: u128 =
-            user_current_mm_token_balance + deposited_mm_token_amount;
+        if storage_version < 1 {
+            migrate_storage_v0(&env);
+        }
 
-        let mut user_mm_balance_map: Map<Symbol, u128> = env
-            .storage()
-            .persistent()
-            .get(&DataKey::UserMMTokenBalance(user_address.clone()))
-            .unwrap_or(Map::new(&env));
-        user_mm_balance_map.set(denom.clone(), new_user_mm_token_balance);
-        env.storage().persistent().set(
-            &DataKey::UserMMTokenBalance(user_address.clone()),
-            &user_mm_balance_map,
-        );
-        env.storage().persistent().bump(
-            &DataKey::UserMMTokenBalance(user_address.clone()),
-            MONTH_LIFETIME_THRESHOLD,
-            MONTH_BUMP_AMOUNT,
-        );
+        if storage_version < 2 {
+            migrate_storage_v1(&env);
+        }
 
-        move_token(
-            &env,
-            &get_token_address(env.clone(), denom.clone()),
-            &env.current_contract_address(),
-            &get_vault_contract(&env),
-            deposited_token_amount.clone() as i128,
-        )
-    }
+        set_storage_version(&env, STORAGE_VERSION);
 
-    pub fn redeem(env: Env, user: Address, denom: Symbol, mut amount: u128) {
-        user.require_auth();
+        events::migrate(&env, storage_version, STORAGE_VERSION);
+    }
 
-        // let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+    pub fn get_storage_version(env: Env) -> u32 {
+        get_storage_version(&env)
+    }
 
-        // if !supported_tokens.contains(denom.clone()) {
-        //     panic!("There is no such supported token yet");
-        // }
+    pub fn deposit(env: Env, user_address: Address, denom: Symbol, deposited_token_amount: u128) {
+        user_address.require_auth();
 
-        execute_update_liquidity_index_data(env.clone(), denom.clone());
+        Self::deposit_internal(
+            env,
+            user_address.clone(),
+            user_address,
+            denom,
+            deposited_token_amount,
+        );
+    }
 
-        let current_balance = get_deposit(env.clone(), user.clone(), denom.clone());
+    pub fn deposit_for(
+        env: Env,
+        from: Address,
+        on_behalf_of: Address,
+        denom: Symbol,
+        deposited_token_amount: u128,
+    ) {
+        // the tokens are taken from the payer and the mm tokens credited to on_behalf_of
+        from.require_auth();
 
-        if amount > current_balance {
-            panic!("The account doesn't have enough digital tokens redeem");
-        }
+        Self::deposit_internal(env, from, on_behalf_of, denom, deposited_token_amount);
+    }
 
-        if amount == 0 {
-            amount = current_balance;
-        }
+    pub fn redeem(env: Env, user: Address, denom: Symbol, amount: u128) {
+        user.require_auth();
 
-        let remaining: u128 = current_balance - amount;
+        Self::redeem_internal(env, user.clone(), user, denom, amount, true);
+    }
 
-        let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+    pub fn redeem_to(env: Env, user: Address, denom: Symbol, amount: u128, to: Address) {
+        user.require_auth();
 
-        let mm_token_price: u128 = get_mm_token_price(env.clone(), denom.clone());
+        Self::redeem_internal(env, user, to, denom, amount, true);
+    }
 
-        let new_user_mm_token_balance: u128 =
-            Decimal::from_i128_with_scale(remaining as i128, token_decimals)
-                .div(Decimal::from_i128_with_scale(
-                    mm_token_price as i128,
-                    token_decimals,
-                ))
-                .to_u128_with_decimals(token_decimals)
-                .unwrap();
+    pub fn borrow(env: Env, user: Address, denom: Symbol, amount: u128) {
+        user.require_auth();
 
-        let mut user_mm_balance_map: Map<Symbol, u128> = env
-            .storage()
-            .persistent()
-            .get(&DataKey::UserMMTokenBalance(user.clone()))
-            .unwrap_or(Map::new(&env));
-        user_mm_balance_map.set(denom.clone(), new_user_mm_token_balance);
-        env.storage().persistent().set(
-            &DataKey::UserMMTokenBalance(user.clone()),
-            &user_mm_balance_map,
-        );
-        env.storage().persistent().bump(
-            &DataKey::UserMMTokenBalance(user.clone()),
-            MONTH_LIFETIME_THRESHOLD,
-            MONTH_BUMP_AMOUNT,
+        Self::borrow_internal(
+            env,
+            user.clone(),
+            user,
+            denom,
+            amount,
+            InterestRateMode::Variable,
+            true,
         );
-
-        // vault cross-contract call to redeem money
-        let vault_contract_client = vault_contract::Client::new(&env, &get_vault_contract(&env));
-        vault_contract_client.redeem_from_vault_contract(
-            &user,
-            &get_token_address(env.clone(), denom.clone()),
-            &amount,
-        )
     }
 
-    pub fn borrow(env: Env, user: Address, denom: Symbol, amount: u128) {
+    pub fn borrow_to(env: Env, user: Address, denom: Symbol, amount: u128, to: Address) {
         user.require_auth();
 
-        // let liquidator = get_liquidator(&env);
+        Self::borrow_internal(
+            env,
+            user,
+            to,
+            denom,
+            amount,
+            InterestRateMode::Variable,
+            true,
+        );
+    }
 
-        // if user == liquidator {
-        //     panic!("The liquidator cannot borrow");
-        // }
+    pub fn borrow_stable(env: Env, user: Address, denom: Symbol, amount: u128) {
+        user.require_auth();
 
-        // let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+        Self::borrow_internal(
+            env,
+            user.clone(),
+            user,
+            denom,
+            amount,
+            InterestRateMode::Stable,
+            true,
+        );
+    }
 
-        // if !supported_tokens.contains(denom.clone()) {
-        //     panic!("There is no such supported token yet");
-        // }
+    pub fn approve_delegation(
+        env: Env,
+        delegator: Address,
+        delegatee: Address,
+        denom: Symbol,
+        amount: u128,
+    ) {
+        delegator.require_auth();
 
-        let available_to_borrow_amount: u128 =
-            get_available_to_borrow(env.clone(), user.clone(), denom.clone());
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
 
-        if amount > available_to_borrow_amount {
-            panic!("The amount to be borrowed is not available");
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
         }
 
-        //     assert!(
-        //         get_available_liquidity_by_token(env.clone(), denom.clone())
-        //             .unwrap()
-        //             .u128()
-        //             >= amount.u128()
-        //     );
-
-        execute_update_liquidity_index_data(env.clone(), denom.clone());
+        set_borrow_allowance(
+            env.clone(),
+            delegator.clone(),
+            delegatee.clone(),
+            denom.clone(),
+            amount,
+        );
 
-        let user_borrow_amount_with_interest: u128 =
-            get_user_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
+        events::approve_delegation(&env, delegator, delegatee, denom, amount);
+    }
 
-        let user_borrowing_info: UserBorrowingInfo =
-            get_user_borrowing_info(env.clone(), user.clone(), denom.clone());
+    pub fn borrow_on_behalf(
+        env: Env,
+        delegator: Address,
+        delegatee: Address,
+        denom: Symbol,
+        amount: u128,
+    ) {
+        // the delegatee receives the tokens, the debt and the health check stay with the delegator
+        delegatee.require_auth();
 
-        let new_user_borrow_amount: u128 = user_borrow_amount_with_interest + amount;
+        let borrow_allowance: u128 = get_borrow_allowance(
+            env.clone(),
+            delegator.clone(),
+            delegatee.clone(),
+            denom.clone(),
+        );
 
-        let current_interest_rate: u128 = get_interest_rate(env.clone(), denom.clone());
+        if amount > borrow_allowance {
+            panic_with_error!(&env, LendingError::BorrowAllowanceExceeded);
+        }
 
-        let borrowed_token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+        set_borrow_allowance(
+            env.clone(),
+            delegator.clone(),
+            delegatee.clone(),
+            denom.clone(),
+            borrow_allowance - amount,
+        );
 
-        let average_interest_rate: u128 = (Decimal::from_i128_with_scale(
-            user_borrow_amount_with_interest as i128,
-            borrowed_token_decimals,
-        )
-        .mul(Decimal::from_i128_with_scale(
-            user_borrowing_info.average_interest_rate as i128,
-            INTEREST_RATE_DECIMALS,
-        ))
-        .add(
-            Decimal::from_i128_with_scale(amount as i128, borrowed_token_decimals).mul(
-                Decimal::from_i128_with_scale(
-                    current_interest_rate as i128,
-                    INTEREST_RATE_DECIMALS,
-                ),
-            ),
-        ))
-        .div(Decimal::from_i128_with_scale(
-            new_user_borrow_amount as i128,
-            borrowed_token_decimals,
-        ))
-        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-        .unwrap();
-
-        // updating user borrowing info
-        let new_user_borrowing_info: UserBorrowingInfo = UserBorrowingInfo {
-            borrowed_amount: new_user_borrow_amount.clone(),
-            average_interest_rate: average_interest_rate,
-            timestamp: env.ledger().timestamp(),
-        };
+        Self::borrow_internal(
+            env,
+            delegator,
+            delegatee,
+            denom,
+            amount,
+            InterestRateMode::Variable,
+            true,
+        );
+    }
 
-        let total_borrow_data: TotalBorrowData = get_total_borrow_data(env.clone(), denom.clone());
+    pub fn get_borrow_allowance(
+        env: Env,
+        delegator: Address,
+        delegatee: Address,
+        denom: Symbol,
+    ) -> u128 {
+        get_borrow_allowance(env, delegator, delegatee, denom)
+    }
 
-        let expected_annual_interest_income: u128 = total_borrow_data
-            .expected_annual_interest_income
-            - Decimal::from_i128_with_scale(
-                user_borrowing_info.borrowed_amount as i128,
-                borrowed_token_decimals,
-            )
-            .mul(Decimal::from_i128_with_scale(
-                (user_borrowing_info.average_interest_rate / HUNDRED) as i128,
-                INTEREST_RATE_DECIMALS,
-            ))
-            .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-            .unwrap()
-            + Decimal::from_i128_with_scale(
-                new_user_borrow_amount as i128,
-                borrowed_token_decimals,
-            )
-            .mul(Decimal::from_i128_with_scale(
-                (average_interest_rate / HUNDRED) as i128,
-                INTEREST_RATE_DECIMALS,
-            ))
-            .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-            .unwrap();
+    pub fn rebalance_stable_rate(env: Env, user: Address, denom: Symbol) {
+        // any account can move an underpriced stable borrow to the current stable rate
+        check_market_active(env.clone(), denom.clone());
 
-        let total_borrowed_amount: u128 = total_borrow_data.total_borrowed_amount
-            - user_borrowing_info.borrowed_amount
-            + new_user_borrow_amount;
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
 
-        let total_average_interest_rate = HUNDRED
-            * Decimal::from_i128_with_scale(
-                expected_annual_interest_income as i128,
-                INTEREST_RATE_DECIMALS,
-            )
-            .div(Decimal::from_i128_with_scale(
-                total_borrowed_amount as i128,
-                borrowed_token_decimals,
-            ))
-            .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-            .unwrap();
+        let stable_borrow_data: StableBorrowData =
+            get_user_stable_borrow_data(env.clone(), user.clone(), denom.clone());
 
-        let new_total_borrow_data: TotalBorrowData = TotalBorrowData {
-            denom: denom.clone(),
-            total_borrowed_amount: total_borrowed_amount,
-            expected_annual_interest_income: expected_annual_interest_income,
-            average_interest_rate: total_average_interest_rate,
-            timestamp: env.ledger().timestamp(),
-        };
+        if stable_borrow_data.borrowed_amount == 0 {
+            panic_with_error!(&env, LendingError::NoBorrow);
+        }
 
-        let mut user_borrow_map: Map<Symbol, UserBorrowingInfo> = env
+        let token_interest: TokenInterestRateModelParams = env
             .storage()
             .persistent()
-            .get(&DataKey::UserBorrowingInfo(user.clone()))
-            .unwrap_or(Map::new(&env));
-        user_borrow_map.set(denom.clone(), new_user_borrowing_info);
-        env.storage()
-            .persistent()
-            .set(&DataKey::UserBorrowingInfo(user.clone()), &user_borrow_map);
-        env.storage().persistent().bump(
-            &DataKey::UserBorrowingInfo(user.clone()),
-            MONTH_LIFETIME_THRESHOLD,
-            MONTH_BUMP_AMOUNT,
-        );
+            .get::<DataKey, Map<Symbol, TokenInterestRateModelParams>>(
+                &DataKey::TokensInterestRateModelParams,
+            )
+            .unwrap_or(Map::new(&env))
+            .get(denom.clone())
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::UnknownDenom));
+
+        // the position earns less than the depositors are paid, or the market runs above
+        // its optimal utilization while the position pays less than the variable rate
+        let below_liquidity_rate: bool =
+            stable_borrow_data.interest_rate < get_liquidity_rate(env.clone(), denom.clone());
+        let below_variable_rate: bool = get_utilization_rate_by_token(env.clone(), denom.clone())
+            > token_interest.optimal_utilization_ratio
+            && stable_borrow_data.interest_rate < get_interest_rate(env.clone(), denom.clone());
+
+        if !below_liquidity_rate && !below_variable_rate {
+            panic_with_error!(&env, LendingError::RebalanceNotAllowed);
+        }
 
-        let mut total_borrow_map: Map<Symbol, TotalBorrowData> = env
-            .storage()
-            .persistent()
-            .get(&DataKey::TotalBorrowData)
-            .unwrap_or(Map::new(&env));
-        total_borrow_map.set(denom.clone(), new_total_borrow_data);
-        env.storage()
-            .persistent()
-            .set(&DataKey::TotalBorrowData, &total_borrow_map);
-        env.storage().persistent().bump(
-            &DataKey::TotalBorrowData,
-            MONTH_LIFETIME_THRESHOLD,
-            MONTH_BUMP_AMOUNT,
-        );
+        let interest_rate: u128 = get_stable_interest_rate(env.clone(), denom.clone());
 
-        let vault_contract_client = vault_contract::Client::new(&env, &get_vault_contract(&env));
-        vault_contract_client.borrow_from_vault_contract(
-            &user,
-            &get_token_address(env.clone(), denom.clone()),
-            &amount,
-        )
+        execute_rebalance_stable_borrow(env.clone(), user.clone(), denom.clone(), interest_rate);
+
+        events::rebalance_stable_rate(
+            &env,
+            user,
+            denom,
+            stable_borrow_data.interest_rate,
+            interest_rate,
+        );
     }
 
-    pub fn repay(env: Env, user: Address, repay_token: Symbol, mut repay_amount: u128) {
+    pub fn repay(env: Env, user: Address, repay_token: Symbol, repay_amount: u128) {
         user.require_auth();
 
-        let token_address: Address = get_token_address(env.clone(), repay_token.clone());
-        move_token(
-            &env,
-            &token_address,
-            &user,
-            &env.current_contract_address(),
-            repay_amount.clone() as i128,
-        );
+        Self::repay_internal(env, user.clone(), user, repay_token, repay_amount);
+    }
+
+    pub fn repay_for(
+        env: Env,
+        payer: Address,
+        borrower: Address,
+        repay_token: Symbol,
+        repay_amount: u128,
+    ) {
+        // the payer covers the debt of the borrower and gets back the overpaid rest
+        payer.require_auth();
 
-        // let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+        Self::repay_internal(env, payer, borrower, repay_token, repay_amount);
+    }
 
-        // if !supported_tokens.contains(repay_token.clone()) {
-        //     panic!("There is no such supported token yet");
-        // }
+    pub fn liquidation(
+        env: Env,
+        liquidator: Address,
+        user: Address,
+        debt_denom: Symbol,
+        collateral_denom: Symbol,
+        mut repay_amount: u128,
+        receive_mm_token: bool,
+    ) {
+        // any account can liquidate, the debt is repaid with its tokens
+        liquidator.require_auth();
 
-        let user_borrowing_info =
-            get_user_borrowing_info(env.clone(), user.clone(), repay_token.clone());
+        if liquidator == user {
+            panic_with_error!(&env, LendingError::InvalidParameter);
+        }
 
-        execute_update_liquidity_index_data(env.clone(), repay_token.clone());
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(debt_denom.clone())
+            || !supported_tokens.contains(collateral_denom.clone())
+        {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        check_market_active(env.clone(), debt_denom.clone());
+        check_market_active(env.clone(), collateral_denom.clone());
+
+        execute_update_liquidity_index_data(env.clone(), debt_denom.clone());
+        execute_update_liquidity_index_data(env.clone(), collateral_denom.clone());
+
+        let user_utilization_rate = get_user_utilization_rate(env.clone(), user.clone());
+
+        let user_liquidation_threshold: u128 =
+            get_user_liquidation_threshold(env.clone(), user.clone());
+
+        if user_utilization_rate < user_liquidation_threshold {
+            panic_with_error!(&env, LendingError::LiquidationThresholdNotReached);
+        }
+
+        if !user_deposit_as_collateral(env.clone(), user.clone(), collateral_denom.clone()) {
+            panic_with_error!(&env, LendingError::CollateralNotEnabled);
+        }
 
         let user_borrow_amount_with_interest =
-            get_user_borrow_amount_with_interest(env.clone(), user.clone(), repay_token.clone());
+            get_user_borrow_amount_with_interest(env.clone(), user.clone(), debt_denom.clone());
 
-        if repay_amount == 0 {
-            repay_amount = user_borrow_amount_with_interest;
+        if user_borrow_amount_with_interest == 0 {
+            panic_with_error!(&env, LendingError::NoBorrow);
         }
 
-        let mut remaining_amount: u128 = 0u128;
-        let mut average_interest_rate: u128 = user_borrowing_info.average_interest_rate;
-        if repay_amount >= user_borrow_amount_with_interest {
-            remaining_amount = repay_amount - user_borrow_amount_with_interest;
-            repay_amount = user_borrow_amount_with_interest;
-            average_interest_rate = 9_u128;
+        // only a part of the borrow limited by the close factor can be repaid at once
+        let max_repay_amount: u128 =
+            user_borrow_amount_with_interest * get_close_factor(&env) / HUNDRED_PERCENT;
+
+        if repay_amount == 0 || repay_amount > max_repay_amount {
+            repay_amount = max_repay_amount;
         }
 
-        let new_user_borrowing_info: UserBorrowingInfo = UserBorrowingInfo {
-            borrowed_amount: (user_borrow_amount_with_interest - repay_amount),
-            average_interest_rate: average_interest_rate,
-            timestamp: env.ledger().timestamp(),
-        };
+        let liquidation_bonus: u128 =
+            get_reserve_configuration(env.clone(), collateral_denom.clone()).liquidation_bonus;
 
-        let total_borrow_data: TotalBorrowData =
-            get_total_borrow_data(env.clone(), repay_token.clone());
+        let debt_token_decimals: u32 = get_token_decimal(env.clone(), debt_denom.clone());
+        let collateral_token_decimals: u32 =
+            get_token_decimal(env.clone(), collateral_denom.clone());
 
-        let repay_token_decimals: u32 = get_token_decimal(env.clone(), repay_token.clone());
+        // the e-mode price source of the user applies to the seized collateral as well
+        let debt_price: u128 =
+            fetch_user_price_by_token(env.clone(), user.clone(), debt_denom.clone());
+        let collateral_price: u128 =
+            fetch_user_price_by_token(env.clone(), user.clone(), collateral_denom.clone());
 
-        let expected_annual_interest_income = total_borrow_data.expected_annual_interest_income
-            + Decimal::from_i128_with_scale(
-                (user_borrow_amount_with_interest - user_borrowing_info.borrowed_amount) as i128,
-                repay_token_decimals,
-            )
-            .mul(Decimal::from_i128_with_scale(
-                (user_borrowing_info.average_interest_rate / HUNDRED) as i128,
-                INTEREST_RATE_DECIMALS,
-            ))
-            .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-            .unwrap()
-            - Decimal::from_i128_with_scale((repay_amount) as i128, repay_token_decimals)
+        let repay_amount_usd: u128 =
+            Decimal::from_i128_with_scale(repay_amount as i128, debt_token_decimals)
                 .mul(Decimal::from_i128_with_scale(
-                    (user_borrowing_info.average_interest_rate / HUNDRED) as i128,
-                    INTEREST_RATE_DECIMALS,
+                    debt_price as i128,
+                    USD_DECIMALS,
                 ))
-                .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-                .unwrap();
-
-        let total_borrowed_amount: u128 = total_borrow_data.total_borrowed_amount
-            + user_borrow_amount_with_interest
-            - user_borrowing_info.borrowed_amount
-            - repay_amount;
-
-        let mut total_average_interest_rate: u128 = 0u128;
-        if total_borrowed_amount != 0u128 {
-            total_average_interest_rate = HUNDRED
-                * Decimal::from_i128_with_scale(
-                    expected_annual_interest_income as i128,
-                    INTEREST_RATE_DECIMALS,
-                )
+                .to_u128_with_decimals(USD_DECIMALS)
+                .unwrap_or_overflow(&env);
+
+        let seized_collateral_usd: u128 =
+            repay_amount_usd * (HUNDRED_PERCENT + liquidation_bonus) / HUNDRED_PERCENT;
+
+        let mut collateral_amount: u128 =
+            Decimal::from_i128_with_scale(seized_collateral_usd as i128, USD_DECIMALS)
                 .div(Decimal::from_i128_with_scale(
-                    total_borrowed_amount as i128,
-                    repay_token_decimals,
+                    collateral_price as i128,
+                    USD_DECIMALS,
                 ))
-                .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-                .unwrap();
+                .to_u128_with_decimals(collateral_token_decimals)
+                .unwrap_or_overflow(&env);
+
+        let user_collateral_balance: u128 =
+            get_deposit(env.clone(), user.clone(), collateral_denom.clone());
+
+        if collateral_amount > user_collateral_balance {
+            // repaying only the part of the borrow covered by the remaining collateral
+            collateral_amount = user_collateral_balance;
+
+            let user_collateral_balance_usd: u128 = Decimal::from_i128_with_scale(
+                user_collateral_balance as i128,
+                collateral_token_decimals,
+            )
+            .mul(Decimal::from_i128_with_scale(
+                collateral_price as i128,
+                USD_DECIMALS,
+            ))
+            .to_u128_with_decimals(USD_DECIMALS)
+            .unwrap_or_overflow(&env);
+
+            repay_amount = Decimal::from_i128_with_scale(
+                (user_collateral_balance_usd * HUNDRED_PERCENT
+                    / (HUNDRED_PERCENT + liquidation_bonus)) as i128,
+                USD_DECIMALS,
+            )
+            .div(Decimal::from_i128_with_scale(
+                debt_price as i128,
+                USD_DECIMALS,
+            ))
+            .to_u128_with_decimals(debt_token_decimals)
+            .unwrap_or_overflow(&env);
         }
 
-        let new_total_borrow_data = TotalBorrowData {
-            denom: repay_token.clone(),
-            total_borrowed_amount: total_borrowed_amount,
-            expected_annual_interest_income: expected_annual_interest_income,
-            average_interest_rate: total_average_interest_rate,
-            timestamp: env.ledger().timestamp(),
-        };
+        if repay_amount == 0 {
+            panic_with_error!(&env, LendingError::ZeroAmount);
+        }
 
-        let mut user_borrow_map: Map<Symbol, UserBorrowingInfo> = env
-            .storage()
-            .persistent()
-            .get(&DataKey::UserBorrowingInfo(user.clone()))
-            .unwrap_or(Map::new(&env));
-        user_borrow_map.set(repay_token.clone(), new_user_borrowing_info);
-        env.storage()
-            .persistent()
-            .set(&DataKey::UserBorrowingInfo(user.clone()), &user_borrow_map);
-        env.storage().persistent().bump(
-            &DataKey::UserBorrowingInfo(user.clone()),
-            MONTH_LIFETIME_THRESHOLD,
-            MONTH_BUMP_AMOUNT,
-        );
+        if !receive_mm_token
+            && collateral_amount
+                > get_available_liquidity_by_token(env.clone(), collateral_denom.clone())
+        {
+            panic_with_error!(&env, LendingError::InsufficientLiquidity);
+        }
 
-        let mut total_borrow_map: Map<Symbol, TotalBorrowData> = env
-            .storage()
-            .persistent()
-            .get(&DataKey::TotalBorrowData)
-            .unwrap_or(Map::new(&env));
-        total_borrow_map.set(repay_token.clone(), new_total_borrow_data);
-        env.storage()
-            .persistent()
-            .set(&DataKey::TotalBorrowData, &total_borrow_map);
-        env.storage().persistent().bump(
-            &DataKey::TotalBorrowData,
-            MONTH_LIFETIME_THRESHOLD,
-            MONTH_BUMP_AMOUNT,
+        // the borrow is repaid with the liquidator tokens
+        move_token(
+            &env,
+            &get_token_address(env.clone(), debt_denom.clone()),
+            &liquidator,
+            &get_vault_contract(&env),
+            repay_amount as i128,
         );
 
-        if remaining_amount > 0 {
-            // transfer the rest to the user
-            move_token(
-                &env,
-                &token_address,
-                &env.current_contract_address(),
-                &user,
-                remaining_amount as i128,
-            );
+        execute_repay_borrow(env.clone(), user.clone(), debt_denom.clone(), repay_amount);
 
-            // transfer full repay_amount (inc. interest, except remaining_amount) to the vault contract
-            move_token(
-                &env,
-                &token_address,
-                &env.current_contract_address(),
-                &get_vault_contract(&env),
-                (repay_amount.clone() - remaining_amount.clone()) as i128,
+        let user_mm_token_balance: u128 =
+            get_mm_token_balance(env.clone(), user.clone(), collateral_denom.clone());
+        set_deposit(
+            env.clone(),
+            user.clone(),
+            collateral_denom.clone(),
+            user_collateral_balance - collateral_amount,
+        );
+        emit_mm_token_burn(
+            env.clone(),
+            collateral_denom.clone(),
+            user.clone(),
+            user_mm_token_balance
+                .checked_sub(get_mm_token_balance(
+                    env.clone(),
+                    user.clone(),
+                    collateral_denom.clone(),
+                ))
+                .unwrap_or_default(),
+        );
+
+        if receive_mm_token {
+            let liquidator_collateral_balance: u128 =
+                get_deposit(env.clone(), liquidator.clone(), collateral_denom.clone());
+            let liquidator_mm_token_balance: u128 =
+                get_mm_token_balance(env.clone(), liquidator.clone(), collateral_denom.clone());
+
+            set_deposit(
+                env.clone(),
+                liquidator.clone(),
+                collateral_denom.clone(),
+                liquidator_collateral_balance + collateral_amount,
+            );
+            emit_mm_token_mint(
+                env.clone(),
+                collateral_denom.clone(),
+                liquidator.clone(),
+                get_mm_token_balance(env.clone(), liquidator.clone(), collateral_denom.clone())
+                    .checked_sub(liquidator_mm_token_balance)
+                    .unwrap_or_default(),
             );
         } else {
-            // transfer part of the repay_amount to the vault contract
-            move_token(
-                &env,
-                &token_address,
-                &env.current_contract_address(),
-                &get_vault_contract(&env),
-                repay_amount.clone() as i128,
+            let vault_contract_client =
+                vault_contract::Client::new(&env, &get_vault_contract(&env));
+            vault_contract_client.redeem_from_vault_contract(
+                &liquidator,
+                &get_token_address(env.clone(), collateral_denom.clone()),
+                &collateral_amount,
             );
         }
+
+        events::liquidation(
+            &env,
+            user.clone(),
+            debt_denom.clone(),
+            liquidator,
+            collateral_denom,
+            collateral_amount,
+            repay_amount,
+        );
+
+        // the liquidated debt left without any deposit is written off as bad debt,
+        // deposits not used as collateral still have to be redeemed to repay it.
+        // The borrows of the other denoms are written off with write_off_bad_debt
+        // so that their prices don't have to be fetched here
+        if !user_has_deposits(env.clone(), user.clone()) {
+            let (bad_debt, covered_by_reserves, socialized) =
+                execute_write_off_bad_debt(env.clone(), user.clone(), debt_denom.clone());
+
+            if bad_debt != 0 {
+                events::bad_debt(
+                    &env,
+                    user,
+                    debt_denom,
+                    bad_debt,
+                    covered_by_reserves,
+                    socialized,
+                );
+            }
+        }
     }
 
-    pub fn liquidation(env: Env, user: Address) {
-        // liquidator only
-        let liquidator: Address = get_liquidator(&env);
-        liquidator.require_auth();
+    pub fn write_off_bad_debt(env: Env, user: Address, denom: Symbol) {
+        // any account can write off the remaining borrows of a user without any deposit,
+        // the outstanding part is then covered with settle_bad_debt
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
 
-        let user_utilization_rate = get_user_utilization_rate(env.clone(), user.clone());
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
 
-        let user_liquidation_threshold: u128 =
-            get_user_liquidation_threshold(env.clone(), user.clone());
+        if user_has_deposits(env.clone(), user.clone()) {
+            panic_with_error!(&env, LendingError::RemainingDeposits);
+        }
+
+        let (bad_debt, covered_by_reserves, socialized) =
+            execute_write_off_bad_debt(env.clone(), user.clone(), denom.clone());
+
+        if bad_debt == 0 {
+            panic_with_error!(&env, LendingError::NoBorrow);
+        }
 
-        assert!(
-            user_utilization_rate >= user_liquidation_threshold,
-            "User borrowing has not reached the threshold of liquidation"
+        events::bad_debt(
+            &env,
+            user,
+            denom,
+            bad_debt,
+            covered_by_reserves,
+            socialized,
         );
+    }
 
-        for token in get_supported_tokens(env.clone()) {
-            execute_update_liquidity_index_data(env.clone(), token.clone());
-
-            let use_user_deposit_as_collateral =
-                user_deposit_as_collateral(env.clone(), user.clone(), token.clone());
-
-            let mut user_token_balance = 0u128;
-            if use_user_deposit_as_collateral {
-                user_token_balance = get_deposit(env.clone(), user.clone(), token.clone());
-
-                let mut user_mm_balance_map: Map<Symbol, u128> = env
-                    .storage()
-                    .persistent()
-                    .get(&DataKey::UserMMTokenBalance(user.clone()))
-                    .unwrap_or(Map::new(&env));
-                user_mm_balance_map.set(token.clone(), 0_u128);
-                env.storage().persistent().set(
-                    &DataKey::UserMMTokenBalance(user.clone()),
-                    &user_mm_balance_map,
-                );
-                env.storage().persistent().bump(
-                    &DataKey::UserMMTokenBalance(user.clone()),
-                    MONTH_LIFETIME_THRESHOLD,
-                    MONTH_BUMP_AMOUNT,
-                );
-            }
+    pub fn flash_loan(
+        env: Env,
+        initiator: Address,
+        receiver: Address,
+        denom: Symbol,
+        amount: u128,
+        params: Bytes,
+    ) {
+        // the receiver is told who started the loan, so it can reject loans it didn't start
+        initiator.require_auth();
 
-            let user_borrow_amount_with_interest =
-                get_user_borrow_amount_with_interest(env.clone(), user.clone(), token.clone());
-
-            if user_borrow_amount_with_interest > 0 || user_token_balance > 0 {
-                let liquidator_balance =
-                    get_deposit(env.clone(), liquidator.clone(), token.clone());
-
-                let token_decimals = get_token_decimal(env.clone(), token.clone());
-
-                if user_borrow_amount_with_interest > 0 {
-                    assert!(
-                        liquidator_balance >= user_borrow_amount_with_interest,
-                        "The liquidator does not have enough deposit balance for liquidation"
-                    );
-
-                    let user_borrowing_info =
-                        get_user_borrowing_info(env.clone(), user.clone(), token.clone());
-
-                    let new_user_borrowing_info = UserBorrowingInfo {
-                        borrowed_amount: 0_u128,
-                        average_interest_rate: 0_u128,
-                        timestamp: env.ledger().timestamp(),
-                    };
-
-                    let total_borrow_data = get_total_borrow_data(env.clone(), token.clone());
-
-                    let expected_annual_interest_income = total_borrow_data
-                        .expected_annual_interest_income
-                        - Decimal::from_i128_with_scale(
-                            (user_borrowing_info.borrowed_amount) as i128,
-                            token_decimals,
-                        )
-                        .mul(Decimal::from_i128_with_scale(
-                            (user_borrowing_info.average_interest_rate / HUNDRED) as i128,
-                            INTEREST_RATE_DECIMALS,
-                        ))
-                        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-                        .unwrap();
-
-                    let total_borrowed_amount = total_borrow_data.total_borrowed_amount
-                        - user_borrowing_info.borrowed_amount;
-
-                    let mut total_average_interest_rate = 0u128;
-                    if total_borrowed_amount != 0u128 {
-                        total_average_interest_rate = HUNDRED
-                            * Decimal::from_i128_with_scale(
-                                expected_annual_interest_income as i128,
-                                INTEREST_RATE_DECIMALS,
-                            )
-                            .div(Decimal::from_i128_with_scale(
-                                total_borrowed_amount as i128,
-                                token_decimals,
-                            ))
-                            .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-                            .unwrap();
-                    }
-
-                    let new_total_borrow_data = TotalBorrowData {
-                        denom: token.clone(),
-                        total_borrowed_amount: total_borrowed_amount,
-                        expected_annual_interest_income: expected_annual_interest_income,
-                        average_interest_rate: total_average_interest_rate,
-                        timestamp: env.ledger().timestamp(),
-                    };
-
-                    let mut user_borrow_map: Map<Symbol, UserBorrowingInfo> = env
-                        .storage()
-                        .persistent()
-                        .get(&DataKey::UserBorrowingInfo(user.clone()))
-                        .unwrap_or(Map::new(&env));
-                    user_borrow_map.set(token.clone(), new_user_borrowing_info);
-                    env.storage()
-                        .persistent()
-                        .set(&DataKey::UserBorrowingInfo(user.clone()), &user_borrow_map);
-                    env.storage().persistent().bump(
-                        &DataKey::UserBorrowingInfo(user.clone()),
-                        MONTH_LIFETIME_THRESHOLD,
-                        MONTH_BUMP_AMOUNT,
-                    );
-                    let mut total_borrow_map: Map<Symbol, TotalBorrowData> = env
-                        .storage()
-                        .persistent()
-                        .get(&DataKey::TotalBorrowData)
-                        .unwrap_or(Map::new(&env));
-                    total_borrow_map.set(token.clone(), new_total_borrow_data);
-                    env.storage()
-                        .persistent()
-                        .set(&DataKey::TotalBorrowData, &total_borrow_map);
-                    env.storage().persistent().bump(
-                        &DataKey::TotalBorrowData,
-                        MONTH_LIFETIME_THRESHOLD,
-                        MONTH_BUMP_AMOUNT,
-                    );
-                }
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
 
-                let new_liquidator_token_balance: u128 =
-                    liquidator_balance + user_token_balance - user_borrow_amount_with_interest;
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
 
-                let mm_token_price = get_mm_token_price(env.clone(), token.clone());
+        check_market_active(env.clone(), denom.clone());
 
-                let new_liquidator_mm_token_balance = Decimal::from_i128_with_scale(
-                    new_liquidator_token_balance as i128,
-                    token_decimals,
-                )
-                .div(Decimal::from_i128_with_scale(
-                    mm_token_price as i128,
-                    token_decimals,
-                ))
-                .to_u128_with_decimals(token_decimals)
-                .unwrap();
-
-                let mut liquidator_mm_balance_map: Map<Symbol, u128> = env
-                    .storage()
-                    .persistent()
-                    .get(&DataKey::UserMMTokenBalance(liquidator.clone()))
-                    .unwrap_or(Map::new(&env));
-                liquidator_mm_balance_map.set(token.clone(), new_liquidator_mm_token_balance);
-                env.storage().persistent().set(
-                    &DataKey::UserMMTokenBalance(liquidator.clone()),
-                    &liquidator_mm_balance_map,
-                );
-                env.storage().persistent().bump(
-                    &DataKey::UserMMTokenBalance(liquidator.clone()),
-                    MONTH_LIFETIME_THRESHOLD,
-                    MONTH_BUMP_AMOUNT,
-                );
-            }
+        if amount == 0 {
+            panic_with_error!(&env, LendingError::ZeroAmount);
+        }
+
+        if amount > get_available_liquidity_by_token(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::InsufficientLiquidity);
+        }
+
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+        // the fee goes to the depositors only
+        let total_reserves: u128 = get_total_reserves_by_token(env.clone(), denom.clone())
+            - get_protocol_reserves(env.clone(), denom.clone());
+
+        // rounded up, so that small loans can't dodge the fee
+        let fee: u128 = (amount * get_flash_loan_fee(&env) + HUNDRED_PERCENT - 1) / HUNDRED_PERCENT;
+
+        let token_address: Address = get_token_address(env.clone(), denom.clone());
+        let vault_contract_address: Address = get_vault_contract(&env);
+
+        let vault_contract_client = vault_contract::Client::new(&env, &vault_contract_address);
+        vault_contract_client.borrow_from_vault_contract(&receiver, &token_address, &amount);
+
+        let receiver_client = FlashLoanReceiverClient::new(&env, &receiver);
+        receiver_client.execute_operation(&initiator, &token_address, &amount, &fee, &params);
+
+        // pulling the principal plus the fee back into the vault within the same invocation
+        let token_client = token::Client::new(&env, &token_address);
+        let repayment = token_client.try_transfer_from(
+            &env.current_contract_address(),
+            &receiver,
+            &vault_contract_address,
+            &((amount + fee) as i128),
+        );
+        if repayment.is_err() {
+            panic_with_error!(&env, LendingError::FlashLoanNotRepaid);
+        }
+
+        if fee > 0 {
+            execute_distribute_income(env.clone(), denom.clone(), fee, total_reserves);
+        }
+
+        events::flash_loan(&env, receiver, denom, initiator, amount, fee);
+    }
+
+    pub fn settle_bad_debt(env: Env, payer: Address, denom: Symbol, amount: u128) {
+        // any account can cover the bad debt that was left outstanding after the write-off
+        payer.require_auth();
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        let bad_debt_data: BadDebtData = get_bad_debt(env.clone(), denom.clone());
+
+        let mut settled_amount: u128 = amount;
+        if settled_amount == 0 || settled_amount > bad_debt_data.outstanding_bad_debt {
+            settled_amount = bad_debt_data.outstanding_bad_debt;
+        }
+
+        if settled_amount == 0 {
+            panic_with_error!(&env, LendingError::ZeroAmount);
         }
+
+        // the vault gets back the tokens missing from the depositors reserves
+        move_token(
+            &env,
+            &get_token_address(env.clone(), denom.clone()),
+            &payer,
+            &get_vault_contract(&env),
+            settled_amount as i128,
+        );
+
+        set_bad_debt(
+            env.clone(),
+            denom.clone(),
+            BadDebtData {
+                outstanding_bad_debt: bad_debt_data.outstanding_bad_debt - settled_amount,
+                ..bad_debt_data
+            },
+        );
+
+        events::settle_bad_debt(&env, payer, denom, settled_amount);
     }
 
     pub fn add_markets(
         env: Env,
+        caller: Address,
         denom: Symbol,
         address: Address,
         name: Symbol,
-        decimals: u32,
         loan_to_value_ratio: u128,
         liquidation_threshold: u128,
         min_interest_rate: u128,
@@ -659,15 +677,22 @@ impl LendingContract {
         rate_growth_factor: u128,
         optimal_utilization_ratio: u128,
     ) {
-        // Admin only
-        let admin: Address = get_admin(&env);
-        admin.require_auth();
+        // Market listing role
+        require_role(&env, &caller, Role::MarketListing);
 
         let mut supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
         if supported_tokens.contains(denom.clone()) {
-            panic!("There already exists such a supported token");
+            panic_with_error!(&env, LendingError::MarketAlreadyExists);
+        }
+
+        // the users collateral flags and the market settings of a removed denom are kept
+        if is_market_removed(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::MarketRemoved);
         }
 
+        // the decimals are read from the token contract
+        let decimals: u32 = token::Client::new(&env, &address).decimals();
+
         supported_tokens.push_back(denom.clone());
         env.storage()
             .persistent()
@@ -680,7 +705,7 @@ impl LendingContract {
 
         let token_info: TokenInfo = TokenInfo {
             denom: denom.clone(),
-            address,
+            address: address.clone(),
             name,
             symbol: denom.clone(),
             decimals,
@@ -705,6 +730,9 @@ impl LendingContract {
             denom: denom.clone(),
             loan_to_value_ratio,
             liquidation_threshold,
+            liquidation_bonus: DEFAULT_LIQUIDATION_BONUS,
+            supply_cap: 0_u128,
+            borrow_cap: 0_u128,
         };
         let mut reserve_map: Map<Symbol, ReserveConfiguration> = env
             .storage()
@@ -747,9 +775,8 @@ impl LendingContract {
 
         let total_borrow_data: TotalBorrowData = TotalBorrowData {
             denom: denom.clone(),
-            total_borrowed_amount: 0_u128,
-            expected_annual_interest_income: 0_u128,
-            average_interest_rate: 0_u128,
+            total_scaled_borrowed_amount: 0_u128,
+            borrow_index_ln: 0_u128,
             timestamp: env.ledger().timestamp(),
         };
 
@@ -788,204 +815,712 @@ impl LendingContract {
             MONTH_LIFETIME_THRESHOLD,
             MONTH_BUMP_AMOUNT,
         );
-    }
-
-    pub fn update_price(env: Env, denom: Symbol, price: u128) {
-        // Admin only
-        let admin: Address = get_admin(&env);
-        admin.require_auth();
-
-        let mut prices: Map<Symbol, u128> = env
-            .storage()
-            .persistent()
-            .get(&DataKey::Prices)
-            .unwrap_or(Map::new(&env));
-        prices.set(denom.clone(), price);
-        env.storage().persistent().set(&DataKey::Prices, &prices);
-        env.storage().persistent().bump(
-            &DataKey::Prices,
-            MONTH_LIFETIME_THRESHOLD,
-            MONTH_BUMP_AMOUNT,
-        );
-    }
-
-    pub fn toggle_collateral_setting(env: Env, user: Address, denom: Symbol) {
-        user.require_auth();
-
-        let use_user_deposit_as_collateral =
-            user_deposit_as_collateral(env.clone(), user.clone(), denom.clone());
 
-        if use_user_deposit_as_collateral {
-            let user_token_balance: u128 = get_deposit(env.clone(), user.clone(), denom.clone());
+        // deploying the transferable mm token of the market, a relisted market keeps its mm token
+        if let (Some(mm_token_wasm_hash), None) = (
+            get_mm_token_wasm_hash(&env),
+            get_mm_token(env.clone(), denom.clone()),
+        ) {
+            let salt: BytesN<32> = env.crypto().sha256(&denom.clone().to_xdr(&env)).into();
+            let mm_token_address: Address = env
+                .deployer()
+                .with_current_contract(salt)
+                .deploy(mm_token_wasm_hash);
+
+            MMTokenClient::new(&env, &mm_token_address).initialize(
+                &env.current_contract_address(),
+                &denom,
+                &address,
+                &decimals,
+            );
 
-            if user_token_balance != 0 {
-                let token_decimals = get_token_decimal(env.clone(), denom.clone());
+            set_mm_token(env.clone(), denom.clone(), mm_token_address);
+        }
 
-                let price = fetch_price_by_token(env.clone(), denom.clone());
+        events::add_markets(&env, denom, address, decimals);
+    }
 
-                let user_token_balance_usd =
-                    Decimal::from_i128_with_scale(user_token_balance as i128, token_decimals)
-                        .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
-                        .to_u128_with_decimals(USD_DECIMALS)
-                        .unwrap();
+    pub fn deprecate_market(env: Env, caller: Address, denom: Symbol) {
+        // Market listing role
+        require_role(&env, &caller, Role::MarketListing);
 
-                let sum_collateral_balance_usd = get_user_collateral_usd(env.clone(), user.clone());
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
 
-                let sum_borrow_balance_usd = get_user_borrowed_usd(env.clone(), user.clone());
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
 
-                let user_liquidation_threshold =
-                    get_user_liquidation_threshold(env.clone(), user.clone());
+        set_market_deprecated(env.clone(), denom.clone(), true);
 
-                assert!(
-                    sum_borrow_balance_usd * HUNDRED_PERCENT / user_liquidation_threshold < sum_collateral_balance_usd - user_token_balance_usd,
-                    "The collateral has already using to collateralise the borrowing. Not enough available balance"
-                );
-            }
-        }
+        // the deposits stop backing new borrows, existing positions keep the liquidation threshold
+        let mut reserve_configuration: ReserveConfiguration =
+            get_reserve_configuration(env.clone(), denom.clone());
+        reserve_configuration.loan_to_value_ratio = 0;
 
-        let mut user_deposit_as_collateral_map: Map<Symbol, bool> = env
+        let mut reserve_map: Map<Symbol, ReserveConfiguration> = env
             .storage()
             .persistent()
-            .get(&DataKey::UserDepositAsCollateral(user.clone()))
+            .get(&DataKey::ReserveConfiguration)
             .unwrap_or(Map::new(&env));
-        user_deposit_as_collateral_map.set(denom.clone(), !use_user_deposit_as_collateral);
-        env.storage().persistent().set(
-            &DataKey::UserDepositAsCollateral(user.clone()),
-            &user_deposit_as_collateral_map,
-        );
+        reserve_map.set(denom.clone(), reserve_configuration);
+        env.storage()
+            .persistent()
+            .set(&DataKey::ReserveConfiguration, &reserve_map);
         env.storage().persistent().bump(
-            &DataKey::UserDepositAsCollateral(user.clone()),
+            &DataKey::ReserveConfiguration,
             MONTH_LIFETIME_THRESHOLD,
             MONTH_BUMP_AMOUNT,
         );
-    }
-
-    pub fn get_deposit(env: Env, user: Address, denom: Symbol) -> u128 {
-        get_deposit(env, user, denom)
-    }
-
-    pub fn get_total_borrow_data(env: Env, denom: Symbol) -> TotalBorrowData {
-        get_total_borrow_data(env, denom)
-    }
 
-    pub fn get_total_reserves_by_token(env: Env, denom: Symbol) -> u128 {
-        get_total_reserves_by_token(env, denom)
-    }
-
-    pub fn get_user_deposited_usd(env: Env, user: Address) -> u128 {
-        get_user_deposited_usd(env, user)
-    }
-
-    pub fn get_mm_token_price(env: Env, denom: Symbol) -> u128 {
-        get_mm_token_price(env, denom)
+        events::deprecate_market(&env, denom);
     }
 
-    pub fn get_price(env: Env, denom: Symbol) -> u128 {
-        fetch_price_by_token(env, denom)
+    pub fn is_market_deprecated(env: Env, denom: Symbol) -> bool {
+        is_market_deprecated(env, denom)
     }
 
-    pub fn get_liquidity_rate(env: Env, denom: Symbol) -> u128 {
-        get_liquidity_rate(env, denom)
-    }
+    pub fn remove_market(env: Env, caller: Address, denom: Symbol) {
+        // Market listing role
+        require_role(&env, &caller, Role::MarketListing);
 
-    pub fn get_user_borrow_with_interest(env: Env, user: Address, denom: Symbol) -> u128 {
-        get_user_borrow_amount_with_interest(env, user, denom)
-    }
+        let mut supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
 
-    pub fn get_user_max_allowed_borrow_usd(env: Env, user: Address) -> u128 {
-        get_user_max_allowed_borrow_amount_usd(env, user)
-    }
+        let denom_index: u32 = supported_tokens
+            .first_index_of(denom.clone())
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::UnknownDenom));
 
-    pub fn get_user_borrowed_usd(env: Env, user: Address) -> u128 {
-        get_user_borrowed_usd(env, user)
-    }
+        if !is_market_deprecated(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::MarketNotDeprecated);
+        }
 
-    pub fn get_user_liquidation_threshold(env: Env, user: Address) -> u128 {
-        get_user_liquidation_threshold(env, user)
-    }
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
 
-    pub fn get_user_utilization_rate(env: Env, user: Address) -> u128 {
-        get_user_utilization_rate(env, user)
-    }
-    pub fn get_available_to_borrow(env: Env, user: Address, denom: Symbol) -> u128 {
-        get_available_to_borrow(env, user, denom)
-    }
+        if get_total_mm_token_supply(env.clone(), denom.clone()) != 0
+            || get_total_borrowed_by_token(env.clone(), denom.clone()) != 0
+            || get_protocol_reserves(env.clone(), denom.clone()) != 0
+        {
+            panic_with_error!(&env, LendingError::MarketNotEmpty);
+        }
 
-    pub fn get_available_to_redeem(env: Env, user: Address, denom: Symbol) -> u128 {
-        get_available_to_redeem(env, user, denom)
-    }
+        supported_tokens.remove(denom_index);
+        env.storage()
+            .persistent()
+            .set(&DataKey::SupportedTokensList, &supported_tokens);
+        env.storage().persistent().bump(
+            &DataKey::SupportedTokensList,
+            MONTH_LIFETIME_THRESHOLD,
+            MONTH_BUMP_AMOUNT,
+        );
 
-    pub fn get_user_collateral_usd(env: Env, user: Address) -> u128 {
-        get_user_collateral_usd(env, user)
-    }
+        set_market_deprecated(env.clone(), denom.clone(), false);
+        set_market_removed(env.clone(), denom.clone());
 
-    pub fn get_reserve_configuration(env: Env, denom: Symbol) -> ReserveConfiguration {
-        get_reserve_configuration(env, denom)
+        events::remove_market(&env, denom);
     }
 
-    pub fn set_reserve_configuration(
+    pub fn update_token_info(
         env: Env,
+        caller: Address,
         denom: Symbol,
-        loan_to_value_ratio: u128,
-        liquidation_threshold: u128,
+        name: Symbol,
+        symbol: Symbol,
     ) {
-        let admin: Address = get_admin(&env);
-        admin.require_auth();
+        // Market listing role
+        require_role(&env, &caller, Role::MarketListing);
 
         let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
 
         if !supported_tokens.contains(denom.clone()) {
-            panic!("There is no such supported token yet");
+            panic_with_error!(&env, LendingError::UnknownDenom);
         }
 
-        let mut reserve_map: Map<Symbol, ReserveConfiguration> = env
+        // the address and decimals back existing balances and cannot be changed
+        let mut token_info: TokenInfo = get_token_info(env.clone(), denom.clone());
+        token_info.name = name.clone();
+        token_info.symbol = symbol.clone();
+
+        let mut supported_tokens_info: Map<Symbol, TokenInfo> = env
             .storage()
             .persistent()
-            .get(&DataKey::ReserveConfiguration)
+            .get(&DataKey::SupportedTokensInfo)
             .unwrap_or(Map::new(&env));
-        reserve_map.set(
-            denom.clone(),
-            ReserveConfiguration {
-                denom: denom.clone(),
-                loan_to_value_ratio,
-                liquidation_threshold,
-            },
-        );
+        supported_tokens_info.set(denom.clone(), token_info);
         env.storage()
             .persistent()
-            .set(&DataKey::ReserveConfiguration, &reserve_map);
+            .set(&DataKey::SupportedTokensInfo, &supported_tokens_info);
         env.storage().persistent().bump(
-            &DataKey::ReserveConfiguration,
+            &DataKey::SupportedTokensInfo,
             MONTH_LIFETIME_THRESHOLD,
             MONTH_BUMP_AMOUNT,
         );
-    }
 
-    pub fn set_vault_contract(env: Env, vault_contract: Address) {
-        set_vault_contract(&env, &vault_contract)
+        events::update_token_info(&env, denom, name, symbol);
     }
 
-    pub fn get_vault_contract(env: Env) -> Address {
+    pub fn update_price(env: Env, caller: Address, denom: Symbol, price: u128) {
+        // Price updater role
+        require_role(&env, &caller, Role::PriceUpdater);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        if price == 0 {
+            panic_with_error!(&env, LendingError::InvalidParameter);
+        }
+
+        let mut prices: Map<Symbol, TokenPrice> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::Prices)
+            .unwrap_or(Map::new(&env));
+        prices.set(
+            denom.clone(),
+            TokenPrice {
+                price,
+                timestamp: env.ledger().timestamp(),
+            },
+        );
+        env.storage().persistent().set(&DataKey::Prices, &prices);
+        env.storage().persistent().bump(
+            &DataKey::Prices,
+            MONTH_LIFETIME_THRESHOLD,
+            MONTH_BUMP_AMOUNT,
+        );
+
+        events::update_price(&env, denom, price);
+    }
+
+    pub fn set_price_oracle(env: Env, caller: Address, denom: Symbol, oracle: Address) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        set_price_oracle(env.clone(), denom.clone(), oracle.clone());
+
+        events::set_price_oracle(&env, denom, oracle);
+    }
+
+    pub fn get_price_oracle(env: Env, denom: Symbol) -> Option<Address> {
+        get_price_oracle(env, denom)
+    }
+
+    pub fn set_max_price_age(env: Env, caller: Address, denom: Symbol, max_price_age: u64) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        set_max_price_age(env.clone(), denom.clone(), max_price_age);
+
+        events::set_max_price_age(&env, denom, max_price_age);
+    }
+
+    pub fn get_max_price_age(env: Env, denom: Symbol) -> u64 {
+        get_max_price_age(env, denom)
+    }
+
+    pub fn toggle_collateral_setting(env: Env, user: Address, denom: Symbol) {
+        user.require_auth();
+
+        let use_user_deposit_as_collateral =
+            user_deposit_as_collateral(env.clone(), user.clone(), denom.clone());
+
+        Self::set_collateral_internal(env, user, denom, !use_user_deposit_as_collateral, true);
+    }
+
+    pub fn execute_actions(env: Env, user: Address, actions: Vec<Action>) {
+        // executes the actions in order with a single health check at the end
+        user.require_auth();
+
+        let mut check_health: bool = false;
+
+        for action in actions.iter() {
+            match action {
+                Action::Deposit(denom, amount) => {
+                    Self::deposit_internal(env.clone(), user.clone(), user.clone(), denom, amount)
+                }
+                Action::Redeem(denom, amount) => {
+                    check_health = true;
+                    Self::redeem_internal(
+                        env.clone(),
+                        user.clone(),
+                        user.clone(),
+                        denom,
+                        amount,
+                        false,
+                    )
+                }
+                Action::Borrow(denom, amount) => {
+                    check_health = true;
+                    Self::borrow_internal(
+                        env.clone(),
+                        user.clone(),
+                        user.clone(),
+                        denom,
+                        amount,
+                        InterestRateMode::Variable,
+                        false,
+                    )
+                }
+                Action::Repay(denom, amount) => {
+                    Self::repay_internal(env.clone(), user.clone(), user.clone(), denom, amount)
+                }
+                Action::EnableCollateral(denom) => {
+                    Self::set_collateral_internal(env.clone(), user.clone(), denom, true, false)
+                }
+                Action::DisableCollateral(denom) => {
+                    check_health = true;
+                    Self::set_collateral_internal(env.clone(), user.clone(), denom, false, false)
+                }
+            }
+        }
+
+        // the borrows must stay within the LTV of the collateral left
+        if check_health
+            && get_user_borrowed_usd(env.clone(), user.clone())
+                > get_user_max_allowed_borrow_amount_usd(env.clone(), user.clone())
+        {
+            panic_with_error!(&env, LendingError::BorrowAmountNotAvailable);
+        }
+    }
+
+    pub fn get_admin(env: Env) -> Address {
+        get_admin(&env)
+    }
+
+    pub fn propose_admin(env: Env, new_admin: Address) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        set_pending_admin(&env, &new_admin);
+
+        events::propose_admin(&env, admin, new_admin);
+    }
+
+    pub fn accept_admin(env: Env) {
+        // Pending admin only
+        let pending_admin: Address = get_pending_admin(&env)
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::NoPendingTransfer));
+        pending_admin.require_auth();
+
+        let previous_admin: Address = get_admin(&env);
+
+        set_admin(&env, &pending_admin);
+        remove_pending_admin(&env);
+
+        events::accept_admin(&env, previous_admin, pending_admin);
+    }
+
+    pub fn cancel_admin_transfer(env: Env) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        let pending_admin: Address = get_pending_admin(&env)
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::NoPendingTransfer));
+
+        remove_pending_admin(&env);
+
+        events::cancel_admin_transfer(&env, pending_admin);
+    }
+
+    pub fn get_pending_admin(env: Env) -> Option<Address> {
+        get_pending_admin(&env)
+    }
+
+    pub fn get_liquidator(env: Env) -> Address {
+        get_liquidator(&env)
+    }
+
+    pub fn propose_liquidator(env: Env, new_liquidator: Address) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        set_pending_liquidator(&env, &new_liquidator);
+
+        events::propose_liquidator(&env, get_liquidator(&env), new_liquidator);
+    }
+
+    pub fn accept_liquidator(env: Env) {
+        // Pending liquidator only
+        let pending_liquidator: Address = get_pending_liquidator(&env)
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::NoPendingTransfer));
+        pending_liquidator.require_auth();
+
+        let previous_liquidator: Address = get_liquidator(&env);
+
+        set_liquidator(&env, &pending_liquidator);
+        remove_pending_liquidator(&env);
+
+        events::accept_liquidator(&env, previous_liquidator, pending_liquidator);
+    }
+
+    pub fn cancel_liquidator_transfer(env: Env) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        let pending_liquidator: Address = get_pending_liquidator(&env)
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::NoPendingTransfer));
+
+        remove_pending_liquidator(&env);
+
+        events::cancel_liquidator_transfer(&env, pending_liquidator);
+    }
+
+    pub fn get_pending_liquidator(env: Env) -> Option<Address> {
+        get_pending_liquidator(&env)
+    }
+
+    pub fn grant_role(env: Env, role: Role, account: Address) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        set_role(&env, role, &account);
+
+        events::grant_role(&env, role, account);
+    }
+
+    pub fn revoke_role(env: Env, role: Role) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        let account: Address = get_role(&env, role)
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::RoleNotGranted));
+
+        remove_role(&env, role);
+
+        events::revoke_role(&env, role, account);
+    }
+
+    pub fn get_role(env: Env, role: Role) -> Option<Address> {
+        get_role(&env, role)
+    }
+
+    pub fn set_paused(env: Env, caller: Address, paused: bool) {
+        // Admin or pause guardian only
+        require_role(&env, &caller, Role::PauseGuardian);
+
+        set_paused(&env, paused);
+
+        events::set_paused(&env, caller, paused);
+    }
+
+    pub fn is_paused(env: Env) -> bool {
+        is_paused(&env)
+    }
+
+    pub fn set_market_flags(
+        env: Env,
+        caller: Address,
+        denom: Symbol,
+        deposits_frozen: bool,
+        borrows_frozen: bool,
+        paused: bool,
+    ) {
+        // Admin or pause guardian only
+        require_role(&env, &caller, Role::PauseGuardian);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        set_market_flags(
+            env.clone(),
+            denom.clone(),
+            MarketFlags {
+                deposits_frozen,
+                borrows_frozen,
+                paused,
+            },
+        );
+
+        events::set_market_flags(&env, denom, deposits_frozen, borrows_frozen, paused);
+    }
+
+    pub fn get_market_flags(env: Env, denom: Symbol) -> MarketFlags {
+        get_market_flags(env, denom)
+    }
+
+    pub fn set_mm_token_wasm_hash(env: Env, wasm_hash: BytesN<32>) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        set_mm_token_wasm_hash(&env, &wasm_hash);
+
+        events::set_mm_token_wasm_hash(&env, wasm_hash);
+    }
+
+    pub fn get_mm_token(env: Env, denom: Symbol) -> Option<Address> {
+        get_mm_token(env, denom)
+    }
+
+    pub fn get_mm_token_balance(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_mm_token_balance(env, user, denom)
+    }
+
+    pub fn get_bad_debt(env: Env, denom: Symbol) -> BadDebtData {
+        get_bad_debt(env, denom)
+    }
+
+    pub fn get_total_mm_token_supply(env: Env, denom: Symbol) -> u128 {
+        get_total_mm_token_supply(env, denom)
+    }
+
+    pub fn transfer_mm_token(env: Env, denom: Symbol, from: Address, to: Address, amount: u128) {
+        // mm token contract of the market only
+        let mm_token: Address = get_mm_token(env.clone(), denom.clone())
+            .unwrap_or_else(|| panic_with_error!(&env, LendingError::MMTokenNotFound));
+        mm_token.require_auth();
+
+        let from_mm_token_balance: u128 =
+            get_mm_token_balance(env.clone(), from.clone(), denom.clone());
+
+        if amount > from_mm_token_balance {
+            panic_with_error!(&env, LendingError::InsufficientDeposit);
+        }
+
+        if from == to || amount == 0 {
+            return;
+        }
+
+        set_mm_token_balance(
+            env.clone(),
+            from.clone(),
+            denom.clone(),
+            from_mm_token_balance - amount,
+        );
+
+        let to_mm_token_balance: u128 =
+            get_mm_token_balance(env.clone(), to.clone(), denom.clone());
+        set_mm_token_balance(
+            env.clone(),
+            to.clone(),
+            denom.clone(),
+            to_mm_token_balance + amount,
+        );
+
+        // the same LTV check as a redemption, so that the collateral can't leave through a transfer
+        if user_deposit_as_collateral(env.clone(), from.clone(), denom.clone())
+            && get_user_borrowed_usd(env.clone(), from.clone())
+                > get_user_max_allowed_borrow_amount_usd(env.clone(), from.clone())
+        {
+            panic_with_error!(&env, LendingError::CollateralInUse);
+        }
+    }
+
+    pub fn get_deposit(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_deposit(env, user, denom)
+    }
+
+    pub fn get_total_borrow_data(env: Env, denom: Symbol) -> TotalBorrowData {
+        get_total_borrow_data(env, denom)
+    }
+
+    pub fn get_total_reserves_by_token(env: Env, denom: Symbol) -> u128 {
+        get_total_reserves_by_token(env, denom)
+    }
+
+    pub fn get_user_deposited_usd(env: Env, user: Address) -> u128 {
+        get_user_deposited_usd(env, user)
+    }
+
+    pub fn get_mm_token_price(env: Env, denom: Symbol) -> u128 {
+        get_mm_token_price(env, denom)
+    }
+
+    pub fn get_price(env: Env, denom: Symbol) -> u128 {
+        fetch_price_by_token(env, denom)
+    }
+
+    pub fn get_supported_tokens(env: Env) -> Vec<Symbol> {
+        get_supported_tokens(env)
+    }
+
+    pub fn get_token_info(env: Env, denom: Symbol) -> TokenInfo {
+        get_token_info(env, denom)
+    }
+
+    pub fn get_token_price(env: Env, denom: Symbol) -> TokenPrice {
+        fetch_token_price(env, denom)
+    }
+
+    pub fn get_liquidity_rate(env: Env, denom: Symbol) -> u128 {
+        get_liquidity_rate(env, denom)
+    }
+
+    pub fn get_user_borrow_with_interest(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_user_borrow_amount_with_interest(env, user, denom)
+    }
+
+    pub fn get_user_max_allowed_borrow_usd(env: Env, user: Address) -> u128 {
+        get_user_max_allowed_borrow_amount_usd(env, user)
+    }
+
+    pub fn get_user_borrowed_usd(env: Env, user: Address) -> u128 {
+        get_user_borrowed_usd(env, user)
+    }
+
+    pub fn get_user_liquidation_threshold(env: Env, user: Address) -> u128 {
+        get_user_liquidation_threshold(env, user)
+    }
+
+    pub fn get_user_utilization_rate(env: Env, user: Address) -> u128 {
+        get_user_utilization_rate(env, user)
+    }
+    pub fn get_available_to_borrow(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_available_to_borrow(env, user, denom)
+    }
+
+    pub fn get_available_to_redeem(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_available_to_redeem(env, user, denom)
+    }
+
+    pub fn get_markets_overview(env: Env, offset: u32, limit: u32) -> Vec<MarketOverview> {
+        get_markets_overview(env, offset, limit)
+    }
+
+    pub fn get_account_snapshot(env: Env, user: Address) -> AccountSnapshot {
+        get_account_snapshot(env, user)
+    }
+
+    pub fn get_user_health_factor(env: Env, user: Address) -> u128 {
+        get_account_snapshot(env, user).health_factor
+    }
+
+    pub fn get_user_collateral_usd(env: Env, user: Address) -> u128 {
+        get_user_collateral_usd(env, user)
+    }
+
+    pub fn get_reserve_configuration(env: Env, denom: Symbol) -> ReserveConfiguration {
+        get_reserve_configuration(env, denom)
+    }
+
+    pub fn set_reserve_configuration(
+        env: Env,
+        caller: Address,
+        denom: Symbol,
+        loan_to_value_ratio: u128,
+        liquidation_threshold: u128,
+        liquidation_bonus: u128,
+        supply_cap: u128,
+        borrow_cap: u128,
+    ) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        if liquidation_bonus > HUNDRED_PERCENT {
+            panic_with_error!(&env, LendingError::InvalidParameter);
+        }
+
+        if loan_to_value_ratio != 0 && is_market_deprecated(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::MarketDeprecated);
+        }
+
+        let mut reserve_map: Map<Symbol, ReserveConfiguration> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::ReserveConfiguration)
+            .unwrap_or(Map::new(&env));
+        reserve_map.set(
+            denom.clone(),
+            ReserveConfiguration {
+                denom: denom.clone(),
+                loan_to_value_ratio,
+                liquidation_threshold,
+                liquidation_bonus,
+                supply_cap,
+                borrow_cap,
+            },
+        );
+        env.storage()
+            .persistent()
+            .set(&DataKey::ReserveConfiguration, &reserve_map);
+        env.storage().persistent().bump(
+            &DataKey::ReserveConfiguration,
+            MONTH_LIFETIME_THRESHOLD,
+            MONTH_BUMP_AMOUNT,
+        );
+
+        events::set_reserve_configuration(
+            &env,
+            denom,
+            loan_to_value_ratio,
+            liquidation_threshold,
+            liquidation_bonus,
+            supply_cap,
+            borrow_cap,
+        );
+    }
+
+    pub fn get_supply_headroom(env: Env, denom: Symbol) -> u128 {
+        get_supply_headroom(env, denom)
+    }
+
+    pub fn get_borrow_headroom(env: Env, denom: Symbol) -> u128 {
+        get_borrow_headroom(env, denom)
+    }
+
+    pub fn set_vault_contract(env: Env, vault_contract: Address) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        set_vault_contract(&env, &vault_contract);
+
+        events::set_vault_contract(&env, vault_contract);
+    }
+
+    pub fn get_vault_contract(env: Env) -> Address {
         get_vault_contract(&env)
     }
 
     pub fn set_token_interest_rate_params(
         env: Env,
+        caller: Address,
         denom: Symbol,
         min_interest_rate: u128,
         safe_borrow_max_rate: u128,
         rate_growth_factor: u128,
         optimal_utilization_ratio: u128,
     ) {
-        let admin: Address = get_admin(&env);
-        admin.require_auth();
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
 
         let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
 
         if !supported_tokens.contains(denom.clone()) {
-            panic!("There is no such supported token yet");
+            panic_with_error!(&env, LendingError::UnknownDenom);
         }
 
+        // accruing the interest at the previous rates
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
         let mut token_interest_params_map: Map<Symbol, TokenInterestRateModelParams> = env
             .storage()
             .persistent()
@@ -1011,14 +1546,319 @@ impl LendingContract {
             MONTH_LIFETIME_THRESHOLD,
             MONTH_BUMP_AMOUNT,
         );
+
+        events::set_token_interest_rate_params(
+            &env,
+            denom,
+            min_interest_rate,
+            safe_borrow_max_rate,
+            rate_growth_factor,
+            optimal_utilization_ratio,
+        );
+    }
+
+    pub fn set_flash_loan_fee(env: Env, caller: Address, flash_loan_fee: u128) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        if flash_loan_fee > HUNDRED_PERCENT {
+            panic_with_error!(&env, LendingError::InvalidParameter);
+        }
+
+        set_flash_loan_fee(&env, flash_loan_fee);
+
+        events::set_flash_loan_fee(&env, flash_loan_fee);
+    }
+
+    pub fn get_flash_loan_fee(env: Env) -> u128 {
+        get_flash_loan_fee(&env)
+    }
+
+    pub fn set_close_factor(env: Env, caller: Address, close_factor: u128) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        if close_factor == 0 || close_factor > HUNDRED_PERCENT {
+            panic_with_error!(&env, LendingError::InvalidParameter);
+        }
+
+        set_close_factor(&env, close_factor);
+
+        events::set_close_factor(&env, close_factor);
+    }
+
+    pub fn get_close_factor(env: Env) -> u128 {
+        get_close_factor(&env)
+    }
+
+    pub fn set_reserve_factor(env: Env, caller: Address, denom: Symbol, reserve_factor: u128) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        if reserve_factor > HUNDRED_PERCENT {
+            panic_with_error!(&env, LendingError::InvalidParameter);
+        }
+
+        // the interest accrued so far is split with the previous reserve factor
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+        set_reserve_factor(env.clone(), denom.clone(), reserve_factor);
+
+        events::set_reserve_factor(&env, denom, reserve_factor);
+    }
+
+    pub fn get_reserve_factor(env: Env, denom: Symbol) -> u128 {
+        get_reserve_factor(env, denom)
+    }
+
+    pub fn get_protocol_reserves(env: Env, denom: Symbol) -> u128 {
+        get_protocol_reserves(env, denom)
+    }
+
+    pub fn set_stable_rate_premium(
+        env: Env,
+        caller: Address,
+        denom: Symbol,
+        stable_rate_premium: u128,
+    ) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        // the stable borrows already taken keep their rates
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+        set_stable_rate_premium(env.clone(), denom.clone(), stable_rate_premium);
+
+        events::set_stable_rate_premium(&env, denom, stable_rate_premium);
+    }
+
+    pub fn set_emode_category(
+        env: Env,
+        caller: Address,
+        category_id: u32,
+        label: Symbol,
+        loan_to_value_ratio: u128,
+        liquidation_threshold: u128,
+        price_source: Option<Symbol>,
+    ) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        if category_id == 0
+            || loan_to_value_ratio > liquidation_threshold
+            || liquidation_threshold > HUNDRED_PERCENT
+        {
+            panic_with_error!(&env, LendingError::InvalidParameter);
+        }
+
+        if let Some(price_source) = price_source.clone() {
+            if !get_supported_tokens(env.clone()).contains(price_source) {
+                panic_with_error!(&env, LendingError::UnknownDenom);
+            }
+        }
+
+        set_emode_category(
+            env.clone(),
+            category_id,
+            EModeCategory {
+                label: label.clone(),
+                loan_to_value_ratio,
+                liquidation_threshold,
+                price_source,
+            },
+        );
+
+        events::set_emode_category(
+            &env,
+            category_id,
+            label,
+            loan_to_value_ratio,
+            liquidation_threshold,
+        );
+    }
+
+    pub fn set_market_emode_category(env: Env, caller: Address, denom: Symbol, category_id: u32) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        if category_id != 0 && get_emode_category(env.clone(), category_id).is_none() {
+            panic_with_error!(&env, LendingError::EModeCategoryNotFound);
+        }
+
+        set_market_emode_category(env.clone(), denom.clone(), category_id);
+
+        events::set_market_emode_category(&env, denom, category_id);
+    }
+
+    pub fn set_user_emode(env: Env, user: Address, category_id: u32) {
+        user.require_auth();
+
+        if category_id != 0 {
+            if get_emode_category(env.clone(), category_id).is_none() {
+                panic_with_error!(&env, LendingError::EModeCategoryNotFound);
+            }
+
+            // the category applies only while all the borrows of the user are in it
+            for token in get_supported_tokens(env.clone()) {
+                if get_user_borrow_amount_with_interest(env.clone(), user.clone(), token.clone())
+                    != 0
+                    && get_market_emode_category(env.clone(), token) != category_id
+                {
+                    panic_with_error!(&env, LendingError::EModeCategoryMismatch);
+                }
+            }
+        }
+
+        set_user_emode_category(env.clone(), user.clone(), category_id);
+
+        // leaving the category lowers the ratios of the collateral
+        if !is_user_healthy(env.clone(), user.clone()) {
+            panic_with_error!(&env, LendingError::CollateralInUse);
+        }
+
+        events::set_user_emode(&env, user, category_id);
+    }
+
+    pub fn get_emode_category(env: Env, category_id: u32) -> Option<EModeCategory> {
+        get_emode_category(env, category_id)
+    }
+
+    pub fn get_market_emode_category(env: Env, denom: Symbol) -> u32 {
+        get_market_emode_category(env, denom)
+    }
+
+    pub fn get_user_emode(env: Env, user: Address) -> u32 {
+        get_user_emode_category(env, user)
+    }
+
+    pub fn set_debt_ceiling(env: Env, caller: Address, denom: Symbol, debt_ceiling: u128) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        set_debt_ceiling(env.clone(), denom.clone(), debt_ceiling);
+
+        events::set_debt_ceiling(&env, denom, debt_ceiling);
+    }
+
+    pub fn set_borrowable_in_isolation(env: Env, caller: Address, denom: Symbol, borrowable: bool) {
+        // Risk manager role
+        require_role(&env, &caller, Role::RiskManager);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        set_borrowable_in_isolation(env.clone(), denom.clone(), borrowable);
+
+        events::set_borrowable_in_isolation(&env, denom, borrowable);
+    }
+
+    pub fn get_debt_ceiling(env: Env, denom: Symbol) -> u128 {
+        get_debt_ceiling(env, denom)
+    }
+
+    pub fn get_isolated_debt(env: Env, denom: Symbol) -> u128 {
+        get_isolated_debt(env, denom)
+    }
+
+    pub fn is_borrowable_in_isolation(env: Env, denom: Symbol) -> bool {
+        is_borrowable_in_isolation(env, denom)
+    }
+
+    pub fn get_user_isolated_collateral(env: Env, user: Address) -> Option<Symbol> {
+        get_user_isolated_collateral(env, user)
+    }
+
+    pub fn get_stable_rate_premium(env: Env, denom: Symbol) -> u128 {
+        get_stable_rate_premium(env, denom)
+    }
+
+    pub fn get_stable_interest_rate(env: Env, denom: Symbol) -> u128 {
+        get_stable_interest_rate(env, denom)
+    }
+
+    pub fn get_user_stable_borrow(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_user_stable_borrow_amount_with_interest(env, user, denom)
+    }
+
+    pub fn get_user_stable_rate(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_user_stable_borrow_data(env, user, denom).interest_rate
+    }
+
+    pub fn get_total_stable_borrow_data(env: Env, denom: Symbol) -> TotalStableBorrowData {
+        get_total_stable_borrow_data(env, denom)
+    }
+
+    pub fn withdraw_reserves(env: Env, caller: Address, denom: Symbol, amount: u128, to: Address) {
+        // Reserves treasurer role
+        require_role(&env, &caller, Role::ReservesTreasurer);
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        if amount == 0 {
+            panic_with_error!(&env, LendingError::ZeroAmount);
+        }
+
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+        let protocol_reserves: u128 = get_protocol_reserves(env.clone(), denom.clone());
+
+        if amount > protocol_reserves {
+            panic_with_error!(&env, LendingError::InsufficientReserves);
+        }
+
+        if amount > get_available_liquidity_by_token(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::InsufficientLiquidity);
+        }
+
+        set_protocol_reserves(env.clone(), denom.clone(), protocol_reserves - amount);
+
+        events::withdraw_reserves(&env, denom.clone(), to.clone(), amount);
+
+        let vault_contract_client = vault_contract::Client::new(&env, &get_vault_contract(&env));
+        vault_contract_client.withdraw_reserves_from_vault(
+            &to,
+            &get_token_address(env.clone(), denom.clone()),
+            &amount,
+        )
     }
 
     pub fn get_interest_rate(env: Env, denom: Symbol) -> u128 {
         get_interest_rate(env, denom)
     }
 
-    pub fn get_user_borrowing_info(env: Env, user: Address, denom: Symbol) -> UserBorrowingInfo {
-        get_user_borrowing_info(env, user, denom)
+    pub fn get_user_scaled_borrowed_amount(env: Env, user: Address, denom: Symbol) -> u128 {
+        get_user_scaled_borrowed_amount(env, user, denom)
     }
 
     pub fn user_deposit_as_collateral(env: Env, user: Address, denom: Symbol) -> bool {
@@ -1053,3 +1893,425 @@ impl LendingContract {
     //     get_all_users_with_borrows(env)
     // }
 }
+
+impl LendingContract {
+    fn set_collateral_internal(
+        env: Env,
+        user: Address,
+        denom: Symbol,
+        use_as_collateral: bool,
+        check_health: bool,
+    ) {
+        check_market_active(env.clone(), denom.clone());
+
+        let use_user_deposit_as_collateral =
+            user_deposit_as_collateral(env.clone(), user.clone(), denom.clone());
+
+        if use_user_deposit_as_collateral == use_as_collateral {
+            return;
+        }
+
+        if use_user_deposit_as_collateral {
+            let user_token_balance: u128 = get_deposit(env.clone(), user.clone(), denom.clone());
+
+            if check_health && user_token_balance != 0 {
+                let token_decimals = get_token_decimal(env.clone(), denom.clone());
+
+                let price = fetch_user_price_by_token(env.clone(), user.clone(), denom.clone());
+
+                let user_token_balance_usd =
+                    Decimal::from_i128_with_scale(user_token_balance as i128, token_decimals)
+                        .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
+                        .to_u128_with_decimals(USD_DECIMALS)
+                        .unwrap_or_overflow(&env);
+
+                let sum_collateral_balance_usd = get_user_collateral_usd(env.clone(), user.clone());
+
+                let sum_borrow_balance_usd = get_user_borrowed_usd(env.clone(), user.clone());
+
+                let user_liquidation_threshold =
+                    get_user_liquidation_threshold(env.clone(), user.clone());
+
+                // the borrows can't be left without collateral backing them
+                if sum_borrow_balance_usd != 0
+                    && (user_liquidation_threshold == 0
+                        || sum_borrow_balance_usd * HUNDRED_PERCENT / user_liquidation_threshold
+                            >= sum_collateral_balance_usd - user_token_balance_usd)
+                {
+                    panic_with_error!(&env, LendingError::CollateralInUse);
+                }
+            }
+        } else {
+            // an isolated collateral can't be combined with any other collateral
+            let has_collateral: bool = get_supported_tokens(env.clone())
+                .iter()
+                .any(|token| user_deposit_as_collateral(env.clone(), user.clone(), token));
+
+            if get_user_isolated_collateral(env.clone(), user.clone()).is_some()
+                || (is_market_isolated(env.clone(), denom.clone()) && has_collateral)
+            {
+                panic_with_error!(&env, LendingError::IsolatedCollateral);
+            }
+        }
+
+        let mut user_deposit_as_collateral_map: Map<Symbol, bool> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::UserDepositAsCollateral(user.clone()))
+            .unwrap_or(Map::new(&env));
+        user_deposit_as_collateral_map.set(denom.clone(), use_as_collateral);
+        env.storage().persistent().set(
+            &DataKey::UserDepositAsCollateral(user.clone()),
+            &user_deposit_as_collateral_map,
+        );
+        env.storage().persistent().bump(
+            &DataKey::UserDepositAsCollateral(user.clone()),
+            MONTH_LIFETIME_THRESHOLD,
+            MONTH_BUMP_AMOUNT,
+        );
+
+        // the existing borrows must be allowed against the isolated collateral and fit its ceiling
+        if use_as_collateral && is_market_isolated(env.clone(), denom.clone()) {
+            execute_charge_isolated_debt(env.clone(), user.clone());
+        }
+
+        events::toggle_collateral_setting(&env, user, denom, use_as_collateral);
+    }
+
+    fn deposit_internal(
+        env: Env,
+        from: Address,
+        on_behalf_of: Address,
+        denom: Symbol,
+        deposited_token_amount: u128,
+    ) {
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        if deposited_token_amount == 0 {
+            panic_with_error!(&env, LendingError::ZeroAmount);
+        }
+
+        if check_market_active(env.clone(), denom.clone()).deposits_frozen {
+            panic_with_error!(&env, LendingError::DepositsFrozen);
+        }
+
+        if is_market_deprecated(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::MarketDeprecated);
+        }
+
+        let token_address: Address = get_token_address(env.clone(), denom.clone());
+        move_token(
+            &env,
+            &token_address,
+            &from,
+            &env.current_contract_address(),
+            deposited_token_amount.clone() as i128,
+        );
+
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+        if deposited_token_amount > get_supply_headroom(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::SupplyCapExceeded);
+        }
+
+        let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+        let mm_token_price: u128 = get_mm_token_price(env.clone(), denom.clone());
+
+        let deposited_mm_token_amount =
+            Decimal::from_i128_with_scale(deposited_token_amount as i128, token_decimals)
+                .div(Decimal::from_i128_with_scale(
+                    mm_token_price as i128,
+                    token_decimals,
+                ))
+                .to_u128_with_decimals(token_decimals)
+                .unwrap_or_overflow(&env);
+
+        let user_current_mm_token_balance: u128 =
+            get_mm_token_balance(env.clone(), on_behalf_of.clone(), denom.clone());
+
+        set_mm_token_balance(
+            env.clone(),
+            on_behalf_of.clone(),
+            denom.clone(),
+            user_current_mm_token_balance + deposited_mm_token_amount,
+        );
+
+        events::deposit(
+            &env,
+            on_behalf_of.clone(),
+            denom.clone(),
+            deposited_token_amount,
+            deposited_mm_token_amount,
+        );
+        emit_mm_token_mint(
+            env.clone(),
+            denom.clone(),
+            on_behalf_of.clone(),
+            deposited_mm_token_amount,
+        );
+
+        move_token(
+            &env,
+            &get_token_address(env.clone(), denom.clone()),
+            &env.current_contract_address(),
+            &get_vault_contract(&env),
+            deposited_token_amount.clone() as i128,
+        )
+    }
+
+    fn redeem_internal(
+        env: Env,
+        user: Address,
+        to: Address,
+        denom: Symbol,
+        mut amount: u128,
+        check_health: bool,
+    ) {
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        check_market_active(env.clone(), denom.clone());
+
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+        let current_balance = get_deposit(env.clone(), user.clone(), denom.clone());
+
+        if amount > current_balance {
+            panic_with_error!(&env, LendingError::InsufficientDeposit);
+        }
+
+        if amount == 0 {
+            amount = current_balance;
+        }
+
+        let remaining: u128 = current_balance - amount;
+
+        let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+        let mm_token_price: u128 = get_mm_token_price(env.clone(), denom.clone());
+
+        let new_user_mm_token_balance: u128 =
+            Decimal::from_i128_with_scale(remaining as i128, token_decimals)
+                .div(Decimal::from_i128_with_scale(
+                    mm_token_price as i128,
+                    token_decimals,
+                ))
+                .to_u128_with_decimals(token_decimals)
+                .unwrap_or_overflow(&env);
+
+        let user_current_mm_token_balance: u128 =
+            get_mm_token_balance(env.clone(), user.clone(), denom.clone());
+
+        set_mm_token_balance(
+            env.clone(),
+            user.clone(),
+            denom.clone(),
+            new_user_mm_token_balance,
+        );
+
+        let redeemed_mm_token_amount: u128 = user_current_mm_token_balance
+            .checked_sub(new_user_mm_token_balance)
+            .unwrap_or_default();
+
+        events::redeem(
+            &env,
+            user.clone(),
+            denom.clone(),
+            amount,
+            redeemed_mm_token_amount,
+        );
+        emit_mm_token_burn(
+            env.clone(),
+            denom.clone(),
+            user.clone(),
+            redeemed_mm_token_amount,
+        );
+
+        // the borrows must stay within the LTV of the collateral left, deposits that don't back
+        // any borrow can always be redeemed
+        if check_health
+            && user_deposit_as_collateral(env.clone(), user.clone(), denom.clone())
+            && get_user_borrowed_usd(env.clone(), user.clone())
+                > get_user_max_allowed_borrow_amount_usd(env.clone(), user.clone())
+        {
+            panic_with_error!(&env, LendingError::BorrowAmountNotAvailable);
+        }
+
+        // vault cross-contract call to redeem money
+        let vault_contract_client = vault_contract::Client::new(&env, &get_vault_contract(&env));
+        vault_contract_client.redeem_from_vault_contract(
+            &to,
+            &get_token_address(env.clone(), denom.clone()),
+            &amount,
+        )
+    }
+
+    fn borrow_internal(
+        env: Env,
+        user: Address,
+        to: Address,
+        denom: Symbol,
+        amount: u128,
+        interest_rate_mode: InterestRateMode,
+        check_health: bool,
+    ) {
+        // if user == liquidator {
+        //     panic!("The liquidator cannot borrow");
+        // }
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(denom.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        if amount == 0 {
+            panic_with_error!(&env, LendingError::ZeroAmount);
+        }
+
+        if check_market_active(env.clone(), denom.clone()).borrows_frozen {
+            panic_with_error!(&env, LendingError::BorrowsFrozen);
+        }
+
+        if is_market_deprecated(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::MarketDeprecated);
+        }
+
+        if interest_rate_mode == InterestRateMode::Stable
+            && get_stable_rate_premium(env.clone(), denom.clone()) == 0
+        {
+            panic_with_error!(&env, LendingError::StableBorrowingDisabled);
+        }
+
+        if amount > get_borrow_headroom(env.clone(), denom.clone()) {
+            panic_with_error!(&env, LendingError::BorrowCapExceeded);
+        }
+
+        if check_health
+            && amount > get_available_to_borrow(env.clone(), user.clone(), denom.clone())
+        {
+            panic_with_error!(&env, LendingError::BorrowAmountNotAvailable);
+        }
+
+        let user_emode_category: u32 = get_user_emode_category(env.clone(), user.clone());
+
+        if user_emode_category != 0
+            && get_market_emode_category(env.clone(), denom.clone()) != user_emode_category
+        {
+            panic_with_error!(&env, LendingError::EModeCategoryMismatch);
+        }
+
+        execute_add_isolated_debt(env.clone(), user.clone(), denom.clone(), amount);
+
+        //     assert!(
+        //         get_available_liquidity_by_token(env.clone(), denom.clone())
+        //             .unwrap()
+        //             .u128()
+        //             >= amount.u128()
+        //     );
+
+        execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+        // the stable rate is quoted at the utilization before the borrow
+        let stable_interest_rate: u128 = get_stable_interest_rate(env.clone(), denom.clone());
+        match interest_rate_mode {
+            InterestRateMode::Variable => {
+                execute_borrow(env.clone(), user.clone(), denom.clone(), amount)
+            }
+            InterestRateMode::Stable => execute_stable_borrow(
+                env.clone(),
+                user.clone(),
+                denom.clone(),
+                amount,
+                stable_interest_rate,
+            ),
+        }
+
+        let vault_contract_client = vault_contract::Client::new(&env, &get_vault_contract(&env));
+        vault_contract_client.borrow_from_vault_contract(
+            &to,
+            &get_token_address(env.clone(), denom.clone()),
+            &amount,
+        );
+
+        let interest_rate: u128 = match interest_rate_mode {
+            InterestRateMode::Variable => get_interest_rate(env.clone(), denom.clone()),
+            InterestRateMode::Stable => stable_interest_rate,
+        };
+
+        events::borrow(
+            &env,
+            user.clone(),
+            denom.clone(),
+            to.clone(),
+            amount,
+            get_user_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone()),
+            interest_rate,
+            interest_rate_mode,
+        );
+    }
+
+    fn repay_internal(
+        env: Env,
+        payer: Address,
+        borrower: Address,
+        repay_token: Symbol,
+        mut repay_amount: u128,
+    ) {
+        check_market_active(env.clone(), repay_token.clone());
+
+        let supported_tokens: Vec<Symbol> = get_supported_tokens(env.clone());
+
+        if !supported_tokens.contains(repay_token.clone()) {
+            panic_with_error!(&env, LendingError::UnknownDenom);
+        }
+
+        execute_update_liquidity_index_data(env.clone(), repay_token.clone());
+
+        let user_borrow_amount_with_interest = get_user_borrow_amount_with_interest(
+            env.clone(),
+            borrower.clone(),
+            repay_token.clone(),
+        );
+
+        if user_borrow_amount_with_interest == 0 {
+            panic_with_error!(&env, LendingError::NoBorrow);
+        }
+
+        // 0 repays the whole borrow, the payer is never charged more than the borrow
+        if repay_amount == 0 || repay_amount > user_borrow_amount_with_interest {
+            repay_amount = user_borrow_amount_with_interest;
+        }
+
+        move_token(
+            &env,
+            &get_token_address(env.clone(), repay_token.clone()),
+            &payer,
+            &get_vault_contract(&env),
+            repay_amount as i128,
+        );
+
+        execute_repay_borrow(
+            env.clone(),
+            borrower.clone(),
+            repay_token.clone(),
+            repay_amount,
+        );
+
+        events::repay(
+            &env,
+            borrower,
+            repay_token,
+            payer,
+            repay_amount,
+            user_borrow_amount_with_interest - repay_amount,
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use soroban_sdk::contracterror;
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum LendingError {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    // Admin, liquidator or vault contract is not set
+    UnknownDenom = 3,
+    // The denom is not in the list of supported tokens
+    MarketAlreadyExists = 4,
+    PriceNotFound = 5,
+    ZeroAmount = 6,
+    MathOverflow = 7,
+    // Decimal conversion or arithmetic overflowed
+    InsufficientDeposit = 8,
+    // The account doesn't have enough digital tokens to redeem
+    BorrowAmountNotAvailable = 9,
+    LiquidationThresholdNotReached = 10,
+    InsufficientLiquidatorDeposit = 11,
+    // The liquidator does not have enough deposit balance for liquidation
+    CollateralInUse = 12,
+    // The collateral is already used to collateralise the borrowing
+    InsufficientLiquidity = 13,
+    // The vault doesn't hold enough tokens of the denom
+    FlashLoanNotRepaid = 14,
+    // The flash loan receiver hasn't returned the principal plus the fee
+    InvalidParameter = 15,
+    CollateralNotEnabled = 16,
+    // The user doesn't use the denom as collateral
+    NoBorrow = 17,
+    // The user has nothing borrowed in the denom
+    StalePrice = 18,
+    // The price is older than the max price age of the market
+    InsufficientReserves = 19,
+    // The protocol reserves of the denom are lower than the requested amount
+    MMTokenNotFound = 20,
+    // The market has no mm token contract
+    SupplyCapExceeded = 21,
+    // The deposit would push the market reserves above its supply cap
+    BorrowCapExceeded = 22,
+    // The borrow would push the market borrows above its borrow cap
+    ProtocolPaused = 23,
+    MarketPaused = 24,
+    DepositsFrozen = 25,
+    BorrowsFrozen = 26,
+    Unauthorized = 27,
+    // The caller is neither the admin nor the holder of the role
+    MarketDeprecated = 28,
+    // The market is deprecated and accepts no new deposits or borrows
+    MarketNotDeprecated = 29,
+    MarketNotEmpty = 30,
+    // The market still has deposits, borrows or protocol reserves
+    NoPendingTransfer = 31,
+    // There is no proposed admin or liquidator to accept or cancel
+    StorageUpToDate = 32,
+    // The storage already has the current layout, there is nothing to migrate
+    RoleNotGranted = 33,
+    // Nobody holds the role to revoke
+    StableBorrowingDisabled = 34,
+    // The market has no stable rate premium set
+    RebalanceNotAllowed = 35,
+    // The stable rate of the position is neither below the deposit rate nor underpriced
+    // at a utilization above the optimal ratio
+    IsolatedCollateral = 36,
+    // An isolated collateral can't be enabled together with any other collateral
+    NotBorrowableInIsolation = 37,
+    // The denom can't be borrowed against an isolated collateral
+    DebtCeilingExceeded = 38,
+    // The borrow would push the debt against the isolated collateral above its ceiling
+    EModeCategoryNotFound = 39,
+    EModeCategoryMismatch = 40,
+    // The borrowed denom is not in the e-mode category of the user
+    BorrowAllowanceExceeded = 41,
+    // The delegator hasn't approved the delegatee to borrow the amount
+    MarketRemoved = 42,
+    // A removed denom can't be listed again, its per-market state is left behind
+    ZeroMMTokenPrice = 43,
+    // The losses socialized among the depositors took the mm token price to zero
+    RemainingDeposits = 44,
+    // The borrows are written off only once all the user deposits are gone
+}
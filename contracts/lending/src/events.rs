@@ -0,0 +1,358 @@
+use soroban_sdk::{symbol_short, Address, BytesN, Env, Symbol};
+
+use crate::storage::{InterestRateMode, Role};
+
+pub(crate) fn deposit(
+    env: &Env,
+    user: Address,
+    denom: Symbol,
+    token_amount: u128,
+    mm_token_amount: u128,
+) {
+    let topics = (symbol_short!("deposit"), user, denom);
+    env.events()
+        .publish(topics, (token_amount, mm_token_amount));
+}
+
+pub(crate) fn redeem(
+    env: &Env,
+    user: Address,
+    denom: Symbol,
+    token_amount: u128,
+    mm_token_amount: u128,
+) {
+    let topics = (symbol_short!("redeem"), user, denom);
+    env.events()
+        .publish(topics, (token_amount, mm_token_amount));
+}
+
+pub(crate) fn borrow(
+    env: &Env,
+    user: Address,
+    denom: Symbol,
+    to: Address,
+    token_amount: u128,
+    borrowed_amount: u128,
+    interest_rate: u128,
+    interest_rate_mode: InterestRateMode,
+) {
+    let topics = (symbol_short!("borrow"), user, denom);
+    env.events().publish(
+        topics,
+        (
+            to,
+            token_amount,
+            borrowed_amount,
+            interest_rate,
+            interest_rate_mode,
+        ),
+    );
+}
+
+pub(crate) fn approve_delegation(
+    env: &Env,
+    delegator: Address,
+    delegatee: Address,
+    denom: Symbol,
+    amount: u128,
+) {
+    let topics = (Symbol::new(env, "approve_delegation"), delegator, denom);
+    env.events().publish(topics, (delegatee, amount));
+}
+
+pub(crate) fn rebalance_stable_rate(
+    env: &Env,
+    user: Address,
+    denom: Symbol,
+    previous_interest_rate: u128,
+    interest_rate: u128,
+) {
+    let topics = (Symbol::new(env, "rebalance_stable_rate"), user, denom);
+    env.events()
+        .publish(topics, (previous_interest_rate, interest_rate));
+}
+
+pub(crate) fn repay(
+    env: &Env,
+    user: Address,
+    denom: Symbol,
+    payer: Address,
+    token_amount: u128,
+    borrowed_amount: u128,
+) {
+    let topics = (symbol_short!("repay"), user, denom);
+    env.events()
+        .publish(topics, (payer, token_amount, borrowed_amount));
+}
+
+pub(crate) fn liquidation(
+    env: &Env,
+    user: Address,
+    debt_denom: Symbol,
+    liquidator: Address,
+    collateral_denom: Symbol,
+    collateral_amount: u128,
+    repaid_amount: u128,
+) {
+    let topics = (symbol_short!("liquidate"), user, debt_denom);
+    env.events().publish(
+        topics,
+        (
+            liquidator,
+            collateral_denom,
+            collateral_amount,
+            repaid_amount,
+        ),
+    );
+}
+
+pub(crate) fn bad_debt(
+    env: &Env,
+    user: Address,
+    denom: Symbol,
+    bad_debt: u128,
+    covered_by_reserves: u128,
+    socialized: u128,
+) {
+    let topics = (symbol_short!("bad_debt"), user, denom);
+    env.events()
+        .publish(topics, (bad_debt, covered_by_reserves, socialized));
+}
+
+pub(crate) fn settle_bad_debt(env: &Env, payer: Address, denom: Symbol, amount: u128) {
+    let topics = (Symbol::new(env, "settle_bad_debt"), payer, denom);
+    env.events().publish(topics, amount);
+}
+
+pub(crate) fn flash_loan(
+    env: &Env,
+    receiver: Address,
+    denom: Symbol,
+    initiator: Address,
+    amount: u128,
+    fee: u128,
+) {
+    let topics = (Symbol::new(env, "flash_loan"), receiver, denom);
+    env.events().publish(topics, (initiator, amount, fee));
+}
+
+pub(crate) fn withdraw_reserves(env: &Env, denom: Symbol, to: Address, amount: u128) {
+    let topics = (Symbol::new(env, "withdraw_reserves"), denom);
+    env.events().publish(topics, (to, amount));
+}
+
+pub(crate) fn toggle_collateral_setting(
+    env: &Env,
+    user: Address,
+    denom: Symbol,
+    use_as_collateral: bool,
+) {
+    let topics = (Symbol::new(env, "toggle_collateral"), user, denom);
+    env.events().publish(topics, use_as_collateral);
+}
+
+pub(crate) fn add_markets(env: &Env, denom: Symbol, address: Address, decimals: u32) {
+    let topics = (Symbol::new(env, "add_markets"), denom);
+    env.events().publish(topics, (address, decimals));
+}
+
+pub(crate) fn update_price(env: &Env, denom: Symbol, price: u128) {
+    let topics = (Symbol::new(env, "update_price"), denom);
+    env.events().publish(topics, price);
+}
+
+pub(crate) fn set_price_oracle(env: &Env, denom: Symbol, oracle: Address) {
+    let topics = (Symbol::new(env, "set_price_oracle"), denom);
+    env.events().publish(topics, oracle);
+}
+
+pub(crate) fn set_max_price_age(env: &Env, denom: Symbol, max_price_age: u64) {
+    let topics = (Symbol::new(env, "set_max_price_age"), denom);
+    env.events().publish(topics, max_price_age);
+}
+
+pub(crate) fn deprecate_market(env: &Env, denom: Symbol) {
+    let topics = (Symbol::new(env, "deprecate_market"), denom);
+    env.events().publish(topics, ());
+}
+
+pub(crate) fn remove_market(env: &Env, denom: Symbol) {
+    let topics = (Symbol::new(env, "remove_market"), denom);
+    env.events().publish(topics, ());
+}
+
+pub(crate) fn update_token_info(env: &Env, denom: Symbol, name: Symbol, symbol: Symbol) {
+    let topics = (Symbol::new(env, "update_token_info"), denom);
+    env.events().publish(topics, (name, symbol));
+}
+
+pub(crate) fn upgrade(env: &Env, new_wasm_hash: BytesN<32>) {
+    let topics = (symbol_short!("upgrade"),);
+    env.events().publish(topics, new_wasm_hash);
+}
+
+pub(crate) fn migrate(env: &Env, from_version: u32, to_version: u32) {
+    let topics = (symbol_short!("migrate"),);
+    env.events().publish(topics, (from_version, to_version));
+}
+
+pub(crate) fn propose_admin(env: &Env, admin: Address, new_admin: Address) {
+    let topics = (Symbol::new(env, "propose_admin"), admin);
+    env.events().publish(topics, new_admin);
+}
+
+pub(crate) fn accept_admin(env: &Env, previous_admin: Address, admin: Address) {
+    let topics = (Symbol::new(env, "accept_admin"), previous_admin);
+    env.events().publish(topics, admin);
+}
+
+pub(crate) fn cancel_admin_transfer(env: &Env, pending_admin: Address) {
+    let topics = (Symbol::new(env, "cancel_admin_transfer"),);
+    env.events().publish(topics, pending_admin);
+}
+
+pub(crate) fn propose_liquidator(env: &Env, liquidator: Address, new_liquidator: Address) {
+    let topics = (Symbol::new(env, "propose_liquidator"), liquidator);
+    env.events().publish(topics, new_liquidator);
+}
+
+pub(crate) fn accept_liquidator(env: &Env, previous_liquidator: Address, liquidator: Address) {
+    let topics = (Symbol::new(env, "accept_liquidator"), previous_liquidator);
+    env.events().publish(topics, liquidator);
+}
+
+pub(crate) fn cancel_liquidator_transfer(env: &Env, pending_liquidator: Address) {
+    let topics = (Symbol::new(env, "cancel_liquidator_transfer"),);
+    env.events().publish(topics, pending_liquidator);
+}
+
+pub(crate) fn grant_role(env: &Env, role: Role, account: Address) {
+    let topics = (Symbol::new(env, "grant_role"), role);
+    env.events().publish(topics, account);
+}
+
+pub(crate) fn revoke_role(env: &Env, role: Role, account: Address) {
+    let topics = (Symbol::new(env, "revoke_role"), role);
+    env.events().publish(topics, account);
+}
+
+pub(crate) fn set_paused(env: &Env, caller: Address, paused: bool) {
+    let topics = (Symbol::new(env, "set_paused"), caller);
+    env.events().publish(topics, paused);
+}
+
+pub(crate) fn set_market_flags(
+    env: &Env,
+    denom: Symbol,
+    deposits_frozen: bool,
+    borrows_frozen: bool,
+    paused: bool,
+) {
+    let topics = (Symbol::new(env, "set_market_flags"), denom);
+    env.events()
+        .publish(topics, (deposits_frozen, borrows_frozen, paused));
+}
+
+pub(crate) fn set_reserve_configuration(
+    env: &Env,
+    denom: Symbol,
+    loan_to_value_ratio: u128,
+    liquidation_threshold: u128,
+    liquidation_bonus: u128,
+    supply_cap: u128,
+    borrow_cap: u128,
+) {
+    let topics = (Symbol::new(env, "set_reserve_config"), denom);
+    env.events().publish(
+        topics,
+        (
+            loan_to_value_ratio,
+            liquidation_threshold,
+            liquidation_bonus,
+            supply_cap,
+            borrow_cap,
+        ),
+    );
+}
+
+pub(crate) fn set_vault_contract(env: &Env, vault_contract: Address) {
+    let topics = (Symbol::new(env, "set_vault_contract"),);
+    env.events().publish(topics, vault_contract);
+}
+
+pub(crate) fn set_flash_loan_fee(env: &Env, flash_loan_fee: u128) {
+    let topics = (Symbol::new(env, "set_flash_loan_fee"),);
+    env.events().publish(topics, flash_loan_fee);
+}
+
+pub(crate) fn set_close_factor(env: &Env, close_factor: u128) {
+    let topics = (Symbol::new(env, "set_close_factor"),);
+    env.events().publish(topics, close_factor);
+}
+
+pub(crate) fn set_mm_token_wasm_hash(env: &Env, wasm_hash: BytesN<32>) {
+    let topics = (Symbol::new(env, "set_mm_token_wasm_hash"),);
+    env.events().publish(topics, wasm_hash);
+}
+
+pub(crate) fn set_reserve_factor(env: &Env, denom: Symbol, reserve_factor: u128) {
+    let topics = (Symbol::new(env, "set_reserve_factor"), denom);
+    env.events().publish(topics, reserve_factor);
+}
+
+pub(crate) fn set_stable_rate_premium(env: &Env, denom: Symbol, stable_rate_premium: u128) {
+    let topics = (Symbol::new(env, "set_stable_rate_premium"), denom);
+    env.events().publish(topics, stable_rate_premium);
+}
+
+pub(crate) fn set_emode_category(
+    env: &Env,
+    category_id: u32,
+    label: Symbol,
+    loan_to_value_ratio: u128,
+    liquidation_threshold: u128,
+) {
+    let topics = (Symbol::new(env, "set_emode_category"), category_id);
+    env.events()
+        .publish(topics, (label, loan_to_value_ratio, liquidation_threshold));
+}
+
+pub(crate) fn set_market_emode_category(env: &Env, denom: Symbol, category_id: u32) {
+    let topics = (Symbol::new(env, "set_market_emode_category"), denom);
+    env.events().publish(topics, category_id);
+}
+
+pub(crate) fn set_user_emode(env: &Env, user: Address, category_id: u32) {
+    let topics = (Symbol::new(env, "set_user_emode"), user);
+    env.events().publish(topics, category_id);
+}
+
+pub(crate) fn set_debt_ceiling(env: &Env, denom: Symbol, debt_ceiling: u128) {
+    let topics = (Symbol::new(env, "set_debt_ceiling"), denom);
+    env.events().publish(topics, debt_ceiling);
+}
+
+pub(crate) fn set_borrowable_in_isolation(env: &Env, denom: Symbol, borrowable: bool) {
+    let topics = (Symbol::new(env, "set_borrowable_in_isolation"), denom);
+    env.events().publish(topics, borrowable);
+}
+
+pub(crate) fn set_token_interest_rate_params(
+    env: &Env,
+    denom: Symbol,
+    min_interest_rate: u128,
+    safe_borrow_max_rate: u128,
+    rate_growth_factor: u128,
+    optimal_utilization_ratio: u128,
+) {
+    let topics = (Symbol::new(env, "set_interest_params"), denom);
+    env.events().publish(
+        topics,
+        (
+            min_interest_rate,
+            safe_borrow_max_rate,
+            rate_growth_factor,
+            optimal_utilization_ratio,
+        ),
+    );
+}
@@ -0,0 +1,18 @@
+use soroban_sdk::{contractclient, Address, Bytes, Env};
+
+// Interface of a contract receiving a flash loan.
+// Before returning from execute_operation the receiver has to approve the lending contract
+// to pull `amount + fee` of `token`, otherwise the whole flash loan is reverted.
+// `initiator` is the account that called flash_loan, receivers paying the fee from their own
+// balance should reject loans started by anyone else.
+#[contractclient(name = "FlashLoanReceiverClient")]
+pub trait FlashLoanReceiver {
+    fn execute_operation(
+        env: Env,
+        initiator: Address,
+        token: Address,
+        amount: u128,
+        fee: u128,
+        params: Bytes,
+    );
+}
@@ -4,3 +4,8 @@ mod storage;
 mod test;
 mod utilities;
 mod contract;
+mod errors;
+mod events;
+mod flash_loan;
+mod oracle;
+mod mm_token;
@@ -0,0 +1,19 @@
+use soroban_sdk::{contractclient, Address, Env, Symbol};
+
+// Interface of the mm token contract deployed for every market by add_markets.
+// The mm token keeps no balances, it reads and moves them through the lending contract,
+// which has it publish the mints and burns of deposits, redemptions and liquidations.
+#[contractclient(name = "MMTokenClient")]
+pub trait MMTokenInterface {
+    fn initialize(
+        env: Env,
+        lending_contract: Address,
+        denom: Symbol,
+        underlying: Address,
+        decimals: u32,
+    );
+
+    fn emit_mint(env: Env, to: Address, amount: i128);
+
+    fn emit_burn(env: Env, from: Address, amount: i128);
+}
@@ -0,0 +1,24 @@
+use soroban_sdk::{contractclient, contracttype, Address, Env, Symbol};
+
+// Types and client of a SEP-40 price feed contract.
+// Markets with a configured oracle read their price through `lastprice`, the asset being the
+// Stellar token address of the market.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn decimals(env: Env) -> u32;
+    fn lastprice(env: Env, asset: Asset) -> Option<PriceData>;
+}
@@ -13,7 +13,7 @@ pub enum DataKey {
     Admin,
     // Address of the Contract admin account
     Liquidator,
-    // Address of the liquidator account
+    // Address of the protocol liquidator account, liquidation itself is open to any account
     TotalBorrowData,
     // Map of TotalBorrowData per denom
     SupportedTokensInfo,
@@ -29,10 +29,111 @@ pub enum DataKey {
     TokensInterestRateModelParams,
     // Map TokenInterestRateModelParams per denom
     Prices,
-    // Map price for denom
+    // Map TokenPrice for denom
     UserDepositAsCollateral(Address),
     // Map of bool per denom
-    UserBorrowingInfo(Address), // Map UserBorrowingInfo per denom
+    UserBorrowingInfo(Address), // Map UserBorrowingInfoV1 per denom
+    // borrows of storage version 1, converted to the scaled amount on the next user update
+    FlashLoanFee,
+    // Flash loan fee in percent of the borrowed amount
+    CloseFactor,
+    // Max share of a borrow that can be repaid in a single liquidation
+    PriceOracles,
+    // Map of SEP-40 price oracle address per denom
+    MaxPriceAge,
+    // Map of the max price age in seconds per denom, 1 hour if not set, 0 disables the check
+    ReserveFactor,
+    // Map of the share of borrow interest diverted to the protocol per denom
+    ProtocolReserves,
+    // Map of protocol reserves accrued up to the liquidity index timestamp per denom
+    MMTokenWasmHash,
+    // Wasm hash of the mm token contract deployed by add_markets
+    MMTokens,
+    // Map of mm token contract address per denom
+    Paused,
+    // Global pause flag, blocks every user operation
+    MarketFlags,
+    // Map of MarketFlags per denom
+    DeprecatedMarkets,
+    // Map of bool per denom, deprecated markets only allow winding positions down
+    TotalMMTokenSupply,
+    // Map of the total mm token supply per denom
+    PendingAdmin,
+    // Address proposed as the new admin, waiting for its acceptance
+    PendingLiquidator,
+    // Address proposed as the new liquidator, waiting for its acceptance
+    StorageVersion,
+    // Version of the storage layout, contracts initialized before versioning have none
+    Roles,
+    // Map of the account holding each Role
+    BadDebt,
+    // Map of BadDebtData per denom
+    UserScaledBorrowedAmount(Address),
+    // Map of the user borrowed amount divided by the borrow index per denom
+    StableRatePremium,
+    // Map of the stable rate premium over the variable rate per denom, 0 disables stable borrows
+    TotalStableBorrowData,
+    // Map of TotalStableBorrowData per denom
+    UserStableBorrowData(Address),
+    // Map of StableBorrowData per denom
+    DebtCeiling,
+    // Map of the max USD debt backed by an isolated collateral per denom, 0 means not isolated
+    IsolatedDebt,
+    // Map of the USD debt borrowed against each isolated collateral
+    BorrowableInIsolation,
+    // Map of bool per denom, whether it can be borrowed against an isolated collateral
+    EModeCategories,
+    // Map of EModeCategory per category id
+    MarketEModeCategory,
+    // Map of the e-mode category id per denom, 0 means no category
+    UserEModeCategory(Address),
+    // e-mode category id the user opted in, 0 means none
+    BorrowAllowance(Address, Address),
+    // Map of the amount the delegatee can borrow against the delegator collateral per denom
+    LiquidityLossLn,
+    // Map of the ln of the losses socialized among the depositors per denom
+    UserIsolatedDebt(Address),
+    // Map of UserIsolatedDebtData per borrowed denom
+    RemovedMarkets,
+    // Map of bool per denom, removed denoms can't be listed again
+    MigrationV1Timestamp,
+    // Time of the migration from storage version 1, when the borrow index started
+}
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InterestRateMode {
+    Variable,
+    // Follows the utilization of the market through the borrow index
+    Stable,
+    // Fixed at borrow time until the position is rebalanced
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub enum Action {
+    Deposit(Symbol, u128),
+    Redeem(Symbol, u128),
+    Borrow(Symbol, u128),
+    // Borrows at the variable rate
+    Repay(Symbol, u128),
+    EnableCollateral(Symbol),
+    DisableCollateral(Symbol),
+}
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    MarketListing,
+    // Adds, deprecates and removes markets
+    RiskManager,
+    // Sets reserve configurations, interest rate params, fees, factors and oracles
+    PriceUpdater,
+    // Updates the admin prices
+    PauseGuardian,
+    // Pauses the protocol and freezes markets next to the admin
+    ReservesTreasurer,
+    // Withdraws the protocol reserves
 }
 
 #[contracttype]
@@ -56,6 +157,56 @@ pub struct LiquidityIndexData {
 #[contracttype]
 #[derive(Clone, Debug)]
 pub struct TotalBorrowData {
+    pub denom: Symbol,
+    pub total_scaled_borrowed_amount: u128,
+    // Sum of the user borrowed amounts divided by the borrow index
+    pub borrow_index_ln: u128,
+    // Natural logarithm of the cumulative borrow interest
+    pub timestamp: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct TotalStableBorrowData {
+    pub denom: Symbol,
+    pub total_borrowed_amount: u128,
+    // Stable borrows with interest as of the timestamp
+    pub average_interest_rate: u128,
+    // Stable rate weighted by the user borrows
+    pub timestamp: u64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct StableBorrowData {
+    pub borrowed_amount: u128,
+    // User stable borrow with interest as of the timestamp
+    pub interest_rate: u128,
+    pub timestamp: u64,
+}
+
+impl Default for StableBorrowData {
+    fn default() -> Self {
+        StableBorrowData {
+            borrowed_amount: 0_u128,
+            interest_rate: 0_u128,
+            timestamp: 0_u64,
+        }
+    }
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct UserIsolatedDebtData {
+    pub isolated_collateral: Symbol,
+    pub debt_usd: u128,
+    // USD value of the borrow when it was taken, released as the borrow is repaid
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct TotalBorrowDataV1 {
+    // TotalBorrowData layout of storage version 1
     pub denom: Symbol,
     pub total_borrowed_amount: u128,
     pub expected_annual_interest_income: u128,
@@ -65,15 +216,16 @@ pub struct TotalBorrowData {
 
 #[contracttype]
 #[derive(Clone, Debug)]
-pub struct UserBorrowingInfo {
+pub struct UserBorrowingInfoV1 {
+    // user borrow of storage version 1, with an interest rate fixed at borrow time
     pub borrowed_amount: u128,
     pub average_interest_rate: u128,
     pub timestamp: u64,
 }
 
-impl Default for UserBorrowingInfo {
+impl Default for UserBorrowingInfoV1 {
     fn default() -> Self {
-        UserBorrowingInfo {
+        UserBorrowingInfoV1 {
             borrowed_amount: 0_u128,
             average_interest_rate: 0_u128,
             timestamp: 0_u64,
@@ -88,6 +240,128 @@ pub struct ReserveConfiguration {
     pub loan_to_value_ratio: u128,
     // LTV ratio
     pub liquidation_threshold: u128,
+    pub liquidation_bonus: u128,
+    // Extra collateral seized by the liquidator, in percent of the repaid value
+    pub supply_cap: u128,
+    // Max total reserves of the market in tokens, 0 means no cap
+    pub borrow_cap: u128,
+    // Max total borrow of the market in tokens, 0 means no cap
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct ReserveConfigurationV0 {
+    // ReserveConfiguration layout of storage version 0
+    pub denom: Symbol,
+    pub loan_to_value_ratio: u128,
+    pub liquidation_threshold: u128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct MarketFlags {
+    pub deposits_frozen: bool,
+    pub borrows_frozen: bool,
+    // Frozen markets still accept repay, redeem and liquidation so positions can be unwound
+    pub paused: bool,
+    // Paused markets reject every user operation
+}
+
+impl Default for MarketFlags {
+    fn default() -> Self {
+        MarketFlags {
+            deposits_frozen: false,
+            borrows_frozen: false,
+            paused: false,
+        }
+    }
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct BadDebtData {
+    pub total_bad_debt: u128,
+    // All debt written off in the market after its borrowers ran out of collateral
+    pub outstanding_bad_debt: u128,
+    // Part neither covered by the protocol reserves nor socialized among the depositors
+}
+
+impl Default for BadDebtData {
+    fn default() -> Self {
+        BadDebtData {
+            total_bad_debt: 0_u128,
+            outstanding_bad_debt: 0_u128,
+        }
+    }
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct EModeCategory {
+    pub label: Symbol,
+    pub loan_to_value_ratio: u128,
+    pub liquidation_threshold: u128,
+    // Replace the market ratios for the category collateral of the users in the category
+    pub price_source: Option<Symbol>,
+    // Market whose price is used for every asset of the category, the asset prices otherwise
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct AccountMarketSnapshot {
+    pub denom: Symbol,
+    pub deposit: u128,
+    pub mm_token_balance: u128,
+    pub borrowed_amount: u128,
+    // Variable and stable borrow with interest
+    pub use_as_collateral: bool,
+    pub deposit_usd: u128,
+    pub borrowed_usd: u128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct AccountSnapshot {
+    pub markets: Vec<AccountMarketSnapshot>,
+    // Markets where the user has a deposit or a borrow
+    pub deposited_usd: u128,
+    pub collateral_usd: u128,
+    pub borrowed_usd: u128,
+    pub max_allowed_borrow_usd: u128,
+    pub liquidation_threshold: u128,
+    // Collateral weighted liquidation threshold in percent
+    pub utilization_rate: u128,
+    pub health_factor: u128,
+    // Collateral weighted by the liquidation thresholds over the borrows in percent,
+    // the position is liquidated below 100%, u128::MAX without borrows
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct MarketOverview {
+    pub token_info: TokenInfo,
+    pub reserve_configuration: ReserveConfiguration,
+    // LTV, liquidation threshold and bonus, supply and borrow caps
+    pub market_flags: MarketFlags,
+    pub interest_rate: u128,
+    pub stable_interest_rate: u128,
+    // 0 when the market has no stable borrows
+    pub liquidity_rate: u128,
+    pub utilization_rate: u128,
+    pub total_reserves: u128,
+    pub total_borrowed: u128,
+    pub price: u128,
+    // USD price with USD_DECIMALS, 0 when the market has no price yet
+    pub price_timestamp: u64,
+    pub mm_token_price: u128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct TokenPrice {
+    pub price: u128,
+    // USD price with USD_DECIMALS
+    pub timestamp: u64,
 }
 
 #[contracttype]
@@ -9,7 +9,9 @@ use std::println;
 use soroban_sdk::arbitrary::std::dbg;
 use soroban_sdk::testutils::{Address as _, Events, Ledger, LedgerInfo};
 use soroban_sdk::token::Interface;
-use soroban_sdk::{symbol_short, token, Address, Env, IntoVal, String, Symbol};
+use soroban_sdk::{
+    symbol_short, token, Address, Bytes, Env, IntoVal, Map, String, Symbol, TryFromVal,
+};
 use token::Client;
 
 mod token_contract {
@@ -22,6 +24,16 @@ mod vault_contract {
     );
 }
 
+mod mm_token_contract {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/mm_token.wasm"
+    );
+}
+
+mod lending_contract {
+    soroban_sdk::contractimport!(file = "../../target/wasm32-unknown-unknown/release/lending.wasm");
+}
+
 fn create_custom_token<'a>(
     env: &Env,
     admin: &Address,
@@ -122,10 +134,10 @@ pub fn success_deposit_of_one_token_setup() -> (LendingContractClient<'static>,
     );
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("xlm"),
         &token_xlm.address,
         &symbol_short!("Xlm"),
-        &TOKENS_DECIMALS,
         &LTV_XLM,
         &LIQUIDATION_THRESHOLD_XLM,
         &MIN_INTEREST_RATE,
@@ -133,12 +145,14 @@ pub fn success_deposit_of_one_token_setup() -> (LendingContractClient<'static>,
         &RATE_GROWTH_FACTOR,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    // the tests move the ledger without refreshing the prices
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("xlm"), &0);
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("eth"),
         &token_eth.address,
         &symbol_short!("Eth"),
-        &TOKENS_DECIMALS,
         &LTV_ETH,
         &LIQUIDATION_THRESHOLD_ETH,
         &MIN_INTEREST_RATE,
@@ -146,6 +160,7 @@ pub fn success_deposit_of_one_token_setup() -> (LendingContractClient<'static>,
         &RATE_GROWTH_FACTOR,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("eth"), &0);
 
     // Funding contract
     // token_xlm.transfer(&admin, &contract_address, &i128::try_from(CONTRACT_RESERVES).unwrap());
@@ -155,8 +170,8 @@ pub fn success_deposit_of_one_token_setup() -> (LendingContractClient<'static>,
         &i128::try_from(CONTRACT_RESERVES).unwrap(),
     );
 
-    // contract_client.update_price(&symbol_short!("xlm"), &PRICE_XLM);
-    // contract_client.update_price(&symbol_short!("eth"), &PRICE_ETH);
+    // contract_client.update_price(&admin, &symbol_short!("xlm"), &PRICE_XLM);
+    // contract_client.update_price(&admin, &symbol_short!("eth"), &PRICE_ETH);
 
     lending_contract_client.deposit(&user1, &symbol_short!("eth"), &FIRST_DEPOSIT_AMOUNT_ETH);
     // contract_client.deposit(&admin, &symbol_short!("eth"), &(FIRST_DEPOSIT_AMOUNT_ETH * 15 / 10));
@@ -281,10 +296,10 @@ pub fn success_deposit_of_diff_token_with_prices(
     );
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("xlm"),
         &token_xlm.address,
         &symbol_short!("Xlm"),
-        &TOKENS_DECIMALS,
         &LTV_XLM,
         &LIQUIDATION_THRESHOLD_XLM,
         &MIN_INTEREST_RATE,
@@ -292,12 +307,14 @@ pub fn success_deposit_of_diff_token_with_prices(
         &RATE_GROWTH_FACTOR,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    // the tests move the ledger without refreshing the prices
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("xlm"), &0);
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("eth"),
         &token_eth.address,
         &symbol_short!("Eth"),
-        &TOKENS_DECIMALS,
         &LTV_ETH,
         &LIQUIDATION_THRESHOLD_ETH,
         &MIN_INTEREST_RATE,
@@ -305,6 +322,7 @@ pub fn success_deposit_of_diff_token_with_prices(
         &RATE_GROWTH_FACTOR,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("eth"), &0);
 
     // Funding vault contract
     token_xlm.transfer(
@@ -318,8 +336,8 @@ pub fn success_deposit_of_diff_token_with_prices(
         &i128::try_from(CONTRACT_RESERVES_ETH).unwrap(),
     );
 
-    lending_contract_client.update_price(&symbol_short!("xlm"), &PRICE_XLM);
-    lending_contract_client.update_price(&symbol_short!("eth"), &PRICE_ETH);
+    lending_contract_client.update_price(&admin, &symbol_short!("xlm"), &PRICE_XLM);
+    lending_contract_client.update_price(&admin, &symbol_short!("eth"), &PRICE_ETH);
 
     let get_price_xlm: u128 = lending_contract_client.get_price(&symbol_short!("xlm"));
     let get_price_eth: u128 = lending_contract_client.get_price(&symbol_short!("eth"));
@@ -467,10 +485,10 @@ pub fn success_borrow_setup() -> (
     );
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("xlm"),
         &token_xlm.address,
         &symbol_short!("xlm"),
-        &TOKENS_DECIMALS,
         &LTV_XLM,
         &LIQUIDATION_THRESHOLD_XLM,
         &MIN_INTEREST_RATE,
@@ -478,12 +496,14 @@ pub fn success_borrow_setup() -> (
         &RATE_GROWTH_FACTOR,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    // the tests move the ledger without refreshing the prices
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("xlm"), &0);
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("eth"),
         &token_eth.address,
         &symbol_short!("Eth"),
-        &TOKENS_DECIMALS,
         &LTV_ETH,
         &LIQUIDATION_THRESHOLD_ETH,
         &MIN_INTEREST_RATE,
@@ -491,6 +511,7 @@ pub fn success_borrow_setup() -> (
         &RATE_GROWTH_FACTOR,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("eth"), &0);
 
     // Funding vault contract
     token_xlm.transfer(
@@ -504,8 +525,8 @@ pub fn success_borrow_setup() -> (
         &i128::try_from(CONTRACT_RESERVES_ETH).unwrap(),
     );
 
-    lending_contract_client.update_price(&symbol_short!("xlm"), &PRICE_XLM);
-    lending_contract_client.update_price(&symbol_short!("eth"), &PRICE_ETH);
+    lending_contract_client.update_price(&admin, &symbol_short!("xlm"), &PRICE_XLM);
+    lending_contract_client.update_price(&admin, &symbol_short!("eth"), &PRICE_ETH);
 
     let get_price_eth: u128 = lending_contract_client.get_price(&symbol_short!("eth"));
     let get_price_xlm: u128 = lending_contract_client.get_price(&symbol_short!("xlm"));
@@ -675,10 +696,10 @@ fn test_successful_deposits_of_one_token() {
     token_eth.mint(&admin, &i128::try_from(CONTRACT_RESERVES * 100).unwrap());
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("xlm"),
         &token_xlm.address,
         &symbol_short!("Xlm"),
-        &TOKENS_DECIMALS,
         &LTV_XLM,
         &LIQUIDATION_THRESHOLD_XLM,
         &5000000000000000000,
@@ -686,12 +707,14 @@ fn test_successful_deposits_of_one_token() {
         &100000000000000000000,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    // the tests move the ledger without refreshing the prices
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("xlm"), &0);
 
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("eth"),
         &token_eth.address,
         &symbol_short!("Eth"),
-        &TOKENS_DECIMALS,
         &LTV_ETH,
         &LIQUIDATION_THRESHOLD_ETH,
         &MIN_INTEREST_RATE,
@@ -699,6 +722,7 @@ fn test_successful_deposits_of_one_token() {
         &RATE_GROWTH_FACTOR,
         &OPTIMAL_UTILIZATION_RATIO,
     );
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("eth"), &0);
 
     // Funding contract
     token_xlm.transfer(
@@ -712,8 +736,8 @@ fn test_successful_deposits_of_one_token() {
         &i128::try_from(CONTRACT_RESERVES / 100).unwrap(),
     );
 
-    lending_contract_client.update_price(&symbol_short!("xlm"), &PRICE_XLM);
-    lending_contract_client.update_price(&symbol_short!("eth"), &PRICE_ETH);
+    lending_contract_client.update_price(&admin, &symbol_short!("xlm"), &PRICE_XLM);
+    lending_contract_client.update_price(&admin, &symbol_short!("eth"), &PRICE_ETH);
 
     lending_contract_client.deposit(&user1, &symbol_short!("eth"), &FIRST_DEPOSIT_AMOUNT);
     lending_contract_client.deposit(
@@ -816,7 +840,8 @@ fn test_get_liquidity_rate() {
     let get_liquidity_rate_eth: u128 = contract_client.get_liquidity_rate(&symbol_short!("eth"));
     let get_liquidity_rate_xlm: u128 = contract_client.get_liquidity_rate(&symbol_short!("xlm"));
 
-    assert_eq!(get_liquidity_rate_xlm, 1153846153846153846); // ~1.154%
+    // 300 XLM * 12.21% borrow APY at 23.08% utilization / 1300 XLM
+    assert_eq!(get_liquidity_rate_xlm, 2818047115384615384); // ~2.818%
     assert_eq!(get_liquidity_rate_eth, 0);
 }
 
@@ -827,6 +852,7 @@ fn test_get_user_borrow_amount_with_interest() {
     const BORROW_AMOUNT_XLM: u128 = 200 * 10u128.pow(TOKENS_DECIMALS); // 200 XLM
 
     const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(3);
 
     // contract reserves: 1000 ETH and 1000 XLM
     // user deposited 200 ETH and 300 XLM
@@ -874,10 +900,14 @@ fn test_get_user_borrow_amount_with_interest() {
     user_borrow_amount_with_interest_xlm =
         contract_client.get_user_borrow_with_interest(&user, &symbol_short!("xlm"));
 
-    // 50 ETH + 5% borrow APY = 50 ETH + 2.5 ETH = 52.5 ETH
-    assert_eq!(user_borrow_amount_with_interest_eth, 52500000000000000000);
-    // 200 XLM + 5% borrow APY = 200 XLM + 10 XLM = 210 XLM
-    assert_eq!(user_borrow_amount_with_interest_xlm, 210000000000000000000);
+    // 50 ETH + 6.302% borrow APY at 4.167% utilization ~= 50 ETH + 3.151 ETH = 53.151 ETH
+    assert!(
+        user_borrow_amount_with_interest_eth.abs_diff(53151040625000000000) < ROUNDING_TOLERANCE
+    );
+    // 200 XLM + 9.808% borrow APY at 15.38% utilization ~= 200 XLM + 19.615 XLM = 219.615 XLM
+    assert!(
+        user_borrow_amount_with_interest_xlm.abs_diff(219615381250000000000) < ROUNDING_TOLERANCE
+    );
 
     // let users_with_borrow = contract_client.GetAllUsersWithBorrows();
 
@@ -942,8 +972,9 @@ fn test_success_borrow_one_token() {
     let user_borrowed_balance: u128 =
         contract_client.get_user_borrow_with_interest(&user, &symbol_short!("xlm"));
 
+    // 300 XLM + 14.375% borrow APY at 30% utilization
     assert_ne!(user_borrowed_balance, BORROW_SECOND_TOKEN);
-    assert_eq!(user_borrowed_balance, BORROW_SECOND_TOKEN * 105 / 100);
+    assert!(user_borrowed_balance.abs_diff(BORROW_SECOND_TOKEN * 114375 / 100000) < 10u128.pow(3));
 }
 
 #[test]
@@ -993,7 +1024,8 @@ fn test_success_repay_more_than_needed() {
 
     let amount_to_repay_with_interest: u128 = user_borrow_amount_with_interest;
 
-    let underlying_balance_before_repay: i128 = token_eth.balance(&contract_client.address);
+    let underlying_balance_before_repay: i128 =
+        token_eth.balance(&contract_client.get_vault_contract());
 
     contract_client.repay(
         &user,
@@ -1001,7 +1033,8 @@ fn test_success_repay_more_than_needed() {
         &(amount_to_repay_with_interest * 2),
     );
 
-    let underlying_balance_after_repay: i128 = token_eth.balance(&contract_client.address);
+    let underlying_balance_after_repay: i128 =
+        token_eth.balance(&contract_client.get_vault_contract());
 
     // paying only what we supposed to, not twice as much
     assert_eq!(
@@ -1031,9 +1064,10 @@ fn test_success_repay_by_parts() {
     let borrow_info_before_first_repay: u128 =
         contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
 
-    assert_eq!(
-        borrow_info_before_first_repay,
-        BORROW_AMOUNT_ETH * 105 / 100
+    // 50 ETH + 6.302% borrow APY at 4.167% utilization
+    assert!(
+        borrow_info_before_first_repay.abs_diff(BORROW_AMOUNT_ETH * 10630208125 / 10000000000)
+            < 10u128.pow(3)
     );
 
     contract_client.repay(
@@ -1393,10 +1427,10 @@ fn test_budget() {
 
     env.budget().reset_unlimited();
     lending_contract_client.add_markets(
+        &admin,
         &symbol_short!("xlm"),
         &token_xlm.address,
         &symbol_short!("XLM"),
-        &7,
         &(75 * 10u128.pow(5)),
         &(80 * 10u128.pow(5)),
         &(5 * 10u128.pow(18)),
@@ -1404,6 +1438,8 @@ fn test_budget() {
         &(70 * 10u128.pow(18)),
         &(80 * 10u128.pow(5)),
     );
+    // the tests move the ledger without refreshing the prices
+    lending_contract_client.set_max_price_age(&admin, &symbol_short!("xlm"), &0);
     println!("CPU costs");
     println!(
         "      add_markets: {:?}",
@@ -1421,3 +1457,3250 @@ fn test_tvl() {
 
     assert_eq!(contract_client.get_tvl(), 2_413_000 * 10u128.pow(8)); // 2_313_000 USD
 }
+
+#[test]
+#[should_panic(expected = "Error(Contract, #1)")]
+fn test_initialize_twice() {
+    let (env, contract_client, admin, _user) = success_deposit_of_diff_token_with_prices();
+
+    let vault_contract_address: Address = contract_client.get_vault_contract();
+
+    contract_client.initialize(&admin, &Address::random(&env), &vault_contract_address);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #3)")]
+fn test_deposit_unknown_denom() {
+    let (_env, contract_client, _admin, user) = success_deposit_of_diff_token_with_prices();
+
+    contract_client.deposit(&user, &symbol_short!("btc"), &(10 * 10u128.pow(18)));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #6)")]
+fn test_deposit_zero_amount() {
+    let (_env, contract_client, _admin, user) = success_deposit_of_diff_token_with_prices();
+
+    contract_client.deposit(&user, &symbol_short!("eth"), &0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #8)")]
+fn test_redeem_more_than_deposited() {
+    // user deposited 200 ETH
+    let (_env, contract_client, _admin, user) = success_deposit_of_diff_token_with_prices();
+
+    contract_client.redeem(&user, &symbol_short!("eth"), &(201 * 10u128.pow(18)));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #9)")]
+fn test_borrow_more_than_available() {
+    // user deposited 200 ETH and 300 XLM as collateral
+    let (_env, contract_client, _admin, user) =
+        success_deposit_as_collateral_of_diff_token_with_prices();
+
+    contract_client.borrow(&user, &symbol_short!("eth"), &(200 * 10u128.pow(18)));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #9)")]
+fn test_redeem_above_ltv() {
+    // user deposited 200 ETH and 300 XLM as collateral and borrowed 50 ETH
+    let (_env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    // 50 ETH of collateral left can't back the borrow
+    contract_client.redeem(&user, &symbol_short!("eth"), &(150 * 10u128.pow(18)));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #9)")]
+fn test_redeem_to_above_ltv() {
+    // user deposited 200 ETH and 300 XLM as collateral and borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let recipient = Address::random(&env);
+
+    contract_client.redeem_to(
+        &user,
+        &symbol_short!("eth"),
+        &(150 * 10u128.pow(18)),
+        &recipient,
+    );
+}
+
+#[test]
+fn test_redeem_non_collateral_deposit_above_ltv() {
+    const DEPOSIT_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH, 20_000$
+    const BORROW_AMOUNT_XLM: u128 = 1_000 * 10u128.pow(18); // 1_000 XLM, 10_000$
+    const DEPOSIT_AMOUNT_XLM: u128 = 500 * 10u128.pow(18); // 500 XLM
+    const DROPPED_PRICE_ETH: u128 = 1000 * 10u128.pow(8); // 1000$
+
+    let (env, contract_client, admin, _user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let trader = Address::random(&env);
+    token_eth.mint(&trader, &(DEPOSIT_AMOUNT_ETH as i128));
+    token_xlm.mint(&trader, &(DEPOSIT_AMOUNT_XLM as i128));
+
+    contract_client.execute_actions(
+        &trader,
+        &soroban_sdk::vec![
+            &env,
+            Action::Deposit(symbol_short!("eth"), DEPOSIT_AMOUNT_ETH),
+            Action::EnableCollateral(symbol_short!("eth")),
+            Action::Borrow(symbol_short!("xlm"), BORROW_AMOUNT_XLM),
+            Action::Deposit(symbol_short!("xlm"), DEPOSIT_AMOUNT_XLM),
+        ],
+    );
+
+    // the borrow is above the LTV of the eth collateral now
+    contract_client.update_price(&admin, &symbol_short!("eth"), &DROPPED_PRICE_ETH);
+    assert!(
+        contract_client.get_user_borrowed_usd(&trader)
+            > contract_client.get_user_max_allowed_borrow_usd(&trader)
+    );
+
+    // the xlm deposit doesn't back the borrow
+    contract_client.redeem(&trader, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+
+    assert_eq!(
+        token_xlm.balance(&trader) as u128,
+        BORROW_AMOUNT_XLM + DEPOSIT_AMOUNT_XLM
+    );
+}
+
+#[test]
+fn test_borrow_events() {
+    const BORROW_AMOUNT_XLM: u128 = 100 * 10u128.pow(18); // 100 XLM
+
+    // user deposited 200 ETH and 300 XLM as collateral
+    let (env, contract_client, _admin, user) =
+        success_deposit_as_collateral_of_diff_token_with_prices();
+
+    contract_client.borrow(&user, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+
+    let (_, topics, data) = env
+        .events()
+        .all()
+        .iter()
+        .filter(|event| event.0 == contract_client.address)
+        .last()
+        .unwrap();
+
+    let action = Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap();
+    let event_user = Address::try_from_val(&env, &topics.get(1).unwrap()).unwrap();
+    let denom = Symbol::try_from_val(&env, &topics.get(2).unwrap()).unwrap();
+    let (recipient, amount, borrowed_amount, interest_rate, interest_rate_mode) =
+        <(Address, u128, u128, u128, InterestRateMode)>::try_from_val(&env, &data).unwrap();
+
+    assert_eq!(action, symbol_short!("borrow"));
+    assert_eq!(event_user, user);
+    assert_eq!(recipient, user);
+    assert_eq!(denom, symbol_short!("xlm"));
+    assert_eq!(amount, BORROW_AMOUNT_XLM);
+    assert_eq!(borrowed_amount, BORROW_AMOUNT_XLM);
+    assert_eq!(
+        interest_rate,
+        contract_client.get_interest_rate(&symbol_short!("xlm"))
+    );
+    assert_eq!(interest_rate_mode, InterestRateMode::Variable);
+}
+
+#[test]
+fn test_repay_for_events() {
+    const REPAY_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let payer = Address::random(&env);
+    token_eth.mint(&payer, &(REPAY_AMOUNT_ETH as i128));
+
+    contract_client.repay_for(&payer, &user, &symbol_short!("eth"), &REPAY_AMOUNT_ETH);
+
+    let (_, topics, data) = env
+        .events()
+        .all()
+        .iter()
+        .filter(|event| event.0 == contract_client.address)
+        .last()
+        .unwrap();
+
+    let action = Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap();
+    let event_user = Address::try_from_val(&env, &topics.get(1).unwrap()).unwrap();
+    let (event_payer, amount, borrowed_amount) =
+        <(Address, u128, u128)>::try_from_val(&env, &data).unwrap();
+
+    assert_eq!(action, symbol_short!("repay"));
+    assert_eq!(event_user, user);
+    assert_eq!(event_payer, payer);
+    assert_eq!(amount, REPAY_AMOUNT_ETH);
+    assert_eq!(
+        borrowed_amount,
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"))
+    );
+}
+
+#[test]
+fn test_set_close_factor_events() {
+    const CLOSE_FACTOR: u128 = 60 * 10u128.pow(5); // 60%
+
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_close_factor(&admin, &CLOSE_FACTOR);
+
+    let (_, topics, data) = env
+        .events()
+        .all()
+        .iter()
+        .filter(|event| event.0 == contract_client.address)
+        .last()
+        .unwrap();
+
+    let action = Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap();
+
+    assert_eq!(action, Symbol::new(&env, "set_close_factor"));
+    assert_eq!(u128::try_from_val(&env, &data).unwrap(), CLOSE_FACTOR);
+}
+
+mod flash_loan_receiver {
+    use soroban_sdk::{
+        contract, contracterror, contractimpl, contracttype, panic_with_error, token, Address,
+        Bytes, Env,
+    };
+
+    #[contracterror]
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+    #[repr(u32)]
+    pub enum ReceiverError {
+        UnknownInitiator = 1,
+    }
+
+    #[contracttype]
+    pub enum DataKey {
+        LendingContract,
+        Owner,
+    }
+
+    #[contract]
+    pub struct FlashLoanReceiver;
+
+    #[contractimpl]
+    impl FlashLoanReceiver {
+        pub fn initialize(env: Env, lending_contract: Address, owner: Address) {
+            env.storage()
+                .instance()
+                .set(&DataKey::LendingContract, &lending_contract);
+            env.storage().instance().set(&DataKey::Owner, &owner);
+        }
+
+        pub fn execute_operation(
+            env: Env,
+            initiator: Address,
+            token: Address,
+            amount: u128,
+            fee: u128,
+            _params: Bytes,
+        ) {
+            // the fee is paid from the receiver balance, so only the owner can start a loan
+            let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+            if initiator != owner {
+                panic_with_error!(&env, ReceiverError::UnknownInitiator);
+            }
+
+            let lending_contract: Address = env
+                .storage()
+                .instance()
+                .get(&DataKey::LendingContract)
+                .unwrap();
+
+            token::Client::new(&env, &token).approve(
+                &env.current_contract_address(),
+                &lending_contract,
+                &((amount + fee) as i128),
+                &(env.ledger().sequence() + 1),
+            );
+        }
+    }
+}
+
+mod non_repaying_flash_loan_receiver {
+    use soroban_sdk::{contract, contractimpl, Address, Bytes, Env};
+
+    #[contract]
+    pub struct NonRepayingFlashLoanReceiver;
+
+    #[contractimpl]
+    impl NonRepayingFlashLoanReceiver {
+        pub fn execute_operation(
+            _env: Env,
+            _initiator: Address,
+            _token: Address,
+            _amount: u128,
+            _fee: u128,
+            _params: Bytes,
+        ) {
+        }
+    }
+}
+
+#[test]
+fn test_flash_loan() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const FLASH_LOAN_AMOUNT_ETH: u128 = 600 * 10u128.pow(TOKENS_DECIMALS); // 600 ETH
+    const FLASH_LOAN_FEE: u128 = 10u128.pow(5); // 1%
+    const FEE_ETH: u128 = 6 * 10u128.pow(TOKENS_DECIMALS); // 6 ETH
+
+    // contract reserves: 1000 ETH
+    // user deposited 200 ETH and 300 XLM
+    // user borrowed 50 ETH
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let receiver_address = env.register_contract(None, flash_loan_receiver::FlashLoanReceiver);
+    let receiver_client =
+        flash_loan_receiver::FlashLoanReceiverClient::new(&env, &receiver_address);
+    receiver_client.initialize(&contract_client.address, &user);
+
+    contract_client.set_flash_loan_fee(&admin, &FLASH_LOAN_FEE);
+    assert_eq!(contract_client.get_flash_loan_fee(), FLASH_LOAN_FEE);
+
+    // the receiver pays the fee from its own balance
+    token_eth.mint(&receiver_address, &(FEE_ETH as i128));
+
+    let vault_contract_address: Address = contract_client.get_vault_contract();
+    let vault_balance_before: i128 = token_eth.balance(&vault_contract_address);
+    let user_deposit_before: u128 = contract_client.get_deposit(&user, &symbol_short!("eth"));
+
+    contract_client.flash_loan(
+        &user,
+        &receiver_address,
+        &symbol_short!("eth"),
+        &FLASH_LOAN_AMOUNT_ETH,
+        &Bytes::new(&env),
+    );
+    assert_eq!(env.auths()[0].0, user);
+
+    assert_eq!(
+        token_eth.balance(&vault_contract_address),
+        vault_balance_before + FEE_ETH as i128
+    );
+    assert_eq!(token_eth.balance(&receiver_address), 0);
+
+    // total reserves ~1200 ETH, so the user's 200 ETH deposit earns ~1/6 of the fee
+    let user_deposit_after: u128 = contract_client.get_deposit(&user, &symbol_short!("eth"));
+    let user_income: u128 = user_deposit_after - user_deposit_before;
+    assert!(user_income > FEE_ETH / 6 * 99 / 100 && user_income <= FEE_ETH / 6);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #14)")]
+fn test_flash_loan_not_repaid() {
+    const FLASH_LOAN_AMOUNT_ETH: u128 = 600 * 10u128.pow(18); // 600 ETH
+
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let receiver_address = env.register_contract(
+        None,
+        non_repaying_flash_loan_receiver::NonRepayingFlashLoanReceiver,
+    );
+
+    contract_client.flash_loan(
+        &user,
+        &receiver_address,
+        &symbol_short!("eth"),
+        &FLASH_LOAN_AMOUNT_ETH,
+        &Bytes::new(&env),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #1)")]
+fn test_flash_loan_started_by_third_party() {
+    const FLASH_LOAN_AMOUNT_ETH: u128 = 600 * 10u128.pow(18); // 600 ETH
+    const FEE_ETH: u128 = 6 * 10u128.pow(18); // 6 ETH
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let receiver_address = env.register_contract(None, flash_loan_receiver::FlashLoanReceiver);
+    let receiver_client =
+        flash_loan_receiver::FlashLoanReceiverClient::new(&env, &receiver_address);
+    receiver_client.initialize(&contract_client.address, &user);
+
+    contract_client.set_flash_loan_fee(&admin, &10u128.pow(5));
+    token_eth.mint(&receiver_address, &(FEE_ETH as i128));
+
+    // a third party can't spend the receiver balance on fees
+    let attacker = Address::random(&env);
+    contract_client.flash_loan(
+        &attacker,
+        &receiver_address,
+        &symbol_short!("eth"),
+        &FLASH_LOAN_AMOUNT_ETH,
+        &Bytes::new(&env),
+    );
+}
+
+#[test]
+fn test_partial_liquidation() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const BORROW_AMOUNT_ETH: u128 = 121 * 10u128.pow(TOKENS_DECIMALS); // 121 ETH
+    const LIQUIDATOR_BALANCE_ETH: u128 = 10_000 * 10u128.pow(TOKENS_DECIMALS); // 10_000 ETH
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(10);
+
+    // user deposited 200 ETH and 300 XLM
+    // user borrowed 50 ETH + 121 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    // any account can liquidate
+    let liquidator = Address::random(&env);
+    token_eth.mint(&liquidator, &(LIQUIDATOR_BALANCE_ETH as i128));
+
+    contract_client.borrow(&user, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp = YEAR_IN_SECONDS + 10000; // after a year
+    env.ledger().set(ledger_info);
+
+    let user_utilization_rate_before: u128 = contract_client.get_user_utilization_rate(&user);
+    assert!(user_utilization_rate_before > contract_client.get_user_liquidation_threshold(&user));
+
+    let user_borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    let user_deposit_eth_before: u128 = contract_client.get_deposit(&user, &symbol_short!("eth"));
+
+    // asking for the whole borrow, but only the close factor (50%) is repaid
+    // the seized collateral is received as mm token shares
+    contract_client.liquidation(
+        &liquidator,
+        &user,
+        &symbol_short!("eth"),
+        &symbol_short!("eth"),
+        &user_borrow_before,
+        &true,
+    );
+
+    let repaid_amount: u128 = user_borrow_before / 2;
+    // collateral of the same value plus 5% liquidation bonus
+    let seized_amount: u128 = repaid_amount * 105 / 100;
+
+    let user_borrow_after: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    assert!(user_borrow_after.abs_diff(user_borrow_before - repaid_amount) < ROUNDING_TOLERANCE);
+
+    let user_deposit_eth_after: u128 = contract_client.get_deposit(&user, &symbol_short!("eth"));
+    assert!(
+        user_deposit_eth_after.abs_diff(user_deposit_eth_before - seized_amount)
+            < ROUNDING_TOLERANCE
+    );
+
+    // the rest of the position is left intact
+    assert_eq!(
+        contract_client.get_deposit(&user, &symbol_short!("xlm")),
+        300_000000000000000000
+    );
+
+    assert_eq!(
+        token_eth.balance(&liquidator) as u128,
+        LIQUIDATOR_BALANCE_ETH - repaid_amount
+    );
+    assert!(
+        contract_client
+            .get_deposit(&liquidator, &symbol_short!("eth"))
+            .abs_diff(seized_amount)
+            < ROUNDING_TOLERANCE
+    );
+
+    assert!(contract_client.get_user_utilization_rate(&user) < user_utilization_rate_before);
+}
+
+#[test]
+fn test_liquidation_limited_by_collateral() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const BORROW_AMOUNT_ETH: u128 = 121 * 10u128.pow(TOKENS_DECIMALS); // 121 ETH
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(10);
+
+    let (env, contract_client, _admin, user, liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.borrow(&user, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp = 2 * YEAR_IN_SECONDS + 10000; // after 2 years
+    env.ledger().set(ledger_info);
+
+    let user_borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    let liquidator_balance_xlm_before: u128 = token_xlm.balance(&liquidator) as u128;
+
+    // seizing XLM collateral, 300 XLM * 10$ = 3_000$ only covers a part of the close factor
+    // the seized collateral is received as tokens
+    contract_client.liquidation(
+        &liquidator,
+        &user,
+        &symbol_short!("eth"),
+        &symbol_short!("xlm"),
+        &0,
+        &false,
+    );
+
+    assert_eq!(contract_client.get_deposit(&user, &symbol_short!("xlm")), 0);
+    assert_eq!(
+        contract_client.get_deposit(&liquidator, &symbol_short!("xlm")),
+        0
+    );
+    assert!(
+        (token_xlm.balance(&liquidator) as u128 - liquidator_balance_xlm_before)
+            .abs_diff(300_000000000000000000)
+            < ROUNDING_TOLERANCE
+    );
+
+    // 3_000$ / 1.05 / 2000$ ~= 1.428571 ETH
+    let user_borrow_after: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    assert!(
+        (user_borrow_before - user_borrow_after).abs_diff(1_428571428571428571)
+            < ROUNDING_TOLERANCE
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #10)")]
+fn test_liquidation_of_healthy_user() {
+    // user borrowed 50 ETH against 200 ETH and 300 XLM
+    let (_env, contract_client, _admin, user, liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.liquidation(
+        &liquidator,
+        &user,
+        &symbol_short!("eth"),
+        &symbol_short!("eth"),
+        &0,
+        &true,
+    );
+}
+
+#[test]
+fn test_bad_debt_socialization() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const DEPOSIT_AMOUNT_XLM: u128 = 1000 * 10u128.pow(TOKENS_DECIMALS); // 1000 XLM
+    const BORROW_AMOUNT_ETH: u128 = 3 * 10u128.pow(TOKENS_DECIMALS); // 3 ETH
+    const RESERVE_FACTOR: u128 = 10 * 10u128.pow(5); // 10%
+    const CRASHED_PRICE_XLM: u128 = 2 * 10u128.pow(8); // 2$
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(10);
+
+    let (env, contract_client, admin, user, liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_reserve_factor(&admin, &symbol_short!("eth"), &RESERVE_FACTOR);
+
+    // 1000 XLM * 10$ = 10_000$ of collateral backs 3 ETH * 2000$ = 6_000$ of debt
+    let borrower = Address::random(&env);
+    token_xlm.mint(&borrower, &(DEPOSIT_AMOUNT_XLM as i128));
+    contract_client.deposit(&borrower, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("xlm"));
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    // the collateral is worth 2_000$ only
+    contract_client.update_price(&admin, &symbol_short!("xlm"), &CRASHED_PRICE_XLM);
+
+    let protocol_reserves_before: u128 =
+        contract_client.get_protocol_reserves(&symbol_short!("eth"));
+    let user_deposit_before: u128 = contract_client.get_deposit(&user, &symbol_short!("eth"));
+    let borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("eth"));
+
+    // seizing all the collateral repays 2_000$ / 1.05 / 2000$ ~= 0.952381 ETH
+    contract_client.liquidation(
+        &liquidator,
+        &borrower,
+        &symbol_short!("eth"),
+        &symbol_short!("xlm"),
+        &0,
+        &false,
+    );
+
+    // the rest of the borrow is written off
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("eth")),
+        0
+    );
+
+    let bad_debt = contract_client.get_bad_debt(&symbol_short!("eth"));
+    assert!(
+        bad_debt
+            .total_bad_debt
+            .abs_diff(borrow_before - 952380952380952380)
+            < ROUNDING_TOLERANCE
+    );
+
+    // covered by the protocol reserves first
+    assert!(contract_client.get_protocol_reserves(&symbol_short!("eth")) < ROUNDING_TOLERANCE);
+
+    // everything left after the reserves is socialized, the mm token price drops below one
+    assert_eq!(bad_debt.outstanding_bad_debt, 0);
+    assert!(
+        contract_client.get_mm_token_price(&symbol_short!("eth")) < 10u128.pow(TOKENS_DECIMALS)
+    );
+
+    // the socialized part is shared among the depositors by their mm token balances
+    let socialized: u128 = bad_debt.total_bad_debt - protocol_reserves_before;
+    let expected_user_deposit: u128 = user_deposit_before
+        - socialized
+            * (contract_client.get_mm_token_balance(&user, &symbol_short!("eth")) / 10u128.pow(9))
+            / (contract_client.get_total_mm_token_supply(&symbol_short!("eth")) / 10u128.pow(9));
+    assert!(
+        contract_client
+            .get_deposit(&user, &symbol_short!("eth"))
+            .abs_diff(expected_user_deposit)
+            < ROUNDING_TOLERANCE
+    );
+}
+
+#[test]
+fn test_no_write_off_with_remaining_deposits() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const DEPOSIT_AMOUNT_XLM: u128 = 1000 * 10u128.pow(TOKENS_DECIMALS); // 1000 XLM
+    const DEPOSIT_AMOUNT_ETH: u128 = 10u128.pow(TOKENS_DECIMALS); // 1 ETH
+    const BORROW_AMOUNT_ETH: u128 = 3 * 10u128.pow(TOKENS_DECIMALS); // 3 ETH
+    const CRASHED_PRICE_XLM: u128 = 2 * 10u128.pow(8); // 2$
+
+    let (env, contract_client, admin, _user, liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    // the ETH deposit isn't used as collateral
+    let borrower = Address::random(&env);
+    token_xlm.mint(&borrower, &(DEPOSIT_AMOUNT_XLM as i128));
+    token_eth.mint(&borrower, &(DEPOSIT_AMOUNT_ETH as i128));
+    contract_client.deposit(&borrower, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+    contract_client.deposit(&borrower, &symbol_short!("eth"), &DEPOSIT_AMOUNT_ETH);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("xlm"));
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    contract_client.update_price(&admin, &symbol_short!("xlm"), &CRASHED_PRICE_XLM);
+
+    contract_client.liquidation(
+        &liquidator,
+        &borrower,
+        &symbol_short!("eth"),
+        &symbol_short!("xlm"),
+        &0,
+        &false,
+    );
+
+    // the borrower still holds a deposit, nothing is written off
+    assert_eq!(
+        contract_client.get_deposit(&borrower, &symbol_short!("xlm")),
+        0
+    );
+    assert!(contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("eth")) > 0);
+    assert_eq!(
+        contract_client
+            .get_bad_debt(&symbol_short!("eth"))
+            .total_bad_debt,
+        0
+    );
+}
+
+#[test]
+fn test_write_off_bad_debt_of_other_denom() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const DEPOSIT_AMOUNT_XLM: u128 = 1000 * 10u128.pow(TOKENS_DECIMALS); // 1000 XLM
+    const BORROW_AMOUNT_ETH: u128 = 2 * 10u128.pow(TOKENS_DECIMALS); // 2 ETH
+    const BORROW_AMOUNT_XLM: u128 = 100 * 10u128.pow(TOKENS_DECIMALS); // 100 XLM
+    const CRASHED_PRICE_XLM: u128 = 2 * 10u128.pow(8); // 2$
+
+    let (env, contract_client, admin, _user, liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    // 1000 XLM * 10$ = 10_000$ of collateral backs 2 ETH * 2000$ + 100 XLM * 10$ = 5_000$ of debt
+    let borrower = Address::random(&env);
+    token_xlm.mint(&borrower, &(DEPOSIT_AMOUNT_XLM as i128));
+    contract_client.deposit(&borrower, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("xlm"));
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+    contract_client.borrow(&borrower, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+
+    contract_client.update_price(&admin, &symbol_short!("xlm"), &CRASHED_PRICE_XLM);
+
+    contract_client.liquidation(
+        &liquidator,
+        &borrower,
+        &symbol_short!("eth"),
+        &symbol_short!("xlm"),
+        &0,
+        &false,
+    );
+
+    // only the liquidated denom is written off
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("eth")),
+        0
+    );
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("xlm")),
+        BORROW_AMOUNT_XLM
+    );
+    assert_eq!(
+        contract_client
+            .get_bad_debt(&symbol_short!("xlm"))
+            .total_bad_debt,
+        0
+    );
+
+    contract_client.write_off_bad_debt(&borrower, &symbol_short!("xlm"));
+
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("xlm")),
+        0
+    );
+    assert_eq!(
+        contract_client
+            .get_bad_debt(&symbol_short!("xlm"))
+            .total_bad_debt,
+        BORROW_AMOUNT_XLM
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #44)")]
+fn test_write_off_bad_debt_with_remaining_deposits() {
+    // user borrowed 50 ETH against 200 ETH and 300 XLM
+    let (_env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.write_off_bad_debt(&user, &symbol_short!("eth"));
+}
+
+#[test]
+fn test_settle_bad_debt() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const DEPOSIT_AMOUNT_ETH: u128 = 10 * 10u128.pow(TOKENS_DECIMALS); // 10 ETH
+    const BORROW_AMOUNT_XLM: u128 = 800 * 10u128.pow(TOKENS_DECIMALS); // 800 XLM
+    const CRASHED_PRICE_ETH: u128 = 10u128.pow(8); // 1$
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(10);
+
+    let (env, contract_client, admin, user, liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    // the borrow exceeds the 300 XLM deposited, the rest of the liquidity is in the vault
+    let borrower = Address::random(&env);
+    token_eth.mint(&borrower, &(DEPOSIT_AMOUNT_ETH as i128));
+    contract_client.deposit(&borrower, &symbol_short!("eth"), &DEPOSIT_AMOUNT_ETH);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("eth"));
+    contract_client.borrow(&borrower, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+
+    contract_client.update_price(&admin, &symbol_short!("eth"), &CRASHED_PRICE_ETH);
+
+    contract_client.liquidation(
+        &liquidator,
+        &borrower,
+        &symbol_short!("xlm"),
+        &symbol_short!("eth"),
+        &0,
+        &false,
+    );
+
+    // the depositors are wiped out and the rest of the loss stays outstanding
+    let bad_debt = contract_client.get_bad_debt(&symbol_short!("xlm"));
+    assert!(contract_client.get_deposit(&user, &symbol_short!("xlm")) < ROUNDING_TOLERANCE);
+    assert!(
+        bad_debt
+            .outstanding_bad_debt
+            .abs_diff(bad_debt.total_bad_debt - 300 * 10u128.pow(TOKENS_DECIMALS))
+            < ROUNDING_TOLERANCE
+    );
+
+    let payer = Address::random(&env);
+    token_xlm.mint(&payer, &(BORROW_AMOUNT_XLM as i128));
+    let vault_balance_before: i128 = token_xlm.balance(&contract_client.get_vault_contract());
+
+    // the amount is capped at the outstanding bad debt
+    contract_client.settle_bad_debt(&payer, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+
+    assert_eq!(
+        contract_client
+            .get_bad_debt(&symbol_short!("xlm"))
+            .outstanding_bad_debt,
+        0
+    );
+    assert_eq!(
+        token_xlm.balance(&payer),
+        (BORROW_AMOUNT_XLM - bad_debt.outstanding_bad_debt) as i128
+    );
+    assert_eq!(
+        token_xlm.balance(&contract_client.get_vault_contract()),
+        vault_balance_before + bad_debt.outstanding_bad_debt as i128
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #6)")]
+fn test_settle_bad_debt_without_outstanding() {
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let payer = Address::random(&env);
+    token_xlm.mint(&payer, &1000);
+
+    contract_client.settle_bad_debt(&payer, &symbol_short!("xlm"), &1000);
+}
+
+mod mock_oracle {
+    use crate::oracle::{Asset, PriceData};
+    use soroban_sdk::{contract, contractimpl, Env};
+
+    pub const ORACLE_DECIMALS: u32 = 14;
+
+    #[contract]
+    pub struct MockOracle;
+
+    #[contractimpl]
+    impl MockOracle {
+        pub fn set_price(env: Env, asset: Asset, price: i128, timestamp: u64) {
+            env.storage()
+                .instance()
+                .set(&asset, &PriceData { price, timestamp });
+        }
+
+        pub fn decimals(_env: Env) -> u32 {
+            ORACLE_DECIMALS
+        }
+
+        pub fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+            env.storage().instance().get(&asset)
+        }
+    }
+}
+
+#[test]
+fn test_oracle_price() {
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let oracle_address = env.register_contract(None, mock_oracle::MockOracle);
+    let oracle_client = mock_oracle::MockOracleClient::new(&env, &oracle_address);
+
+    oracle_client.set_price(
+        &crate::oracle::Asset::Stellar(token_eth.address.clone()),
+        &(2500 * 10i128.pow(mock_oracle::ORACLE_DECIMALS)),
+        &env.ledger().timestamp(),
+    );
+
+    // admin-pushed price is used until the market has an oracle
+    assert_eq!(
+        contract_client.get_price(&symbol_short!("eth")),
+        2000_00000000
+    );
+
+    contract_client.set_price_oracle(&admin, &symbol_short!("eth"), &oracle_address);
+
+    assert_eq!(
+        contract_client.get_price_oracle(&symbol_short!("eth")),
+        Some(oracle_address)
+    );
+    assert_eq!(
+        contract_client.get_price(&symbol_short!("eth")),
+        2500_00000000
+    );
+
+    // 200 ETH * 2500 + 300 XLM * 10 == 503_000$
+    assert_eq!(
+        contract_client.get_user_collateral_usd(&user),
+        503_000_00000000
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #5)")]
+fn test_oracle_price_not_found() {
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let oracle_address = env.register_contract(None, mock_oracle::MockOracle);
+
+    contract_client.set_price_oracle(&admin, &symbol_short!("eth"), &oracle_address);
+
+    contract_client.get_price(&symbol_short!("eth"));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #18)")]
+fn test_stale_price() {
+    const MAX_PRICE_AGE: u64 = 3600; // 1 hour
+
+    // prices were updated at 0, user borrowed 50 ETH at 10000
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_max_price_age(&admin, &symbol_short!("eth"), &MAX_PRICE_AGE);
+
+    contract_client.get_user_collateral_usd(&user);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #18)")]
+fn test_default_max_price_age() {
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let token_usdt = create_custom_token(&env, &admin, "USDT", "usdt", &18);
+    contract_client.add_markets(
+        &admin,
+        &symbol_short!("usdt"),
+        &token_usdt.address,
+        &symbol_short!("Usdt"),
+        &(80 * 10u128.pow(5)),
+        &(85 * 10u128.pow(5)),
+        &(5 * 10u128.pow(18)),
+        &(30 * 10u128.pow(18)),
+        &(70 * 10u128.pow(18)),
+        &(80 * 10u128.pow(5)),
+    );
+    contract_client.update_price(&admin, &symbol_short!("usdt"), &100000000); // 1$
+
+    // a new market rejects prices older than 1 hour without setting a max price age
+    assert_eq!(
+        contract_client.get_max_price_age(&symbol_short!("usdt")),
+        3600
+    );
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += 3601;
+    env.ledger().set(ledger_info);
+
+    contract_client.get_price(&symbol_short!("usdt"));
+}
+
+#[test]
+fn test_refreshed_price() {
+    const MAX_PRICE_AGE: u64 = 3600; // 1 hour
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_max_price_age(&admin, &symbol_short!("eth"), &MAX_PRICE_AGE);
+    contract_client.set_max_price_age(&admin, &symbol_short!("xlm"), &MAX_PRICE_AGE);
+
+    contract_client.update_price(&admin, &symbol_short!("eth"), &2000_00000000);
+    contract_client.update_price(&admin, &symbol_short!("xlm"), &10_00000000);
+
+    let token_price: TokenPrice = contract_client.get_token_price(&symbol_short!("eth"));
+    assert_eq!(token_price.timestamp, env.ledger().timestamp());
+
+    // 200 ETH * 2000 + 300 XLM * 10 == 403_000$
+    assert_eq!(
+        contract_client.get_user_collateral_usd(&user),
+        403_000_00000000
+    );
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += MAX_PRICE_AGE + 1;
+    env.ledger().set(ledger_info);
+
+    let result = contract_client.try_get_available_to_borrow(&user, &symbol_short!("eth"));
+    assert!(result.is_err());
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #15)")]
+fn test_update_zero_price() {
+    let (_env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.update_price(&admin, &symbol_short!("eth"), &0);
+}
+
+#[test]
+fn test_reserve_factor() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const RESERVE_FACTOR: u128 = 10 * 10u128.pow(5); // 10%
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(6);
+
+    // user borrowed 50 ETH at 6.302%, so the expected annual interest income is 3.151 ETH
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_reserve_factor(&admin, &symbol_short!("eth"), &RESERVE_FACTOR);
+    assert_eq!(
+        contract_client.get_reserve_factor(&symbol_short!("eth")),
+        RESERVE_FACTOR
+    );
+
+    let liquidity_rate_before: u128 = contract_client.get_liquidity_rate(&symbol_short!("eth"));
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    // 3.151 ETH * 10% ~= 0.3151 ETH
+    let protocol_reserves: u128 = contract_client.get_protocol_reserves(&symbol_short!("eth"));
+    assert!(protocol_reserves.abs_diff(315104062500000000) < ROUNDING_TOLERANCE);
+
+    // the depositors get the rest of the interest
+    assert!(contract_client.get_liquidity_rate(&symbol_short!("eth")) < liquidity_rate_before);
+
+    let treasury = Address::random(&env);
+    let withdraw_amount: u128 = 2 * 10u128.pow(TOKENS_DECIMALS - 1); // 0.2 ETH
+
+    contract_client.withdraw_reserves(&admin, &symbol_short!("eth"), &withdraw_amount, &treasury);
+
+    assert_eq!(token_eth.balance(&treasury) as u128, withdraw_amount);
+    assert!(
+        contract_client
+            .get_protocol_reserves(&symbol_short!("eth"))
+            .abs_diff(protocol_reserves - withdraw_amount)
+            < ROUNDING_TOLERANCE
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #19)")]
+fn test_withdraw_more_than_reserves() {
+    const RESERVE_FACTOR: u128 = 10 * 10u128.pow(5); // 10%
+
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_reserve_factor(&admin, &symbol_short!("eth"), &RESERVE_FACTOR);
+
+    let treasury = Address::random(&env);
+
+    // no time has passed, nothing is accrued yet
+    contract_client.withdraw_reserves(&admin, &symbol_short!("eth"), &1, &treasury);
+}
+
+#[test]
+fn test_supply_and_borrow_caps() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const LTV_ETH: u128 = 85 * 10u128.pow(5); // 85%
+    const LIQUIDATION_THRESHOLD_ETH: u128 = 90 * 10u128.pow(5); // 90%
+    const LIQUIDATION_BONUS: u128 = 5 * 10u128.pow(5); // 5%
+    const SUPPLY_CAP_ETH: u128 = 1300 * 10u128.pow(TOKENS_DECIMALS);
+    const BORROW_CAP_ETH: u128 = 80 * 10u128.pow(TOKENS_DECIMALS);
+
+    // total reserves are 1200 ETH, 50 ETH of them borrowed
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    assert_eq!(
+        contract_client.get_supply_headroom(&symbol_short!("eth")),
+        u128::MAX
+    );
+    assert_eq!(
+        contract_client.get_borrow_headroom(&symbol_short!("eth")),
+        u128::MAX
+    );
+
+    contract_client.set_reserve_configuration(
+        &admin,
+        &symbol_short!("eth"),
+        &LTV_ETH,
+        &LIQUIDATION_THRESHOLD_ETH,
+        &LIQUIDATION_BONUS,
+        &SUPPLY_CAP_ETH,
+        &BORROW_CAP_ETH,
+    );
+
+    assert_eq!(
+        contract_client.get_supply_headroom(&symbol_short!("eth")),
+        100 * 10u128.pow(TOKENS_DECIMALS)
+    );
+    assert_eq!(
+        contract_client.get_borrow_headroom(&symbol_short!("eth")),
+        30 * 10u128.pow(TOKENS_DECIMALS)
+    );
+    assert_eq!(
+        contract_client.get_available_to_borrow(&user, &symbol_short!("eth")),
+        30 * 10u128.pow(TOKENS_DECIMALS)
+    );
+
+    contract_client.deposit(
+        &user,
+        &symbol_short!("eth"),
+        &(100 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+    contract_client.borrow(
+        &user,
+        &symbol_short!("eth"),
+        &(30 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+
+    assert_eq!(
+        contract_client.get_supply_headroom(&symbol_short!("eth")),
+        0
+    );
+    assert_eq!(
+        contract_client.get_borrow_headroom(&symbol_short!("eth")),
+        0
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #21)")]
+fn test_deposit_above_supply_cap() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const LTV_ETH: u128 = 85 * 10u128.pow(5); // 85%
+    const LIQUIDATION_THRESHOLD_ETH: u128 = 90 * 10u128.pow(5); // 90%
+    const LIQUIDATION_BONUS: u128 = 5 * 10u128.pow(5); // 5%
+    const SUPPLY_CAP_ETH: u128 = 1300 * 10u128.pow(TOKENS_DECIMALS);
+
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_reserve_configuration(
+        &admin,
+        &symbol_short!("eth"),
+        &LTV_ETH,
+        &LIQUIDATION_THRESHOLD_ETH,
+        &LIQUIDATION_BONUS,
+        &SUPPLY_CAP_ETH,
+        &0,
+    );
+
+    contract_client.deposit(
+        &user,
+        &symbol_short!("eth"),
+        &(101 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #22)")]
+fn test_borrow_above_borrow_cap() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const LTV_ETH: u128 = 85 * 10u128.pow(5); // 85%
+    const LIQUIDATION_THRESHOLD_ETH: u128 = 90 * 10u128.pow(5); // 90%
+    const LIQUIDATION_BONUS: u128 = 5 * 10u128.pow(5); // 5%
+    const BORROW_CAP_ETH: u128 = 80 * 10u128.pow(TOKENS_DECIMALS);
+
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_reserve_configuration(
+        &admin,
+        &symbol_short!("eth"),
+        &LTV_ETH,
+        &LIQUIDATION_THRESHOLD_ETH,
+        &LIQUIDATION_BONUS,
+        &0,
+        &BORROW_CAP_ETH,
+    );
+
+    contract_client.borrow(
+        &user,
+        &symbol_short!("eth"),
+        &(31 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+}
+
+#[test]
+fn test_frozen_market_can_be_unwound() {
+    const TOKENS_DECIMALS: u32 = 18;
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let guardian = Address::random(&env);
+    contract_client.grant_role(&Role::PauseGuardian, &guardian);
+    assert_eq!(
+        contract_client.get_role(&Role::PauseGuardian),
+        Some(guardian.clone())
+    );
+
+    contract_client.set_market_flags(&guardian, &symbol_short!("eth"), &true, &true, &false);
+
+    let market_flags = contract_client.get_market_flags(&symbol_short!("eth"));
+    assert!(market_flags.deposits_frozen);
+    assert!(market_flags.borrows_frozen);
+    assert!(!market_flags.paused);
+
+    // repay and redeem are still allowed while frozen
+    let user_borrow_amount_with_interest: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    contract_client.repay(
+        &user,
+        &symbol_short!("eth"),
+        &user_borrow_amount_with_interest,
+    );
+    contract_client.redeem(
+        &user,
+        &symbol_short!("eth"),
+        &(10 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth")),
+        0
+    );
+
+    contract_client.set_market_flags(&admin, &symbol_short!("eth"), &false, &false, &false);
+
+    contract_client.deposit(
+        &user,
+        &symbol_short!("eth"),
+        &(10 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #25)")]
+fn test_deposit_to_frozen_market() {
+    const TOKENS_DECIMALS: u32 = 18;
+
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_market_flags(&admin, &symbol_short!("eth"), &true, &false, &false);
+
+    contract_client.deposit(
+        &user,
+        &symbol_short!("eth"),
+        &(10 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #26)")]
+fn test_borrow_from_frozen_market() {
+    const TOKENS_DECIMALS: u32 = 18;
+
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_market_flags(&admin, &symbol_short!("eth"), &false, &true, &false);
+
+    contract_client.borrow(
+        &user,
+        &symbol_short!("eth"),
+        &(10 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #24)")]
+fn test_repay_to_paused_market() {
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_market_flags(&admin, &symbol_short!("eth"), &false, &false, &true);
+
+    contract_client.repay(&user, &symbol_short!("eth"), &0);
+}
+
+#[test]
+fn test_liquidation_threshold_without_collateral() {
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let depositor = Address::random(&env);
+
+    assert_eq!(
+        contract_client.get_user_liquidation_threshold(&depositor),
+        0
+    );
+    assert_ne!(contract_client.get_user_liquidation_threshold(&user), 0);
+}
+
+fn zero_liquidation_threshold_setup(
+    env: &Env,
+    contract_client: &LendingContractClient,
+    token_xlm: &token_contract::Client,
+) -> Address {
+    const DEPOSIT_AMOUNT_XLM: u128 = 1_000 * 10u128.pow(18); // 1_000 XLM, 10_000$
+    const BORROW_AMOUNT_ETH: u128 = 10u128.pow(18); // 1 ETH, 2_000$
+
+    let borrower = Address::random(env);
+    token_xlm.mint(&borrower, &(DEPOSIT_AMOUNT_XLM as i128));
+    contract_client.deposit(&borrower, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("xlm"));
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    let admin: Address = contract_client.get_admin();
+
+    // the only collateral of the borrower backs nothing anymore
+    contract_client.set_reserve_configuration(&admin, &symbol_short!("xlm"), &0, &0, &0, &0, &0);
+    assert_eq!(contract_client.get_user_liquidation_threshold(&borrower), 0);
+    borrower
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #12)")]
+fn test_disable_collateral_with_zero_liquidation_threshold() {
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrower = zero_liquidation_threshold_setup(&env, &contract_client, &token_xlm);
+
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("xlm"));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #12)")]
+fn test_unhealthy_with_zero_liquidation_threshold() {
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrower = zero_liquidation_threshold_setup(&env, &contract_client, &token_xlm);
+
+    contract_client.set_user_emode(&borrower, &0);
+}
+
+#[test]
+fn test_flash_loan_fee_rounded_up() {
+    const FLASH_LOAN_AMOUNT: u128 = 99; // 1% fee of 0.99 rounded up to 1
+    const FLASH_LOAN_FEE: u128 = 10u128.pow(5); // 1%
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let receiver_address = env.register_contract(None, flash_loan_receiver::FlashLoanReceiver);
+    let receiver_client =
+        flash_loan_receiver::FlashLoanReceiverClient::new(&env, &receiver_address);
+    receiver_client.initialize(&contract_client.address, &user);
+
+    contract_client.set_flash_loan_fee(&admin, &FLASH_LOAN_FEE);
+    token_eth.mint(&receiver_address, &1);
+
+    let vault_contract_address: Address = contract_client.get_vault_contract();
+    let vault_balance_before: i128 = token_eth.balance(&vault_contract_address);
+
+    contract_client.flash_loan(
+        &user,
+        &receiver_address,
+        &symbol_short!("eth"),
+        &FLASH_LOAN_AMOUNT,
+        &Bytes::new(&env),
+    );
+
+    assert_eq!(
+        token_eth.balance(&vault_contract_address),
+        vault_balance_before + 1
+    );
+    assert_eq!(token_eth.balance(&receiver_address), 0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #24)")]
+fn test_flash_loan_from_paused_market() {
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let receiver_address = env.register_contract(None, flash_loan_receiver::FlashLoanReceiver);
+
+    contract_client.set_market_flags(&admin, &symbol_short!("eth"), &false, &false, &true);
+
+    contract_client.flash_loan(
+        &user,
+        &receiver_address,
+        &symbol_short!("eth"),
+        &(10 * 10u128.pow(18)),
+        &Bytes::new(&env),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #23)")]
+fn test_flash_loan_while_paused() {
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let receiver_address = env.register_contract(None, flash_loan_receiver::FlashLoanReceiver);
+
+    contract_client.set_paused(&admin, &true);
+
+    contract_client.flash_loan(
+        &user,
+        &receiver_address,
+        &symbol_short!("eth"),
+        &(10 * 10u128.pow(18)),
+        &Bytes::new(&env),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #23)")]
+fn test_paused_protocol() {
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let guardian = Address::random(&env);
+    contract_client.grant_role(&Role::PauseGuardian, &guardian);
+    contract_client.set_paused(&guardian, &true);
+    assert!(contract_client.is_paused());
+
+    contract_client.toggle_collateral_setting(&user, &symbol_short!("xlm"));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #27)")]
+fn test_pause_by_unauthorized_caller() {
+    let (env, contract_client, _admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_paused(&Address::random(&env), &true);
+}
+
+#[test]
+fn test_market_delisting() {
+    const TOKENS_DECIMALS: u32 = 18;
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.update_token_info(
+        &admin,
+        &symbol_short!("eth"),
+        &symbol_short!("Ether"),
+        &symbol_short!("ETH"),
+    );
+
+    let token_info = contract_client.get_token_info(&symbol_short!("eth"));
+    assert_eq!(token_info.name, symbol_short!("Ether"));
+    assert_eq!(token_info.symbol, symbol_short!("ETH"));
+    assert_eq!(token_info.decimals, TOKENS_DECIMALS);
+
+    contract_client.deprecate_market(&admin, &symbol_short!("eth"));
+
+    assert!(contract_client.is_market_deprecated(&symbol_short!("eth")));
+    assert_eq!(
+        contract_client
+            .get_reserve_configuration(&symbol_short!("eth"))
+            .loan_to_value_ratio,
+        0
+    );
+
+    // winding the market down
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += 1000;
+    env.ledger().set(ledger_info);
+
+    let user_borrow_amount_with_interest: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    contract_client.repay(
+        &user,
+        &symbol_short!("eth"),
+        &user_borrow_amount_with_interest,
+    );
+    contract_client.redeem(&user, &symbol_short!("eth"), &0);
+
+    assert_eq!(
+        contract_client.get_total_mm_token_supply(&symbol_short!("eth")),
+        0
+    );
+
+    contract_client.remove_market(&admin, &symbol_short!("eth"));
+
+    assert!(!contract_client
+        .get_supported_tokens()
+        .contains(symbol_short!("eth")));
+    assert!(!contract_client.is_market_deprecated(&symbol_short!("eth")));
+
+    // the collateral loops skip the removed market
+    assert_eq!(
+        contract_client.get_user_collateral_usd(&user),
+        3000 * 10u128.pow(8)
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #28)")]
+fn test_deposit_to_deprecated_market() {
+    const TOKENS_DECIMALS: u32 = 18;
+
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.deprecate_market(&admin, &symbol_short!("eth"));
+
+    contract_client.deposit(
+        &user,
+        &symbol_short!("eth"),
+        &(10 * 10u128.pow(TOKENS_DECIMALS)),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #30)")]
+fn test_remove_market_with_deposits() {
+    let (_env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.deprecate_market(&admin, &symbol_short!("xlm"));
+    contract_client.remove_market(&admin, &symbol_short!("xlm"));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #42)")]
+fn test_relist_removed_market() {
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let token_usdt = create_custom_token(&env, &admin, "USDT", "usdt", &18);
+    let add_usdt_market = || {
+        contract_client.add_markets(
+            &admin,
+            &symbol_short!("usdt"),
+            &token_usdt.address,
+            &symbol_short!("Usdt"),
+            &(80 * 10u128.pow(5)),
+            &(85 * 10u128.pow(5)),
+            &(5 * 10u128.pow(18)),
+            &(30 * 10u128.pow(18)),
+            &(70 * 10u128.pow(18)),
+            &(80 * 10u128.pow(5)),
+        )
+    };
+
+    add_usdt_market();
+    contract_client.deprecate_market(&admin, &symbol_short!("usdt"));
+    contract_client.remove_market(&admin, &symbol_short!("usdt"));
+
+    assert!(!contract_client
+        .get_supported_tokens()
+        .contains(symbol_short!("usdt")));
+
+    add_usdt_market();
+}
+
+#[test]
+fn test_admin_and_liquidator_handover() {
+    let (env, contract_client, admin, _user, liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let new_admin = Address::random(&env);
+    let new_liquidator = Address::random(&env);
+
+    contract_client.propose_admin(&new_admin);
+    assert_eq!(contract_client.get_pending_admin(), Some(new_admin.clone()));
+    assert_eq!(contract_client.get_admin(), admin);
+
+    contract_client.accept_admin();
+    assert_eq!(contract_client.get_admin(), new_admin);
+    assert_eq!(contract_client.get_pending_admin(), None);
+
+    contract_client.propose_liquidator(&new_liquidator);
+    assert_eq!(
+        contract_client.get_pending_liquidator(),
+        Some(new_liquidator.clone())
+    );
+    assert_eq!(contract_client.get_liquidator(), liquidator);
+
+    contract_client.accept_liquidator();
+    assert_eq!(contract_client.get_liquidator(), new_liquidator);
+    assert_eq!(contract_client.get_pending_liquidator(), None);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #31)")]
+fn test_accept_cancelled_admin_transfer() {
+    let (env, contract_client, _admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.propose_admin(&Address::random(&env));
+    contract_client.cancel_admin_transfer();
+
+    contract_client.accept_admin();
+}
+
+#[test]
+fn test_upgrade_populated_contract() {
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let deposit_eth: u128 = contract_client.get_deposit(&user, &symbol_short!("eth"));
+    let mm_token_balance_eth: u128 =
+        contract_client.get_mm_token_balance(&user, &symbol_short!("eth"));
+    let borrow_eth: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    let collateral_usd: u128 = contract_client.get_user_collateral_usd(&user);
+
+    let new_wasm_hash = env.deployer().upload_contract_wasm(lending_contract::WASM);
+    contract_client.upgrade(&new_wasm_hash);
+
+    assert_eq!(contract_client.get_storage_version(), 2);
+    assert_eq!(
+        contract_client.get_deposit(&user, &symbol_short!("eth")),
+        deposit_eth
+    );
+    assert_eq!(
+        contract_client.get_mm_token_balance(&user, &symbol_short!("eth")),
+        mm_token_balance_eth
+    );
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth")),
+        borrow_eth
+    );
+    assert_eq!(
+        contract_client.get_user_collateral_usd(&user),
+        collateral_usd
+    );
+}
+
+#[test]
+fn test_migrate_storage_v0() {
+    const PERCENT_DECIMALS: u32 = 5;
+    const LTV_ETH: u128 = 85 * 10u128.pow(PERCENT_DECIMALS); // 85%
+    const LIQUIDATION_THRESHOLD_ETH: u128 = 90 * 10u128.pow(PERCENT_DECIMALS); // 90%
+    const PRICE_ETH: u128 = 2000 * 10u128.pow(8);
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrow_eth: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+
+    let mut legacy_total_borrow_map: Map<Symbol, TotalBorrowDataV1> = Map::new(&env);
+    for denom in contract_client.get_supported_tokens().iter() {
+        legacy_total_borrow_map.set(
+            denom.clone(),
+            TotalBorrowDataV1 {
+                denom: denom.clone(),
+                total_borrowed_amount: contract_client.get_total_borrowed_by_token(&denom),
+                expected_annual_interest_income: 0,
+                average_interest_rate: 0,
+                timestamp: env.ledger().timestamp(),
+            },
+        );
+    }
+
+    // rewinding the storage to the layout written before versioning
+    env.as_contract(&contract_client.address, || {
+        env.storage()
+            .persistent()
+            .set(&DataKey::TotalBorrowData, &legacy_total_borrow_map);
+
+        let mut legacy_reserve_map: Map<Symbol, ReserveConfigurationV0> = Map::new(&env);
+        legacy_reserve_map.set(
+            symbol_short!("eth"),
+            ReserveConfigurationV0 {
+                denom: symbol_short!("eth"),
+                loan_to_value_ratio: LTV_ETH,
+                liquidation_threshold: LIQUIDATION_THRESHOLD_ETH,
+            },
+        );
+        env.storage()
+            .persistent()
+            .set(&DataKey::ReserveConfiguration, &legacy_reserve_map);
+
+        let mut legacy_prices: Map<Symbol, u128> = Map::new(&env);
+        legacy_prices.set(symbol_short!("eth"), PRICE_ETH);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Prices, &legacy_prices);
+
+        env.storage().persistent().remove(&DataKey::StorageVersion);
+    });
+
+    assert_eq!(contract_client.get_storage_version(), 0);
+
+    contract_client.migrate();
+
+    assert_eq!(contract_client.get_storage_version(), 2);
+
+    let reserve_configuration = contract_client.get_reserve_configuration(&symbol_short!("eth"));
+    assert_eq!(reserve_configuration.loan_to_value_ratio, LTV_ETH);
+    assert_eq!(
+        reserve_configuration.liquidation_threshold,
+        LIQUIDATION_THRESHOLD_ETH
+    );
+    assert_eq!(
+        reserve_configuration.liquidation_bonus,
+        5 * 10u128.pow(PERCENT_DECIMALS)
+    );
+    assert_eq!(reserve_configuration.supply_cap, 0);
+
+    // the legacy price counts as stale until the next update
+    let token_price = contract_client.get_token_price(&symbol_short!("eth"));
+    assert_eq!(token_price.price, PRICE_ETH);
+    assert_eq!(token_price.timestamp, 0);
+
+    contract_client.set_max_price_age(&admin, &symbol_short!("eth"), &3600);
+    assert!(contract_client
+        .try_get_price(&symbol_short!("eth"))
+        .is_err());
+
+    assert_eq!(
+        contract_client.get_total_borrowed_by_token(&symbol_short!("eth")),
+        borrow_eth
+    );
+}
+
+#[test]
+fn test_migrate_storage_v1() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const BORROW_AMOUNT_ETH: u128 = 50 * 10u128.pow(TOKENS_DECIMALS); // 50 ETH
+    const INTEREST_RATE: u128 = 10 * 10u128.pow(18); // 10%
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(3);
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let mut legacy_total_borrow_map: Map<Symbol, TotalBorrowDataV1> = Map::new(&env);
+    for denom in contract_client.get_supported_tokens().iter() {
+        legacy_total_borrow_map.set(
+            denom.clone(),
+            TotalBorrowDataV1 {
+                denom: denom.clone(),
+                total_borrowed_amount: contract_client.get_total_borrowed_by_token(&denom),
+                expected_annual_interest_income: 0,
+                average_interest_rate: 0,
+                timestamp: env.ledger().timestamp(),
+            },
+        );
+    }
+    legacy_total_borrow_map.set(
+        symbol_short!("eth"),
+        TotalBorrowDataV1 {
+            denom: symbol_short!("eth"),
+            total_borrowed_amount: BORROW_AMOUNT_ETH,
+            expected_annual_interest_income: BORROW_AMOUNT_ETH / 10,
+            average_interest_rate: INTEREST_RATE,
+            timestamp: env.ledger().timestamp(),
+        },
+    );
+
+    let mut legacy_borrow_map: Map<Symbol, UserBorrowingInfoV1> = Map::new(&env);
+    legacy_borrow_map.set(
+        symbol_short!("eth"),
+        UserBorrowingInfoV1 {
+            borrowed_amount: BORROW_AMOUNT_ETH,
+            average_interest_rate: INTEREST_RATE,
+            timestamp: env.ledger().timestamp(),
+        },
+    );
+
+    // rewinding the borrows to the layout of storage version 1
+    env.as_contract(&contract_client.address, || {
+        env.storage()
+            .persistent()
+            .set(&DataKey::TotalBorrowData, &legacy_total_borrow_map);
+        env.storage().persistent().set(
+            &DataKey::UserBorrowingInfo(user.clone()),
+            &legacy_borrow_map,
+        );
+        env.storage()
+            .persistent()
+            .remove(&DataKey::UserScaledBorrowedAmount(user.clone()));
+        env.storage()
+            .persistent()
+            .set(&DataKey::StorageVersion, &1_u32);
+    });
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    contract_client.migrate();
+    assert_eq!(contract_client.get_storage_version(), 2);
+
+    // the legacy borrow has accrued 10% up to the migration
+    let borrow_eth: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    assert!(borrow_eth.abs_diff(BORROW_AMOUNT_ETH * 110 / 100) < ROUNDING_TOLERANCE);
+    assert!(
+        contract_client
+            .get_total_borrowed_by_token(&symbol_short!("eth"))
+            .abs_diff(borrow_eth)
+            < ROUNDING_TOLERANCE
+    );
+    assert_eq!(
+        contract_client.get_user_scaled_borrowed_amount(&user, &symbol_short!("eth")),
+        borrow_eth
+    );
+
+    // the next update converts it to the scaled amount
+    contract_client.repay(&user, &symbol_short!("eth"), &(borrow_eth / 2));
+    env.as_contract(&contract_client.address, || {
+        assert!(!env
+            .storage()
+            .persistent()
+            .has(&DataKey::UserBorrowingInfo(user.clone())));
+    });
+
+    assert!(
+        contract_client
+            .get_user_borrow_with_interest(&user, &symbol_short!("eth"))
+            .abs_diff(borrow_eth - borrow_eth / 2)
+            < ROUNDING_TOLERANCE
+    );
+    assert!(
+        contract_client
+            .get_total_borrowed_by_token(&symbol_short!("eth"))
+            .abs_diff(borrow_eth - borrow_eth / 2)
+            < ROUNDING_TOLERANCE
+    );
+}
+
+#[test]
+fn test_migrate_storage_v1_borrows_at_different_rates() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const BORROW_AMOUNT_ETH: u128 = 25 * 10u128.pow(TOKENS_DECIMALS); // 25 ETH
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(3);
+
+    // user borrowed 50 ETH, rewound as two legacy borrows of 25 ETH at 5% and 15%
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+    let second_user = Address::random(&env);
+
+    let mut legacy_total_borrow_map: Map<Symbol, TotalBorrowDataV1> = Map::new(&env);
+    for denom in contract_client.get_supported_tokens().iter() {
+        legacy_total_borrow_map.set(
+            denom.clone(),
+            TotalBorrowDataV1 {
+                denom: denom.clone(),
+                total_borrowed_amount: contract_client.get_total_borrowed_by_token(&denom),
+                expected_annual_interest_income: 0,
+                average_interest_rate: 0,
+                timestamp: env.ledger().timestamp(),
+            },
+        );
+    }
+    legacy_total_borrow_map.set(
+        symbol_short!("eth"),
+        TotalBorrowDataV1 {
+            denom: symbol_short!("eth"),
+            total_borrowed_amount: 2 * BORROW_AMOUNT_ETH,
+            expected_annual_interest_income: 2 * BORROW_AMOUNT_ETH / 10,
+            average_interest_rate: 10 * 10u128.pow(18), // 10%
+            timestamp: env.ledger().timestamp(),
+        },
+    );
+
+    let legacy_borrow = |interest_rate: u128| {
+        let mut legacy_borrow_map: Map<Symbol, UserBorrowingInfoV1> = Map::new(&env);
+        legacy_borrow_map.set(
+            symbol_short!("eth"),
+            UserBorrowingInfoV1 {
+                borrowed_amount: BORROW_AMOUNT_ETH,
+                average_interest_rate: interest_rate,
+                timestamp: env.ledger().timestamp(),
+            },
+        );
+        legacy_borrow_map
+    };
+    let legacy_borrow_map = legacy_borrow(5 * 10u128.pow(18)); // 5%
+    let second_legacy_borrow_map = legacy_borrow(15 * 10u128.pow(18)); // 15%
+
+    env.as_contract(&contract_client.address, || {
+        env.storage()
+            .persistent()
+            .set(&DataKey::TotalBorrowData, &legacy_total_borrow_map);
+        env.storage().persistent().set(
+            &DataKey::UserBorrowingInfo(user.clone()),
+            &legacy_borrow_map,
+        );
+        env.storage().persistent().set(
+            &DataKey::UserBorrowingInfo(second_user.clone()),
+            &second_legacy_borrow_map,
+        );
+        env.storage()
+            .persistent()
+            .remove(&DataKey::UserScaledBorrowedAmount(user.clone()));
+        env.storage()
+            .persistent()
+            .set(&DataKey::StorageVersion, &1_u32);
+    });
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    contract_client.migrate();
+
+    // both legacy borrows stop accruing their fixed rates at the migration
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    let borrow_eth: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    let second_borrow_eth: u128 =
+        contract_client.get_user_borrow_with_interest(&second_user, &symbol_short!("eth"));
+    let total_borrowed_eth: u128 =
+        contract_client.get_total_borrowed_by_token(&symbol_short!("eth"));
+
+    assert!(total_borrowed_eth > 2 * BORROW_AMOUNT_ETH * 110 / 100);
+    assert!((borrow_eth + second_borrow_eth).abs_diff(total_borrowed_eth) < ROUNDING_TOLERANCE);
+    assert!(
+        (borrow_eth * 115).abs_diff(second_borrow_eth * 105) < ROUNDING_TOLERANCE * 115,
+        "the borrows keep the ratio they had at the migration"
+    );
+}
+
+#[test]
+fn test_sum_of_user_borrows_equals_total_borrow() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const DEPOSIT_AMOUNT_XLM: u128 = 10_000 * 10u128.pow(TOKENS_DECIMALS); // 10_000 XLM
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(TOKENS_DECIMALS); // 10 ETH
+    const MONTH_IN_SECONDS: u64 = 2592000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(3);
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrowers = [user.clone(), Address::random(&env), Address::random(&env)];
+    for borrower in borrowers.iter().skip(1) {
+        token_xlm.mint(borrower, &(DEPOSIT_AMOUNT_XLM as i128));
+        contract_client.deposit(borrower, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+        contract_client.toggle_collateral_setting(borrower, &symbol_short!("xlm"));
+    }
+
+    let interest_rate_before: u128 = contract_client.get_interest_rate(&symbol_short!("eth"));
+
+    // borrows and repays at different times move the utilization and the rate
+    for (i, borrower) in borrowers.iter().enumerate() {
+        let mut ledger_info: LedgerInfo = env.ledger().get();
+        ledger_info.timestamp += MONTH_IN_SECONDS;
+        env.ledger().set(ledger_info);
+
+        contract_client.borrow(
+            borrower,
+            &symbol_short!("eth"),
+            &(BORROW_AMOUNT_ETH * (i as u128 + 1)),
+        );
+    }
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += MONTH_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    contract_client.repay(&borrowers[1], &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    // the first borrower pays the rate raised by the later borrows
+    assert!(contract_client.get_interest_rate(&symbol_short!("eth")) > interest_rate_before);
+
+    for _ in 0..3 {
+        let mut ledger_info: LedgerInfo = env.ledger().get();
+        ledger_info.timestamp += 4 * MONTH_IN_SECONDS;
+        env.ledger().set(ledger_info);
+
+        let sum_of_user_borrows: u128 = borrowers
+            .iter()
+            .map(|borrower| {
+                contract_client.get_user_borrow_with_interest(borrower, &symbol_short!("eth"))
+            })
+            .sum();
+
+        assert!(
+            sum_of_user_borrows
+                .abs_diff(contract_client.get_total_borrowed_by_token(&symbol_short!("eth")))
+                < ROUNDING_TOLERANCE
+        );
+    }
+}
+
+fn stable_borrower_setup(
+    env: &Env,
+    contract_client: &LendingContractClient,
+    token_xlm: &token_contract::Client,
+    collateral_amount: u128,
+) -> Address {
+    let borrower = Address::random(env);
+    token_xlm.mint(&borrower, &(collateral_amount as i128));
+    contract_client.deposit(&borrower, &symbol_short!("xlm"), &collateral_amount);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("xlm"));
+    borrower
+}
+
+#[test]
+fn test_stable_borrow() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const INTEREST_RATE_DECIMALS: u32 = 18;
+    const STABLE_RATE_PREMIUM: u128 = 2 * 10u128.pow(INTEREST_RATE_DECIMALS); // 2%
+    const DEPOSIT_AMOUNT_XLM: u128 = 10_000 * 10u128.pow(TOKENS_DECIMALS); // 10_000 XLM
+    const WHALE_DEPOSIT_AMOUNT_XLM: u128 = 400_000 * 10u128.pow(TOKENS_DECIMALS); // 400_000 XLM
+    const STABLE_BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(TOKENS_DECIMALS); // 10 ETH
+    const VARIABLE_BORROW_AMOUNT_ETH: u128 = 800 * 10u128.pow(TOKENS_DECIMALS); // 800 ETH
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(3);
+
+    // user borrowed 50 ETH
+    let (env, contract_client, admin, user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_stable_rate_premium(&admin, &symbol_short!("eth"), &STABLE_RATE_PREMIUM);
+
+    let borrower = stable_borrower_setup(&env, &contract_client, &token_xlm, DEPOSIT_AMOUNT_XLM);
+    let whale = stable_borrower_setup(&env, &contract_client, &token_xlm, WHALE_DEPOSIT_AMOUNT_XLM);
+
+    let variable_rate_before: u128 = contract_client.get_interest_rate(&symbol_short!("eth"));
+    let stable_rate: u128 = contract_client.get_stable_interest_rate(&symbol_short!("eth"));
+
+    assert_eq!(stable_rate, variable_rate_before + STABLE_RATE_PREMIUM);
+
+    contract_client.borrow_stable(&borrower, &symbol_short!("eth"), &STABLE_BORROW_AMOUNT_ETH);
+
+    assert_eq!(
+        contract_client.get_user_stable_rate(&borrower, &symbol_short!("eth")),
+        stable_rate
+    );
+    assert_eq!(
+        contract_client.get_user_scaled_borrowed_amount(&borrower, &symbol_short!("eth")),
+        0
+    );
+
+    // a large variable borrow raises the variable rate but not the stable one
+    contract_client.borrow(&whale, &symbol_short!("eth"), &VARIABLE_BORROW_AMOUNT_ETH);
+
+    assert!(contract_client.get_interest_rate(&symbol_short!("eth")) > stable_rate);
+    assert_eq!(
+        contract_client.get_user_stable_rate(&borrower, &symbol_short!("eth")),
+        stable_rate
+    );
+    assert_eq!(
+        contract_client
+            .get_total_stable_borrow_data(&symbol_short!("eth"))
+            .average_interest_rate,
+        stable_rate
+    );
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    let stable_borrow: u128 =
+        contract_client.get_user_stable_borrow(&borrower, &symbol_short!("eth"));
+
+    assert!(stable_borrow > STABLE_BORROW_AMOUNT_ETH);
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("eth")),
+        stable_borrow
+    );
+
+    // the stable borrows are part of the market total
+    let sum_of_user_borrows: u128 = [user, borrower.clone(), whale]
+        .iter()
+        .map(|account| {
+            contract_client.get_user_borrow_with_interest(account, &symbol_short!("eth"))
+        })
+        .sum();
+
+    assert!(
+        sum_of_user_borrows
+            .abs_diff(contract_client.get_total_borrowed_by_token(&symbol_short!("eth")))
+            < ROUNDING_TOLERANCE
+    );
+
+    token_eth.mint(&borrower, &(stable_borrow as i128));
+    contract_client.repay(&borrower, &symbol_short!("eth"), &stable_borrow);
+
+    assert_eq!(
+        contract_client.get_user_stable_borrow(&borrower, &symbol_short!("eth")),
+        0
+    );
+    assert_eq!(
+        contract_client
+            .get_total_stable_borrow_data(&symbol_short!("eth"))
+            .total_borrowed_amount,
+        0
+    );
+}
+
+#[test]
+fn test_rebalance_stable_rate() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const INTEREST_RATE_DECIMALS: u32 = 18;
+    const STABLE_RATE_PREMIUM: u128 = 10u128.pow(INTEREST_RATE_DECIMALS); // 1%
+    const DEPOSIT_AMOUNT_XLM: u128 = 10_000 * 10u128.pow(TOKENS_DECIMALS); // 10_000 XLM
+    const WHALE_DEPOSIT_AMOUNT_XLM: u128 = 400_000 * 10u128.pow(TOKENS_DECIMALS); // 400_000 XLM
+    const STABLE_BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(TOKENS_DECIMALS); // 10 ETH
+    const VARIABLE_BORROW_AMOUNT_ETH: u128 = 950 * 10u128.pow(TOKENS_DECIMALS); // 950 ETH
+
+    // user borrowed 50 ETH
+    let (env, contract_client, admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_stable_rate_premium(&admin, &symbol_short!("eth"), &STABLE_RATE_PREMIUM);
+
+    let borrower = stable_borrower_setup(&env, &contract_client, &token_xlm, DEPOSIT_AMOUNT_XLM);
+    let whale = stable_borrower_setup(&env, &contract_client, &token_xlm, WHALE_DEPOSIT_AMOUNT_XLM);
+
+    contract_client.borrow_stable(&borrower, &symbol_short!("eth"), &STABLE_BORROW_AMOUNT_ETH);
+    let stable_rate_before: u128 =
+        contract_client.get_user_stable_rate(&borrower, &symbol_short!("eth"));
+
+    // the market is pushed above its optimal utilization of 80%
+    contract_client.borrow(&whale, &symbol_short!("eth"), &VARIABLE_BORROW_AMOUNT_ETH);
+
+    assert!(
+        contract_client.get_utilization_rate_by_token(&symbol_short!("eth")) > 80 * 10u128.pow(5)
+    );
+
+    // any account can rebalance the position
+    contract_client.rebalance_stable_rate(&borrower, &symbol_short!("eth"));
+
+    let stable_rate_after: u128 =
+        contract_client.get_user_stable_rate(&borrower, &symbol_short!("eth"));
+
+    assert!(stable_rate_after > stable_rate_before);
+    assert_eq!(
+        stable_rate_after,
+        contract_client.get_stable_interest_rate(&symbol_short!("eth"))
+    );
+    assert_eq!(
+        contract_client.get_user_stable_borrow(&borrower, &symbol_short!("eth")),
+        STABLE_BORROW_AMOUNT_ETH
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #35)")]
+fn test_rebalance_fairly_priced_stable_rate() {
+    const STABLE_BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    // user borrowed 50 ETH
+    let (_env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_stable_rate_premium(&admin, &symbol_short!("eth"), &10u128.pow(18));
+    contract_client.borrow_stable(&user, &symbol_short!("eth"), &STABLE_BORROW_AMOUNT_ETH);
+
+    contract_client.rebalance_stable_rate(&user, &symbol_short!("eth"));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #34)")]
+fn test_stable_borrow_disabled() {
+    const STABLE_BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    // user borrowed 50 ETH
+    let (_env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.borrow_stable(&user, &symbol_short!("eth"), &STABLE_BORROW_AMOUNT_ETH);
+}
+
+fn isolated_borrower_setup(
+    env: &Env,
+    contract_client: &LendingContractClient,
+    token_xlm: &token_contract::Client,
+) -> Address {
+    const DEPOSIT_AMOUNT_XLM: u128 = 10_000 * 10u128.pow(18); // 10_000 XLM, 100_000$
+    const DEBT_CEILING_XLM: u128 = 50_000 * 10u128.pow(8); // 50_000$
+
+    let admin: Address = contract_client.get_admin();
+    contract_client.set_debt_ceiling(&admin, &symbol_short!("xlm"), &DEBT_CEILING_XLM);
+
+    let borrower = Address::random(env);
+    token_xlm.mint(&borrower, &(DEPOSIT_AMOUNT_XLM as i128));
+    contract_client.deposit(&borrower, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("xlm"));
+    borrower
+}
+
+#[test]
+fn test_isolation_mode() {
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH, 20_000$
+    const REPAY_AMOUNT_ETH: u128 = 5 * 10u128.pow(18); // 5 ETH, 10_000$
+
+    let (env, contract_client, admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrower = isolated_borrower_setup(&env, &contract_client, &token_xlm);
+    contract_client.set_borrowable_in_isolation(&admin, &symbol_short!("eth"), &true);
+
+    assert_eq!(
+        contract_client.get_user_isolated_collateral(&borrower),
+        Some(symbol_short!("xlm"))
+    );
+
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    assert_eq!(
+        contract_client.get_isolated_debt(&symbol_short!("xlm")),
+        20_000 * 10u128.pow(8)
+    );
+
+    contract_client.repay(&borrower, &symbol_short!("eth"), &REPAY_AMOUNT_ETH);
+
+    assert_eq!(
+        contract_client.get_isolated_debt(&symbol_short!("xlm")),
+        10_000 * 10u128.pow(8)
+    );
+}
+
+#[test]
+fn test_isolated_debt_released_at_borrow_value() {
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH, 20_000$
+    const DROPPED_PRICE_ETH: u128 = 1000 * 10u128.pow(8); // 1000$
+
+    let (env, contract_client, admin, _user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let borrower = isolated_borrower_setup(&env, &contract_client, &token_xlm);
+    contract_client.set_borrowable_in_isolation(&admin, &symbol_short!("eth"), &true);
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    // the repaid borrow is worth 10_000$ only, the 20_000$ charged are released anyway
+    contract_client.update_price(&admin, &symbol_short!("eth"), &DROPPED_PRICE_ETH);
+    contract_client.repay(&borrower, &symbol_short!("eth"), &(BORROW_AMOUNT_ETH / 2));
+
+    assert_eq!(
+        contract_client.get_isolated_debt(&symbol_short!("xlm")),
+        10_000 * 10u128.pow(8)
+    );
+
+    token_eth.mint(&borrower, &(BORROW_AMOUNT_ETH as i128));
+    contract_client.repay(&borrower, &symbol_short!("eth"), &0);
+
+    assert_eq!(contract_client.get_isolated_debt(&symbol_short!("xlm")), 0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #36)")]
+fn test_isolated_collateral_with_other_collateral() {
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrower = isolated_borrower_setup(&env, &contract_client, &token_xlm);
+
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("eth"));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #37)")]
+fn test_borrow_not_borrowable_in_isolation() {
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrower = isolated_borrower_setup(&env, &contract_client, &token_xlm);
+
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #38)")]
+fn test_borrow_above_debt_ceiling() {
+    const BORROW_AMOUNT_ETH: u128 = 30 * 10u128.pow(18); // 30 ETH, 60_000$
+
+    let (env, contract_client, admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let borrower = isolated_borrower_setup(&env, &contract_client, &token_xlm);
+    contract_client.set_borrowable_in_isolation(&admin, &symbol_short!("eth"), &true);
+
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+}
+
+fn emode_borrower_setup(
+    env: &Env,
+    contract_client: &LendingContractClient,
+    token_eth: &token_contract::Client,
+) -> Address {
+    const DEPOSIT_AMOUNT_ETH: u128 = 10u128.pow(18); // 1 ETH, 2000$
+    const PERCENT_DECIMALS: u32 = 5;
+    const EMODE_LTV: u128 = 95 * 10u128.pow(PERCENT_DECIMALS); // 95%
+    const EMODE_LIQUIDATION_THRESHOLD: u128 = 97 * 10u128.pow(PERCENT_DECIMALS); // 97%
+
+    let admin: Address = contract_client.get_admin();
+    contract_client.set_emode_category(
+        &admin,
+        &1,
+        &symbol_short!("eth_xlm"),
+        &EMODE_LTV,
+        &EMODE_LIQUIDATION_THRESHOLD,
+        &None,
+    );
+    contract_client.set_market_emode_category(&admin, &symbol_short!("eth"), &1);
+
+    let borrower = Address::random(env);
+    token_eth.mint(&borrower, &(DEPOSIT_AMOUNT_ETH as i128));
+    contract_client.deposit(&borrower, &symbol_short!("eth"), &DEPOSIT_AMOUNT_ETH);
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("eth"));
+    borrower
+}
+
+#[test]
+fn test_emode() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const PERCENT_DECIMALS: u32 = 5;
+    const EMODE_LTV: u128 = 95 * 10u128.pow(PERCENT_DECIMALS); // 95%
+    const EMODE_LIQUIDATION_THRESHOLD: u128 = 97 * 10u128.pow(PERCENT_DECIMALS); // 97%
+    const BORROW_AMOUNT_XLM: u128 = 185 * 10u128.pow(TOKENS_DECIMALS); // 185 XLM, 1850$
+
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let borrower = emode_borrower_setup(&env, &contract_client, &token_eth);
+    contract_client.set_market_emode_category(&admin, &symbol_short!("xlm"), &1);
+
+    // 85% LTV of 2000$ outside of the category
+    assert_eq!(
+        contract_client.get_available_to_borrow(&borrower, &symbol_short!("xlm")),
+        170 * 10u128.pow(TOKENS_DECIMALS)
+    );
+
+    contract_client.set_user_emode(&borrower, &1);
+
+    assert_eq!(contract_client.get_user_emode(&borrower), 1);
+    assert_eq!(
+        contract_client.get_available_to_borrow(&borrower, &symbol_short!("xlm")),
+        190 * 10u128.pow(TOKENS_DECIMALS)
+    );
+
+    // with the ETH price as the shared price source, XLM is valued at 2000$ as well
+    contract_client.set_emode_category(
+        &admin,
+        &1,
+        &symbol_short!("eth_xlm"),
+        &EMODE_LTV,
+        &EMODE_LIQUIDATION_THRESHOLD,
+        &Some(symbol_short!("eth")),
+    );
+
+    assert_eq!(
+        contract_client.get_available_to_borrow(&borrower, &symbol_short!("xlm")),
+        95 * 10u128.pow(TOKENS_DECIMALS - 2)
+    );
+
+    contract_client.set_emode_category(
+        &admin,
+        &1,
+        &symbol_short!("eth_xlm"),
+        &EMODE_LTV,
+        &EMODE_LIQUIDATION_THRESHOLD,
+        &None,
+    );
+
+    contract_client.borrow(&borrower, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("xlm")),
+        BORROW_AMOUNT_XLM
+    );
+}
+
+#[test]
+fn test_emode_liquidation_with_price_source() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const PERCENT_DECIMALS: u32 = 5;
+    const HUNDRED_PERCENT: u128 = 100 * 10u128.pow(PERCENT_DECIMALS);
+    const EMODE_LTV: u128 = 95 * 10u128.pow(PERCENT_DECIMALS); // 95%
+    const EMODE_LIQUIDATION_THRESHOLD: u128 = 97 * 10u128.pow(PERCENT_DECIMALS); // 97%
+    const BORROW_AMOUNT_XLM: u128 = 95 * 10u128.pow(TOKENS_DECIMALS - 2); // 0.95 XLM, 1900$
+    const YEAR_IN_SECONDS: u64 = 31536000;
+    const ROUNDING_TOLERANCE: u128 = 10u128.pow(10);
+
+    let (env, contract_client, admin, _user, liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    // XLM is valued at the ETH price within the category
+    let borrower = emode_borrower_setup(&env, &contract_client, &token_eth);
+    contract_client.set_market_emode_category(&admin, &symbol_short!("xlm"), &1);
+    contract_client.set_emode_category(
+        &admin,
+        &1,
+        &symbol_short!("eth_xlm"),
+        &EMODE_LTV,
+        &EMODE_LIQUIDATION_THRESHOLD,
+        &Some(symbol_short!("eth")),
+    );
+    contract_client.set_user_emode(&borrower, &1);
+    contract_client.borrow(&borrower, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+
+    // the accrued interest takes the borrow above the liquidation threshold
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    let borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("xlm"));
+    let deposit_before: u128 = contract_client.get_deposit(&borrower, &symbol_short!("eth"));
+
+    contract_client.liquidation(
+        &liquidator,
+        &borrower,
+        &symbol_short!("xlm"),
+        &symbol_short!("eth"),
+        &0,
+        &false,
+    );
+
+    let repaid: u128 = borrow_before
+        - contract_client.get_user_borrow_with_interest(&borrower, &symbol_short!("xlm"));
+    let liquidation_bonus: u128 = contract_client
+        .get_reserve_configuration(&symbol_short!("eth"))
+        .liquidation_bonus;
+
+    // both sides are valued at the shared price, not at the 10$ XLM market price
+    assert!(repaid > 0);
+    assert!(
+        (deposit_before - contract_client.get_deposit(&borrower, &symbol_short!("eth")))
+            .abs_diff(repaid * (HUNDRED_PERCENT + liquidation_bonus) / HUNDRED_PERCENT)
+            < ROUNDING_TOLERANCE
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #40)")]
+fn test_emode_borrow_outside_of_category() {
+    const BORROW_AMOUNT_XLM: u128 = 100 * 10u128.pow(18); // 100 XLM
+
+    let (env, contract_client, _admin, _user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let borrower = emode_borrower_setup(&env, &contract_client, &token_eth);
+    contract_client.set_user_emode(&borrower, &1);
+
+    contract_client.borrow(&borrower, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #12)")]
+fn test_leave_emode_with_unhealthy_position() {
+    const BORROW_AMOUNT_XLM: u128 = 185 * 10u128.pow(18); // 185 XLM, 1850$
+
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let borrower = emode_borrower_setup(&env, &contract_client, &token_eth);
+    contract_client.set_market_emode_category(&admin, &symbol_short!("xlm"), &1);
+    contract_client.set_user_emode(&borrower, &1);
+    contract_client.borrow(&borrower, &symbol_short!("xlm"), &BORROW_AMOUNT_XLM);
+
+    // 90% liquidation threshold of 2000$ outside of the category
+    contract_client.set_user_emode(&borrower, &0);
+}
+
+#[test]
+fn test_credit_delegation() {
+    const BORROW_ALLOWANCE_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+    const BORROW_AMOUNT_ETH: u128 = 4 * 10u128.pow(18); // 4 ETH
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let delegatee = Address::random(&env);
+    let user_borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+
+    contract_client.approve_delegation(
+        &user,
+        &delegatee,
+        &symbol_short!("eth"),
+        &BORROW_ALLOWANCE_ETH,
+    );
+    contract_client.borrow_on_behalf(&user, &delegatee, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    assert_eq!(token_eth.balance(&delegatee) as u128, BORROW_AMOUNT_ETH);
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth")),
+        user_borrow_before + BORROW_AMOUNT_ETH
+    );
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&delegatee, &symbol_short!("eth")),
+        0
+    );
+    assert_eq!(
+        contract_client.get_borrow_allowance(&user, &delegatee, &symbol_short!("eth")),
+        BORROW_ALLOWANCE_ETH - BORROW_AMOUNT_ETH
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #41)")]
+fn test_borrow_on_behalf_above_allowance() {
+    const BORROW_ALLOWANCE_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let delegatee = Address::random(&env);
+
+    contract_client.approve_delegation(
+        &user,
+        &delegatee,
+        &symbol_short!("eth"),
+        &BORROW_ALLOWANCE_ETH,
+    );
+    contract_client.borrow_on_behalf(
+        &user,
+        &delegatee,
+        &symbol_short!("eth"),
+        &(BORROW_ALLOWANCE_ETH + 1),
+    );
+}
+
+#[test]
+fn test_deposit_and_repay_for_another_account() {
+    const DEPOSIT_AMOUNT_XLM: u128 = 100 * 10u128.pow(18); // 100 XLM
+    const REPAY_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let payer = Address::random(&env);
+    token_xlm.mint(&payer, &(DEPOSIT_AMOUNT_XLM as i128));
+    token_eth.mint(&payer, &(REPAY_AMOUNT_ETH as i128));
+
+    let user_deposit_before: u128 = contract_client.get_deposit(&user, &symbol_short!("xlm"));
+    let user_borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+
+    contract_client.deposit_for(&payer, &user, &symbol_short!("xlm"), &DEPOSIT_AMOUNT_XLM);
+    contract_client.repay_for(&payer, &user, &symbol_short!("eth"), &REPAY_AMOUNT_ETH);
+
+    assert_eq!(token_xlm.balance(&payer), 0);
+    assert_eq!(token_eth.balance(&payer), 0);
+    assert_eq!(
+        contract_client.get_deposit(&payer, &symbol_short!("xlm")),
+        0
+    );
+    assert_eq!(
+        contract_client.get_deposit(&user, &symbol_short!("xlm")),
+        user_deposit_before + DEPOSIT_AMOUNT_XLM
+    );
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth")),
+        user_borrow_before - REPAY_AMOUNT_ETH
+    );
+}
+
+#[test]
+fn test_repay_for_whole_borrow() {
+    const PAYER_BALANCE_ETH: u128 = 60 * 10u128.pow(18); // 60 ETH
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let payer = Address::random(&env);
+    token_eth.mint(&payer, &(PAYER_BALANCE_ETH as i128));
+
+    let user_borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    let vault_balance_before: i128 = token_eth.balance(&contract_client.get_vault_contract());
+
+    // 0 repays the whole borrow
+    contract_client.repay_for(&payer, &user, &symbol_short!("eth"), &0);
+
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth")),
+        0
+    );
+    assert_eq!(
+        token_eth.balance(&payer) as u128,
+        PAYER_BALANCE_ETH - user_borrow_before
+    );
+    assert_eq!(
+        token_eth.balance(&contract_client.get_vault_contract()),
+        vault_balance_before + user_borrow_before as i128
+    );
+}
+
+#[test]
+fn test_repay_for_more_than_borrowed() {
+    const PAYER_BALANCE_ETH: u128 = 60 * 10u128.pow(18); // 60 ETH
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let payer = Address::random(&env);
+    token_eth.mint(&payer, &(PAYER_BALANCE_ETH as i128));
+
+    let user_borrow_before: u128 =
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth"));
+    let vault_balance_before: i128 = token_eth.balance(&contract_client.get_vault_contract());
+
+    // only the borrow is taken from the payer
+    contract_client.repay_for(&payer, &user, &symbol_short!("eth"), &PAYER_BALANCE_ETH);
+
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&user, &symbol_short!("eth")),
+        0
+    );
+    assert_eq!(
+        token_eth.balance(&payer) as u128,
+        PAYER_BALANCE_ETH - user_borrow_before
+    );
+    assert_eq!(
+        token_eth.balance(&contract_client.get_vault_contract()),
+        vault_balance_before + user_borrow_before as i128
+    );
+}
+
+#[test]
+fn test_redeem_and_borrow_to_another_account() {
+    const REDEEM_AMOUNT_XLM: u128 = 100 * 10u128.pow(18); // 100 XLM
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    // user borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let recipient = Address::random(&env);
+    let user_balance_xlm: i128 = token_xlm.balance(&user);
+    let user_balance_eth: i128 = token_eth.balance(&user);
+
+    contract_client.redeem_to(&user, &symbol_short!("xlm"), &REDEEM_AMOUNT_XLM, &recipient);
+    contract_client.borrow_to(&user, &symbol_short!("eth"), &BORROW_AMOUNT_ETH, &recipient);
+
+    assert_eq!(token_xlm.balance(&recipient) as u128, REDEEM_AMOUNT_XLM);
+    assert_eq!(token_eth.balance(&recipient) as u128, BORROW_AMOUNT_ETH);
+    assert_eq!(token_xlm.balance(&user), user_balance_xlm);
+    assert_eq!(token_eth.balance(&user), user_balance_eth);
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&recipient, &symbol_short!("eth")),
+        0
+    );
+}
+
+#[test]
+fn test_execute_actions() {
+    const DEPOSIT_AMOUNT_XLM: u128 = 10_000 * 10u128.pow(18); // 10_000 XLM, 100_000$
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH, 20_000$
+
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    let trader = Address::random(&env);
+    token_xlm.mint(&trader, &(DEPOSIT_AMOUNT_XLM as i128));
+
+    // the borrow is covered by the collateral enabled later in the same call
+    contract_client.execute_actions(
+        &trader,
+        &soroban_sdk::vec![
+            &env,
+            Action::Borrow(symbol_short!("eth"), BORROW_AMOUNT_ETH),
+            Action::Deposit(symbol_short!("xlm"), DEPOSIT_AMOUNT_XLM),
+            Action::EnableCollateral(symbol_short!("xlm")),
+        ],
+    );
+
+    assert_eq!(token_eth.balance(&trader) as u128, BORROW_AMOUNT_ETH);
+    assert_eq!(token_xlm.balance(&trader), 0);
+    assert_eq!(
+        contract_client.get_deposit(&trader, &symbol_short!("xlm")),
+        DEPOSIT_AMOUNT_XLM
+    );
+    assert!(contract_client.user_deposit_as_collateral(&trader, &symbol_short!("xlm")));
+
+    contract_client.execute_actions(
+        &trader,
+        &soroban_sdk::vec![
+            &env,
+            Action::Repay(symbol_short!("eth"), BORROW_AMOUNT_ETH),
+            Action::DisableCollateral(symbol_short!("xlm")),
+            Action::Redeem(symbol_short!("xlm"), DEPOSIT_AMOUNT_XLM),
+        ],
+    );
+
+    assert_eq!(
+        contract_client.get_user_borrow_with_interest(&trader, &symbol_short!("eth")),
+        0
+    );
+    assert_eq!(token_xlm.balance(&trader) as u128, DEPOSIT_AMOUNT_XLM);
+    assert!(!contract_client.user_deposit_as_collateral(&trader, &symbol_short!("xlm")));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #9)")]
+fn test_execute_actions_above_ltv() {
+    const DEPOSIT_AMOUNT_XLM: u128 = 10_000 * 10u128.pow(18); // 10_000 XLM, 100_000$
+    const BORROW_AMOUNT_ETH: u128 = 40 * 10u128.pow(18); // 40 ETH, 80_000$
+
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let trader = Address::random(&env);
+    token_xlm.mint(&trader, &(DEPOSIT_AMOUNT_XLM as i128));
+
+    contract_client.execute_actions(
+        &trader,
+        &soroban_sdk::vec![
+            &env,
+            Action::Deposit(symbol_short!("xlm"), DEPOSIT_AMOUNT_XLM),
+            Action::EnableCollateral(symbol_short!("xlm")),
+            Action::Borrow(symbol_short!("eth"), BORROW_AMOUNT_ETH),
+        ],
+    );
+}
+
+fn isolation_switch_setup(
+    env: &Env,
+    contract_client: &LendingContractClient,
+    token_xlm: &token_contract::Client,
+    token_eth: &token_contract::Client,
+    borrow_amount_eth: u128,
+) -> Address {
+    const DEPOSIT_AMOUNT_ETH: u128 = 50 * 10u128.pow(18); // 50 ETH, 100_000$
+    const DEPOSIT_AMOUNT_XLM: u128 = 10_000 * 10u128.pow(18); // 10_000 XLM, 100_000$
+    const DEBT_CEILING_XLM: u128 = 50_000 * 10u128.pow(8); // 50_000$
+
+    let admin: Address = contract_client.get_admin();
+    contract_client.set_debt_ceiling(&admin, &symbol_short!("xlm"), &DEBT_CEILING_XLM);
+
+    let trader = Address::random(env);
+    token_eth.mint(&trader, &(DEPOSIT_AMOUNT_ETH as i128));
+    token_xlm.mint(&trader, &(DEPOSIT_AMOUNT_XLM as i128));
+
+    contract_client.execute_actions(
+        &trader,
+        &soroban_sdk::vec![
+            env,
+            Action::Deposit(symbol_short!("eth"), DEPOSIT_AMOUNT_ETH),
+            Action::EnableCollateral(symbol_short!("eth")),
+            Action::Borrow(symbol_short!("eth"), borrow_amount_eth),
+            Action::Deposit(symbol_short!("xlm"), DEPOSIT_AMOUNT_XLM),
+        ],
+    );
+
+    // the borrow taken against eth ends up backed by the isolated xlm collateral
+    contract_client.execute_actions(
+        &trader,
+        &soroban_sdk::vec![
+            env,
+            Action::DisableCollateral(symbol_short!("eth")),
+            Action::EnableCollateral(symbol_short!("xlm")),
+        ],
+    );
+    trader
+}
+
+#[test]
+fn test_execute_actions_switch_to_isolated_collateral() {
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH, 20_000$
+
+    let (env, contract_client, admin, _user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_borrowable_in_isolation(&admin, &symbol_short!("eth"), &true);
+    let trader = isolation_switch_setup(
+        &env,
+        &contract_client,
+        &token_xlm,
+        &token_eth,
+        BORROW_AMOUNT_ETH,
+    );
+
+    assert_eq!(
+        contract_client.get_user_isolated_collateral(&trader),
+        Some(symbol_short!("xlm"))
+    );
+    assert_eq!(
+        contract_client.get_isolated_debt(&symbol_short!("xlm")),
+        20_000 * 10u128.pow(8)
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #37)")]
+fn test_execute_actions_switch_to_isolated_collateral_not_borrowable() {
+    const BORROW_AMOUNT_ETH: u128 = 10 * 10u128.pow(18); // 10 ETH, 20_000$
+
+    let (env, contract_client, _admin, _user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    isolation_switch_setup(
+        &env,
+        &contract_client,
+        &token_xlm,
+        &token_eth,
+        BORROW_AMOUNT_ETH,
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #38)")]
+fn test_execute_actions_switch_to_isolated_collateral_above_debt_ceiling() {
+    const BORROW_AMOUNT_ETH: u128 = 30 * 10u128.pow(18); // 30 ETH, 60_000$
+
+    let (env, contract_client, admin, _user, _liquidator, token_xlm, token_eth) =
+        success_borrow_setup();
+
+    contract_client.set_borrowable_in_isolation(&admin, &symbol_short!("eth"), &true);
+    isolation_switch_setup(
+        &env,
+        &contract_client,
+        &token_xlm,
+        &token_eth,
+        BORROW_AMOUNT_ETH,
+    );
+}
+
+#[test]
+fn test_account_snapshot() {
+    const TOKENS_DECIMALS: u32 = 18;
+    const BORROW_AMOUNT_ETH: u128 = 50 * 10u128.pow(TOKENS_DECIMALS); // 50 ETH
+    const YEAR_IN_SECONDS: u64 = 31536000;
+
+    // user deposited 200 ETH and 300 XLM and borrowed 50 ETH
+    let (env, contract_client, _admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let mut ledger_info: LedgerInfo = env.ledger().get();
+    ledger_info.timestamp += YEAR_IN_SECONDS;
+    env.ledger().set(ledger_info);
+
+    let snapshot: AccountSnapshot = contract_client.get_account_snapshot(&user);
+
+    assert_eq!(snapshot.markets.len(), 2);
+    assert_eq!(
+        snapshot.collateral_usd,
+        contract_client.get_user_collateral_usd(&user)
+    );
+    assert_eq!(
+        snapshot.borrowed_usd,
+        contract_client.get_user_borrowed_usd(&user)
+    );
+    assert_eq!(
+        snapshot.max_allowed_borrow_usd,
+        contract_client.get_user_max_allowed_borrow_usd(&user)
+    );
+    assert_eq!(
+        snapshot.liquidation_threshold,
+        contract_client.get_user_liquidation_threshold(&user)
+    );
+    assert_eq!(
+        snapshot.utilization_rate,
+        contract_client.get_user_utilization_rate(&user)
+    );
+    assert_eq!(
+        snapshot.health_factor,
+        contract_client.get_user_health_factor(&user)
+    );
+    assert!(snapshot.health_factor > 100 * 10u128.pow(5));
+
+    for market in snapshot.markets.iter() {
+        assert!(market.use_as_collateral);
+        assert_eq!(
+            market.deposit,
+            contract_client.get_deposit(&user, &market.denom)
+        );
+        assert_eq!(
+            market.mm_token_balance,
+            contract_client.get_mm_token_balance(&user, &market.denom)
+        );
+        assert_eq!(
+            market.borrowed_amount,
+            contract_client.get_user_borrow_with_interest(&user, &market.denom)
+        );
+    }
+
+    let market_eth: AccountMarketSnapshot = snapshot
+        .markets
+        .iter()
+        .find(|market| market.denom == symbol_short!("eth"))
+        .unwrap();
+
+    assert!(market_eth.borrowed_amount > BORROW_AMOUNT_ETH);
+
+    // no borrows, no liquidation
+    assert_eq!(
+        contract_client.get_user_health_factor(&Address::random(&env)),
+        u128::MAX
+    );
+}
+
+#[test]
+fn test_markets_overview() {
+    // user borrowed 50 ETH
+    let (_env, contract_client, _admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let markets = contract_client.get_markets_overview(&0, &0);
+    let supported_tokens = contract_client.get_supported_tokens();
+
+    assert_eq!(markets.len(), supported_tokens.len());
+
+    for (market, denom) in markets.iter().zip(supported_tokens.iter()) {
+        assert_eq!(market.token_info.denom, denom);
+        assert_eq!(
+            market.interest_rate,
+            contract_client.get_interest_rate(&denom)
+        );
+        assert_eq!(market.stable_interest_rate, 0);
+        assert_eq!(
+            market.liquidity_rate,
+            contract_client.get_liquidity_rate(&denom)
+        );
+        assert_eq!(
+            market.utilization_rate,
+            contract_client.get_utilization_rate_by_token(&denom)
+        );
+        assert_eq!(
+            market.total_reserves,
+            contract_client.get_total_reserves_by_token(&denom)
+        );
+        assert_eq!(
+            market.total_borrowed,
+            contract_client.get_total_borrowed_by_token(&denom)
+        );
+        assert_eq!(market.price, contract_client.get_price(&denom));
+        assert_eq!(
+            market.mm_token_price,
+            contract_client.get_mm_token_price(&denom)
+        );
+        assert_eq!(
+            market.reserve_configuration.loan_to_value_ratio,
+            contract_client
+                .get_reserve_configuration(&denom)
+                .loan_to_value_ratio
+        );
+    }
+
+    // the second page of one market
+    let page = contract_client.get_markets_overview(&1, &1);
+
+    assert_eq!(page.len(), 1);
+    assert_eq!(
+        page.get(0).unwrap().token_info.denom,
+        supported_tokens.get(1).unwrap()
+    );
+    assert_eq!(contract_client.get_markets_overview(&5, &0).len(), 0);
+}
+
+#[test]
+fn test_markets_overview_without_price() {
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let token_usdt = create_custom_token(&env, &admin, "USDT", "usdt", &18);
+
+    contract_client.add_markets(
+        &admin,
+        &symbol_short!("usdt"),
+        &token_usdt.address,
+        &symbol_short!("Usdt"),
+        &(80 * 10u128.pow(5)),
+        &(85 * 10u128.pow(5)),
+        &(5 * 10u128.pow(18)),
+        &(30 * 10u128.pow(18)),
+        &(70 * 10u128.pow(18)),
+        &(80 * 10u128.pow(5)),
+    );
+
+    // the new market has no price yet, the others are still listed
+    let markets = contract_client.get_markets_overview(&0, &0);
+    let usdt_market = markets.get(markets.len() - 1).unwrap();
+
+    assert_eq!(markets.len(), 3);
+    assert_eq!(usdt_market.token_info.denom, symbol_short!("usdt"));
+    assert_eq!(usdt_market.price, 0);
+    assert_eq!(usdt_market.price_timestamp, 0);
+    assert_ne!(markets.get(0).unwrap().price, 0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #32)")]
+fn test_migrate_up_to_date_storage() {
+    let (_env, contract_client, _admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.migrate();
+}
+
+#[test]
+fn test_roles() {
+    const PRICE_ETH: u128 = 2100 * 10u128.pow(8);
+    const CLOSE_FACTOR: u128 = 60 * 10u128.pow(5); // 60%
+
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let price_updater = Address::random(&env);
+    contract_client.grant_role(&Role::PriceUpdater, &price_updater);
+    assert_eq!(
+        contract_client.get_role(&Role::PriceUpdater),
+        Some(price_updater.clone())
+    );
+
+    contract_client.update_price(&price_updater, &symbol_short!("eth"), &PRICE_ETH);
+    assert_eq!(env.auths()[0].0, price_updater);
+
+    // the admin keeps the operations of a granted role
+    contract_client.update_price(&admin, &symbol_short!("eth"), &PRICE_ETH);
+    assert_eq!(env.auths()[0].0, admin);
+
+    contract_client.set_close_factor(&admin, &CLOSE_FACTOR);
+    assert_eq!(env.auths()[0].0, admin);
+
+    contract_client.revoke_role(&Role::PriceUpdater);
+    assert_eq!(contract_client.get_role(&Role::PriceUpdater), None);
+
+    contract_client.update_price(&admin, &symbol_short!("eth"), &PRICE_ETH);
+    assert_eq!(env.auths()[0].0, admin);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #27)")]
+fn test_revoked_role() {
+    const PRICE_ETH: u128 = 2100 * 10u128.pow(8);
+
+    let (env, contract_client, _admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let price_updater = Address::random(&env);
+    contract_client.grant_role(&Role::PriceUpdater, &price_updater);
+    contract_client.revoke_role(&Role::PriceUpdater);
+
+    contract_client.update_price(&price_updater, &symbol_short!("eth"), &PRICE_ETH);
+}
+
+#[test]
+fn test_set_vault_contract_by_admin() {
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let vault_contract_address = Address::random(&env);
+    contract_client.set_vault_contract(&vault_contract_address);
+
+    assert_eq!(env.auths()[0].0, admin);
+
+    let (_, topics, data) = env
+        .events()
+        .all()
+        .iter()
+        .filter(|event| event.0 == contract_client.address)
+        .last()
+        .unwrap();
+
+    let action = Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap();
+
+    assert_eq!(action, Symbol::new(&env, "set_vault_contract"));
+    assert_eq!(
+        Address::try_from_val(&env, &data).unwrap(),
+        vault_contract_address
+    );
+    assert_eq!(contract_client.get_vault_contract(), vault_contract_address);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #33)")]
+fn test_revoke_not_granted_role() {
+    let (_env, contract_client, _admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    contract_client.revoke_role(&Role::ReservesTreasurer);
+}
+
+fn add_market_with_mm_token<'a>(
+    env: &Env,
+    contract_client: &LendingContractClient,
+    admin: &Address,
+) -> (token_contract::Client<'a>, mm_token_contract::Client<'a>) {
+    const TOKENS_DECIMALS: u32 = 18;
+    const PERCENT_DECIMALS: u32 = 5;
+    const INTEREST_RATE_DECIMALS: u32 = 18;
+
+    let mm_token_wasm_hash = env.deployer().upload_contract_wasm(mm_token_contract::WASM);
+    contract_client.set_mm_token_wasm_hash(&mm_token_wasm_hash);
+
+    let token_usdc = create_custom_token(env, admin, "Usdc", "usdc", &TOKENS_DECIMALS);
+
+    contract_client.add_markets(
+        &admin,
+        &symbol_short!("usdc"),
+        &token_usdc.address,
+        &symbol_short!("Usdc"),
+        &(80 * 10u128.pow(PERCENT_DECIMALS)), // LTV 80%
+        &(85 * 10u128.pow(PERCENT_DECIMALS)), // liquidation threshold 85%
+        &(5 * 10u128.pow(INTEREST_RATE_DECIMALS)),
+        &(30 * 10u128.pow(INTEREST_RATE_DECIMALS)),
+        &(70 * 10u128.pow(INTEREST_RATE_DECIMALS)),
+        &(80 * 10u128.pow(PERCENT_DECIMALS)),
+    );
+    // the tests move the ledger without refreshing the prices
+    contract_client.set_max_price_age(&admin, &symbol_short!("usdc"), &0);
+    contract_client.update_price(&admin, &symbol_short!("usdc"), &100000000); // 1$
+
+    let mm_token_address: Address = contract_client
+        .get_mm_token(&symbol_short!("usdc"))
+        .unwrap();
+    let mm_token = mm_token_contract::Client::new(env, &mm_token_address);
+
+    (token_usdc, mm_token)
+}
+
+#[test]
+fn test_mm_token_transfer() {
+    const DEPOSIT_AMOUNT_USDC: u128 = 1000 * 10u128.pow(18); // 1000 USDC
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let (token_usdc, mm_token) = add_market_with_mm_token(&env, &contract_client, &admin);
+
+    assert_eq!(mm_token.decimals(), 18);
+    assert_eq!(mm_token.name(), String::from_slice(&env, "mmUsdc"));
+    assert_eq!(mm_token.symbol(), String::from_slice(&env, "mmusdc"));
+
+    token_usdc.mint(&user, &(DEPOSIT_AMOUNT_USDC as i128));
+    contract_client.deposit(&user, &symbol_short!("usdc"), &DEPOSIT_AMOUNT_USDC);
+
+    let user_mm_token_balance: u128 =
+        contract_client.get_mm_token_balance(&user, &symbol_short!("usdc"));
+    assert_eq!(mm_token.balance(&user) as u128, user_mm_token_balance);
+
+    let receiver = Address::random(&env);
+    mm_token.transfer(&user, &receiver, &((user_mm_token_balance / 2) as i128));
+
+    assert_eq!(
+        mm_token.balance(&user) as u128,
+        user_mm_token_balance - user_mm_token_balance / 2
+    );
+    assert_eq!(
+        contract_client.get_deposit(&receiver, &symbol_short!("usdc")),
+        DEPOSIT_AMOUNT_USDC / 2
+    );
+
+    // moving the shares back through an allowance
+    let spender = Address::random(&env);
+    mm_token.approve(
+        &receiver,
+        &spender,
+        &((user_mm_token_balance / 2) as i128),
+        &(env.ledger().sequence() + 100),
+    );
+    mm_token.transfer_from(
+        &spender,
+        &receiver,
+        &user,
+        &((user_mm_token_balance / 2) as i128),
+    );
+
+    assert_eq!(mm_token.balance(&receiver), 0);
+    assert_eq!(mm_token.allowance(&receiver, &spender), 0);
+    assert_eq!(mm_token.balance(&user) as u128, user_mm_token_balance);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #12)")]
+fn test_mm_token_transfer_of_collateral_in_use() {
+    const DEPOSIT_AMOUNT_USDC: u128 = 1000 * 10u128.pow(18); // 1000 USDC
+    const BORROW_AMOUNT_ETH: u128 = 3 * 10u128.pow(17); // 0.3 ETH == 600$
+
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let (token_usdc, mm_token) = add_market_with_mm_token(&env, &contract_client, &admin);
+
+    let borrower = Address::random(&env);
+    token_usdc.mint(&borrower, &(DEPOSIT_AMOUNT_USDC as i128));
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("usdc"));
+    contract_client.deposit(&borrower, &symbol_short!("usdc"), &DEPOSIT_AMOUNT_USDC);
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    // the borrow is backed by the usdc deposit only
+    mm_token.transfer(
+        &borrower,
+        &Address::random(&env),
+        &mm_token.balance(&borrower),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #12)")]
+fn test_mm_token_transfer_above_ltv() {
+    const DEPOSIT_AMOUNT_USDC: u128 = 1000 * 10u128.pow(18); // 1000 USDC
+    const BORROW_AMOUNT_ETH: u128 = 3 * 10u128.pow(17); // 0.3 ETH == 600$
+
+    let (env, contract_client, admin, _user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let (token_usdc, mm_token) = add_market_with_mm_token(&env, &contract_client, &admin);
+
+    let borrower = Address::random(&env);
+    token_usdc.mint(&borrower, &(DEPOSIT_AMOUNT_USDC as i128));
+    contract_client.toggle_collateral_setting(&borrower, &symbol_short!("usdc"));
+    contract_client.deposit(&borrower, &symbol_short!("usdc"), &DEPOSIT_AMOUNT_USDC);
+    contract_client.borrow(&borrower, &symbol_short!("eth"), &BORROW_AMOUNT_ETH);
+
+    // 730$ of collateral left is above the liquidation threshold but below the LTV of the borrow
+    mm_token.transfer(
+        &borrower,
+        &Address::random(&env),
+        &(mm_token.balance(&borrower) * 27 / 100),
+    );
+}
+
+#[test]
+fn test_mm_token_mint_and_burn_events() {
+    const DEPOSIT_AMOUNT_USDC: u128 = 1000 * 10u128.pow(18); // 1000 USDC
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let (token_usdc, mm_token) = add_market_with_mm_token(&env, &contract_client, &admin);
+
+    token_usdc.mint(&user, &(DEPOSIT_AMOUNT_USDC as i128));
+    contract_client.deposit(&user, &symbol_short!("usdc"), &DEPOSIT_AMOUNT_USDC);
+
+    let mm_token_balance: i128 = mm_token.balance(&user);
+    let mm_token_event = |name: &str| {
+        env.events()
+            .all()
+            .iter()
+            .find(|(contract, topics, _data)| {
+                *contract == mm_token.address
+                    && Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap()
+                        == Symbol::new(&env, name)
+            })
+            .unwrap()
+    };
+
+    let (_contract, topics, data) = mm_token_event("mint");
+    assert_eq!(
+        Address::try_from_val(&env, &topics.get(1).unwrap()).unwrap(),
+        contract_client.address
+    );
+    assert_eq!(
+        Address::try_from_val(&env, &topics.get(2).unwrap()).unwrap(),
+        user
+    );
+    assert_eq!(i128::try_from_val(&env, &data).unwrap(), mm_token_balance);
+
+    contract_client.redeem(&user, &symbol_short!("usdc"), &0);
+
+    let (_contract, topics, data) = mm_token_event("burn");
+    assert_eq!(
+        Address::try_from_val(&env, &topics.get(1).unwrap()).unwrap(),
+        user
+    );
+    assert_eq!(i128::try_from_val(&env, &data).unwrap(), mm_token_balance);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #3)")]
+fn test_mm_token_transfer_from_above_allowance() {
+    const DEPOSIT_AMOUNT_USDC: u128 = 1000 * 10u128.pow(18); // 1000 USDC
+
+    let (env, contract_client, admin, user, _liquidator, _token_xlm, _token_eth) =
+        success_borrow_setup();
+
+    let (token_usdc, mm_token) = add_market_with_mm_token(&env, &contract_client, &admin);
+
+    token_usdc.mint(&user, &(DEPOSIT_AMOUNT_USDC as i128));
+    contract_client.deposit(&user, &symbol_short!("usdc"), &DEPOSIT_AMOUNT_USDC);
+
+    let spender = Address::random(&env);
+    mm_token.approve(&user, &spender, &1, &(env.ledger().sequence() + 100));
+
+    mm_token.transfer_from(&spender, &user, &spender, &2);
+}
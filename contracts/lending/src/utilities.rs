@@ -1,10 +1,14 @@
 use soroban_sdk::{
-    contract, contractimpl, map, symbol_short, token, Address, Env, Map, String, Symbol, Vec,
-}; // contracterror, panic_with_error, vec
+    contract, contractimpl, map, panic_with_error, symbol_short, token, Address, BytesN, Env, Map,
+    String, Symbol, Vec,
+}; // contracterror, vec
 
 use core::ops::{Add, Div, Mul};
 use rust_decimal::prelude::{Decimal, MathematicalOps, ToPrimitive};
 
+use crate::errors::LendingError;
+use crate::mm_token::MMTokenClient;
+use crate::oracle::{Asset, PriceOracleClient};
 use crate::storage::*;
 
 pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
@@ -24,6 +28,12 @@ pub(crate) const YEAR_IN_SECONDS: u128 = 31536000; // 365 days
 
 pub(crate) const USD_DECIMALS: u32 = 8;
 
+pub(crate) const STORAGE_VERSION: u32 = 2;
+
+pub(crate) const DEFAULT_CLOSE_FACTOR: u128 = 50 * 10u128.pow(PERCENT_DECIMALS); // 50%
+pub(crate) const DEFAULT_LIQUIDATION_BONUS: u128 = 5 * 10u128.pow(PERCENT_DECIMALS); // 5%
+pub(crate) const DEFAULT_MAX_PRICE_AGE: u64 = 60 * 60; // 1 hour
+
 pub trait DecimalExt {
     fn to_u128_with_decimals(&self, decimals: u32) -> Result<u128, rust_decimal::Error>;
 }
@@ -31,11 +41,24 @@ pub trait DecimalExt {
 impl DecimalExt for Decimal {
     // converting high-precise numbers into u128
     fn to_u128_with_decimals(&self, decimals: u32) -> Result<u128, rust_decimal::Error> {
-        let number_dec_new: Decimal = self * Decimal::new(10_i64.pow(decimals), 0);
+        let number_dec_new: Decimal = self
+            .checked_mul(Decimal::new(10_i64.pow(decimals), 0))
+            .ok_or(rust_decimal::Error::ExceedsMaximumPossibleValue)?;
         Ok(number_dec_new.to_u128().unwrap_or(0))
     }
 }
 
+pub trait OverflowExt<T> {
+    fn unwrap_or_overflow(self, env: &Env) -> T;
+}
+
+impl<T> OverflowExt<T> for Result<T, rust_decimal::Error> {
+    // aborting with LendingError::MathOverflow instead of an opaque unwrap panic
+    fn unwrap_or_overflow(self, env: &Env) -> T {
+        self.unwrap_or_else(|_| panic_with_error!(env, LendingError::MathOverflow))
+    }
+}
+
 pub fn has_admin(env: &Env) -> bool {
     let key = DataKey::Admin;
     env.storage().persistent().has(&key)
@@ -43,7 +66,10 @@ pub fn has_admin(env: &Env) -> bool {
 
 pub fn get_admin(env: &Env) -> Address {
     let key = DataKey::Admin;
-    env.storage().persistent().get(&key).unwrap()
+    env.storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::NotInitialized))
 }
 
 pub fn set_admin(env: &Env, admin: &Address) {
@@ -64,12 +90,18 @@ pub fn set_vault_contract(env: &Env, vault_contract: &Address) {
 
 pub fn get_vault_contract(env: &Env) -> Address {
     let key = DataKey::VaultContract;
-    env.storage().persistent().get(&key).unwrap()
+    env.storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::NotInitialized))
 }
 
 pub fn get_liquidator(env: &Env) -> Address {
     let key = DataKey::Liquidator;
-    env.storage().persistent().get(&key).unwrap()
+    env.storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::NotInitialized))
 }
 
 pub fn set_liquidator(env: &Env, liquidator: &Address) {
@@ -80,17 +112,403 @@ pub fn set_liquidator(env: &Env, liquidator: &Address) {
         .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
 }
 
-pub fn get_deposit(env: Env, user: Address, denom: Symbol) -> u128 {
-    // calculates user deposit including deposit interest
-    let token_decimals = get_token_decimal(env.clone(), denom.clone());
+pub fn get_storage_version(env: &Env) -> u32 {
+    let key = DataKey::StorageVersion;
+    env.storage().persistent().get(&key).unwrap_or(0)
+}
+
+pub fn set_storage_version(env: &Env, storage_version: u32) {
+    let key = DataKey::StorageVersion;
+    env.storage().persistent().set(&key, &storage_version);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_migration_v1_timestamp(env: &Env) -> u64 {
+    let key = DataKey::MigrationV1Timestamp;
+    env.storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or(env.ledger().timestamp())
+}
+
+pub fn set_migration_v1_timestamp(env: &Env, timestamp: u64) {
+    let key = DataKey::MigrationV1Timestamp;
+    env.storage().persistent().set(&key, &timestamp);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn migrate_storage_v0(env: &Env) {
+    // version 0 stored reserve configurations without liquidation bonus and caps
+    let legacy_reserve_map: Option<Map<Symbol, ReserveConfigurationV0>> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::ReserveConfiguration);
+
+    if let Some(legacy_reserve_map) = legacy_reserve_map {
+        let mut reserve_map: Map<Symbol, ReserveConfiguration> = Map::new(env);
+        for (denom, legacy_configuration) in legacy_reserve_map.iter() {
+            reserve_map.set(
+                denom,
+                ReserveConfiguration {
+                    denom: legacy_configuration.denom,
+                    loan_to_value_ratio: legacy_configuration.loan_to_value_ratio,
+                    liquidation_threshold: legacy_configuration.liquidation_threshold,
+                    liquidation_bonus: DEFAULT_LIQUIDATION_BONUS,
+                    supply_cap: 0_u128,
+                    borrow_cap: 0_u128,
+                },
+            );
+        }
+        env.storage()
+            .persistent()
+            .set(&DataKey::ReserveConfiguration, &reserve_map);
+        env.storage().persistent().bump(
+            &DataKey::ReserveConfiguration,
+            MONTH_LIFETIME_THRESHOLD,
+            MONTH_BUMP_AMOUNT,
+        );
+    }
+
+    // and plain prices without a timestamp, they are stamped with 0 so that they count as stale
+    // until the next update
+    let legacy_prices: Option<Map<Symbol, u128>> = env.storage().persistent().get(&DataKey::Prices);
+
+    if let Some(legacy_prices) = legacy_prices {
+        let mut prices: Map<Symbol, TokenPrice> = Map::new(env);
+        for (denom, price) in legacy_prices.iter() {
+            prices.set(
+                denom,
+                TokenPrice {
+                    price,
+                    timestamp: 0_u64,
+                },
+            );
+        }
+        env.storage().persistent().set(&DataKey::Prices, &prices);
+        env.storage().persistent().bump(
+            &DataKey::Prices,
+            MONTH_LIFETIME_THRESHOLD,
+            MONTH_BUMP_AMOUNT,
+        );
+    }
+}
+
+pub fn migrate_storage_v1(env: &Env) {
+    // version 1 tracked the total borrow with an average interest rate, the total with its
+    // interest becomes the principal of a borrow index starting at the migration time
+    set_migration_v1_timestamp(env, env.ledger().timestamp());
+
+    let legacy_total_borrow_map: Option<Map<Symbol, TotalBorrowDataV1>> =
+        env.storage().persistent().get(&DataKey::TotalBorrowData);
+
+    if let Some(legacy_total_borrow_map) = legacy_total_borrow_map {
+        let mut total_borrow_map: Map<Symbol, TotalBorrowData> = Map::new(env);
+        for (denom, legacy_total_borrow_data) in legacy_total_borrow_map.iter() {
+            let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+            let total_borrowed_amount: u128 = calc_borrow_amount_with_interest(
+                env.clone(),
+                legacy_total_borrow_data.total_borrowed_amount,
+                legacy_total_borrow_data.average_interest_rate,
+                (env.ledger().timestamp() - legacy_total_borrow_data.timestamp) as u128,
+                token_decimals,
+            );
+
+            // the protocol share accrued so far follows the version 1 expected income
+            let reserve_factor: u128 = get_reserve_factor(env.clone(), denom.clone());
+            if reserve_factor != 0 {
+                let liquidity_index_data: LiquidityIndexData = env
+                    .storage()
+                    .persistent()
+                    .get(&DataKey::LiquidityIndexData)
+                    .unwrap_or(Map::new(env))
+                    .get(denom.clone())
+                    .unwrap_or_else(|| panic_with_error!(env, LendingError::UnknownDenom));
+
+                let interval: u128 = env
+                    .ledger()
+                    .timestamp()
+                    .checked_sub(liquidity_index_data.timestamp)
+                    .unwrap_or_default() as u128;
+
+                let accrued_reserves: u128 = Decimal::from_i128_with_scale(
+                    legacy_total_borrow_data.expected_annual_interest_income as i128,
+                    INTEREST_RATE_DECIMALS,
+                )
+                .mul(Decimal::from_i128_with_scale(
+                    (reserve_factor * interval) as i128,
+                    PERCENT_DECIMALS + 2,
+                ))
+                .div(Decimal::from_i128_with_scale(YEAR_IN_SECONDS as i128, 0u32))
+                .to_u128_with_decimals(token_decimals)
+                .unwrap_or_overflow(env);
+
+                let protocol_reserves: u128 = env
+                    .storage()
+                    .persistent()
+                    .get(&DataKey::ProtocolReserves)
+                    .unwrap_or(Map::<Symbol, u128>::new(env))
+                    .get(denom.clone())
+                    .unwrap_or(0_u128);
+                set_protocol_reserves(
+                    env.clone(),
+                    denom.clone(),
+                    protocol_reserves + accrued_reserves,
+                );
+            }
+
+            total_borrow_map.set(
+                denom.clone(),
+                TotalBorrowData {
+                    denom,
+                    total_scaled_borrowed_amount: total_borrowed_amount,
+                    borrow_index_ln: 0_u128,
+                    timestamp: env.ledger().timestamp(),
+                },
+            );
+        }
+        env.storage()
+            .persistent()
+            .set(&DataKey::TotalBorrowData, &total_borrow_map);
+        env.storage().persistent().bump(
+            &DataKey::TotalBorrowData,
+            MONTH_LIFETIME_THRESHOLD,
+            MONTH_BUMP_AMOUNT,
+        );
+    }
+
+    // user borrows are per user keys, they are converted by get/set_user_scaled_borrowed_amount
+}
+
+pub fn get_pending_admin(env: &Env) -> Option<Address> {
+    let key = DataKey::PendingAdmin;
+    env.storage().persistent().get(&key)
+}
+
+pub fn set_pending_admin(env: &Env, pending_admin: &Address) {
+    let key = DataKey::PendingAdmin;
+    env.storage().persistent().set(&key, pending_admin);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn remove_pending_admin(env: &Env) {
+    let key = DataKey::PendingAdmin;
+    env.storage().persistent().remove(&key);
+}
+
+pub fn get_pending_liquidator(env: &Env) -> Option<Address> {
+    let key = DataKey::PendingLiquidator;
+    env.storage().persistent().get(&key)
+}
+
+pub fn set_pending_liquidator(env: &Env, pending_liquidator: &Address) {
+    let key = DataKey::PendingLiquidator;
+    env.storage().persistent().set(&key, pending_liquidator);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn remove_pending_liquidator(env: &Env) {
+    let key = DataKey::PendingLiquidator;
+    env.storage().persistent().remove(&key);
+}
+
+pub fn get_role(env: &Env, role: Role) -> Option<Address> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::Roles)
+        .unwrap_or(Map::<Role, Address>::new(env))
+        .get(role)
+}
+
+pub fn set_role(env: &Env, role: Role, account: &Address) {
+    let mut roles: Map<Role, Address> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::Roles)
+        .unwrap_or(Map::new(env));
+    roles.set(role, account.clone());
+    env.storage().persistent().set(&DataKey::Roles, &roles);
+    env.storage()
+        .persistent()
+        .bump(&DataKey::Roles, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn remove_role(env: &Env, role: Role) {
+    let mut roles: Map<Role, Address> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::Roles)
+        .unwrap_or(Map::new(env));
+    roles.remove(role);
+    env.storage().persistent().set(&DataKey::Roles, &roles);
+    env.storage()
+        .persistent()
+        .bump(&DataKey::Roles, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn require_role(env: &Env, caller: &Address, role: Role) {
+    // the admin keeps the operations of a granted role next to its holder
+    caller.require_auth();
+
+    if *caller != get_admin(env) && Some(caller.clone()) != get_role(env, role) {
+        panic_with_error!(env, LendingError::Unauthorized);
+    }
+}
+
+pub fn is_paused(env: &Env) -> bool {
+    let key = DataKey::Paused;
+    env.storage().persistent().get(&key).unwrap_or(false)
+}
+
+pub fn set_paused(env: &Env, paused: bool) {
+    let key = DataKey::Paused;
+    env.storage().persistent().set(&key, &paused);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_flash_loan_fee(env: &Env) -> u128 {
+    let key = DataKey::FlashLoanFee;
+    env.storage().persistent().get(&key).unwrap_or(0_u128)
+}
+
+pub fn set_flash_loan_fee(env: &Env, flash_loan_fee: u128) {
+    let key = DataKey::FlashLoanFee;
+    env.storage().persistent().set(&key, &flash_loan_fee);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_close_factor(env: &Env) -> u128 {
+    let key = DataKey::CloseFactor;
+    env.storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or(DEFAULT_CLOSE_FACTOR)
+}
+
+pub fn set_close_factor(env: &Env, close_factor: u128) {
+    let key = DataKey::CloseFactor;
+    env.storage().persistent().set(&key, &close_factor);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_mm_token_wasm_hash(env: &Env) -> Option<BytesN<32>> {
+    let key = DataKey::MMTokenWasmHash;
+    env.storage().persistent().get(&key)
+}
+
+pub fn set_mm_token_wasm_hash(env: &Env, wasm_hash: &BytesN<32>) {
+    let key = DataKey::MMTokenWasmHash;
+    env.storage().persistent().set(&key, wasm_hash);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_mm_token(env: Env, denom: Symbol) -> Option<Address> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::MMTokens)
+        .unwrap_or(Map::<Symbol, Address>::new(&env))
+        .get(denom)
+}
+
+pub fn set_mm_token(env: Env, denom: Symbol, mm_token: Address) {
+    let mut mm_tokens: Map<Symbol, Address> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::MMTokens)
+        .unwrap_or(Map::new(&env));
+    mm_tokens.set(denom, mm_token);
+    env.storage()
+        .persistent()
+        .set(&DataKey::MMTokens, &mm_tokens);
+    env.storage().persistent().bump(
+        &DataKey::MMTokens,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
 
-    let user_mm_token_balance: u128 = env
+pub fn get_mm_token_balance(env: Env, user: Address, denom: Symbol) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::UserMMTokenBalance(user))
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u128)
+}
+
+pub fn set_mm_token_balance(env: Env, user: Address, denom: Symbol, mm_token_balance: u128) {
+    let mut user_mm_balance_map: Map<Symbol, u128> = env
         .storage()
         .persistent()
         .get(&DataKey::UserMMTokenBalance(user.clone()))
-        .unwrap_or(Map::new(&env))
-        .get(denom.clone())
-        .unwrap_or(0_u128);
+        .unwrap_or(Map::new(&env));
+    let current_mm_token_balance: u128 = user_mm_balance_map.get(denom.clone()).unwrap_or(0_u128);
+    user_mm_balance_map.set(denom.clone(), mm_token_balance);
+    env.storage().persistent().set(
+        &DataKey::UserMMTokenBalance(user.clone()),
+        &user_mm_balance_map,
+    );
+    env.storage().persistent().bump(
+        &DataKey::UserMMTokenBalance(user),
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+
+    let total_mm_token_supply: u128 = get_total_mm_token_supply(env.clone(), denom.clone())
+        .checked_sub(current_mm_token_balance)
+        .unwrap_or_default()
+        + mm_token_balance;
+    set_total_mm_token_supply(env, denom, total_mm_token_supply);
+}
+
+pub fn get_total_mm_token_supply(env: Env, denom: Symbol) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::TotalMMTokenSupply)
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u128)
+}
+
+pub fn set_total_mm_token_supply(env: Env, denom: Symbol, total_mm_token_supply: u128) {
+    let mut total_mm_token_supply_map: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::TotalMMTokenSupply)
+        .unwrap_or(Map::new(&env));
+    total_mm_token_supply_map.set(denom, total_mm_token_supply);
+    env.storage()
+        .persistent()
+        .set(&DataKey::TotalMMTokenSupply, &total_mm_token_supply_map);
+    env.storage().persistent().bump(
+        &DataKey::TotalMMTokenSupply,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_deposit(env: Env, user: Address, denom: Symbol) -> u128 {
+    // calculates user deposit including deposit interest
+    let token_decimals = get_token_decimal(env.clone(), denom.clone());
+
+    let user_mm_token_balance: u128 =
+        get_mm_token_balance(env.clone(), user.clone(), denom.clone());
 
     let mm_token_price = get_mm_token_price(env.clone(), denom.clone());
 
@@ -101,21 +519,33 @@ pub fn get_deposit(env: Env, user: Address, denom: Symbol) -> u128 {
                 token_decimals,
             ))
             .to_u128_with_decimals(token_decimals)
-            .unwrap();
+            .unwrap_or_overflow(&env);
 
     user_token_balance
 }
 
+pub fn set_deposit(env: Env, user: Address, denom: Symbol, token_amount: u128) {
+    // stores the user deposit as mm tokens at the current mm token price
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+    let mm_token_price: u128 = get_mm_token_price(env.clone(), denom.clone());
+
+    let mm_token_balance: u128 =
+        Decimal::from_i128_with_scale(token_amount as i128, token_decimals)
+            .div(Decimal::from_i128_with_scale(
+                mm_token_price as i128,
+                token_decimals,
+            ))
+            .to_u128_with_decimals(token_decimals)
+            .unwrap_or_overflow(&env);
+
+    set_mm_token_balance(env, user, denom, mm_token_balance);
+}
+
 pub fn get_available_liquidity_by_token(env: Env, denom: Symbol) -> u128 {
     let contract_address = get_vault_contract(&env);
-    let token_info: Map<Symbol, TokenInfo> = env
-        .storage()
-        .persistent()
-        .get(&DataKey::SupportedTokensInfo)
-        .unwrap_or(Map::new(&env));
     token_balance(
         &env,
-        &token_info.get(denom).unwrap().address,
+        &get_token_address(env.clone(), denom),
         &contract_address,
     ) as u128
 }
@@ -126,11 +556,106 @@ pub fn get_total_borrow_data(env: Env, denom: Symbol) -> TotalBorrowData {
         .persistent()
         .get(&DataKey::TotalBorrowData)
         .unwrap_or(Map::new(&env));
-    total_borrow_data.get(denom).unwrap()
+    total_borrow_data
+        .get(denom)
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::UnknownDenom))
+}
+
+pub fn set_total_borrow_data(env: Env, denom: Symbol, total_borrow_data: TotalBorrowData) {
+    let mut total_borrow_map: Map<Symbol, TotalBorrowData> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::TotalBorrowData)
+        .unwrap_or(Map::new(&env));
+    total_borrow_map.set(denom, total_borrow_data);
+    env.storage()
+        .persistent()
+        .set(&DataKey::TotalBorrowData, &total_borrow_map);
+    env.storage().persistent().bump(
+        &DataKey::TotalBorrowData,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_total_stable_borrow_data(env: Env, denom: Symbol) -> TotalStableBorrowData {
+    env.storage()
+        .persistent()
+        .get(&DataKey::TotalStableBorrowData)
+        .unwrap_or(Map::<Symbol, TotalStableBorrowData>::new(&env))
+        .get(denom.clone())
+        .unwrap_or(TotalStableBorrowData {
+            denom,
+            total_borrowed_amount: 0_u128,
+            average_interest_rate: 0_u128,
+            timestamp: env.ledger().timestamp(),
+        })
+}
+
+pub fn set_total_stable_borrow_data(
+    env: Env,
+    denom: Symbol,
+    total_stable_borrow_data: TotalStableBorrowData,
+) {
+    let mut total_stable_borrow_map: Map<Symbol, TotalStableBorrowData> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::TotalStableBorrowData)
+        .unwrap_or(Map::new(&env));
+    total_stable_borrow_map.set(denom, total_stable_borrow_data);
+    env.storage()
+        .persistent()
+        .set(&DataKey::TotalStableBorrowData, &total_stable_borrow_map);
+    env.storage().persistent().bump(
+        &DataKey::TotalStableBorrowData,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_total_stable_borrowed_by_token(env: Env, denom: Symbol) -> u128 {
+    let total_stable_borrow_data: TotalStableBorrowData =
+        get_total_stable_borrow_data(env.clone(), denom.clone());
+
+    calc_borrow_amount_with_interest(
+        env.clone(),
+        total_stable_borrow_data.total_borrowed_amount,
+        total_stable_borrow_data.average_interest_rate,
+        (env.ledger().timestamp() - total_stable_borrow_data.timestamp) as u128,
+        get_token_decimal(env, denom),
+    )
+}
+
+pub fn get_indexed_variable_borrowed_by_token(env: Env, denom: Symbol) -> u128 {
+    // variable borrow as of the last borrow index update
+    let total_borrow_data: TotalBorrowData = get_total_borrow_data(env.clone(), denom.clone());
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom);
+
+    calc_amount_from_scaled(
+        env,
+        total_borrow_data.total_scaled_borrowed_amount,
+        total_borrow_data.borrow_index_ln,
+        token_decimals,
+    )
+}
+
+pub fn get_indexed_total_borrowed_by_token(env: Env, denom: Symbol) -> u128 {
+    // variable and stable borrow as of the last borrow index update
+    get_indexed_variable_borrowed_by_token(env.clone(), denom.clone())
+        + get_total_stable_borrow_data(env, denom).total_borrowed_amount
 }
 
 pub fn get_interest_rate(env: Env, denom: Symbol) -> u128 {
-    let utilization_rate = get_utilization_rate_by_token(env.clone(), denom.clone());
+    // the borrow rate accruing on the borrow index, the utilization is taken with the total
+    // borrow as of the last index update so that the accrual doesn't depend on itself
+    let borrowed_by_token: u128 = get_indexed_total_borrowed_by_token(env.clone(), denom.clone());
+    let reserves_by_token: u128 =
+        get_available_liquidity_by_token(env.clone(), denom.clone()) + borrowed_by_token;
+
+    let mut utilization_rate: u128 = 0_u128;
+    if reserves_by_token != 0 {
+        utilization_rate = borrowed_by_token * HUNDRED_PERCENT / reserves_by_token;
+    }
 
     let token_interest: TokenInterestRateModelParams = env
         .storage()
@@ -138,9 +663,9 @@ pub fn get_interest_rate(env: Env, denom: Symbol) -> u128 {
         .get::<DataKey, Map<Symbol, TokenInterestRateModelParams>>(
             &DataKey::TokensInterestRateModelParams,
         )
-        .unwrap()
+        .unwrap_or(Map::new(&env))
         .get(denom.clone())
-        .unwrap();
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::UnknownDenom));
 
     let min_interest_rate: u128 = token_interest.min_interest_rate;
     let safe_borrow_max_rate: u128 = token_interest.safe_borrow_max_rate;
@@ -158,22 +683,23 @@ pub fn get_interest_rate(env: Env, denom: Symbol) -> u128 {
     }
 }
 
-pub fn get_token_decimal(env: Env, denom: Symbol) -> u32 {
+pub fn get_token_info(env: Env, denom: Symbol) -> TokenInfo {
     let token_info: Map<Symbol, TokenInfo> = env
         .storage()
         .persistent()
         .get(&DataKey::SupportedTokensInfo)
-        .unwrap();
-    token_info.get(denom).unwrap().decimals
+        .unwrap_or(Map::new(&env));
+    token_info
+        .get(denom)
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::UnknownDenom))
+}
+
+pub fn get_token_decimal(env: Env, denom: Symbol) -> u32 {
+    get_token_info(env, denom).decimals
 }
 
 pub fn get_token_address(env: Env, denom: Symbol) -> Address {
-    let token_info: Map<Symbol, TokenInfo> = env
-        .storage()
-        .persistent()
-        .get(&DataKey::SupportedTokensInfo)
-        .unwrap();
-    token_info.get(denom).unwrap().address
+    get_token_info(env, denom).address
 }
 
 pub fn get_supported_tokens(env: Env) -> Vec<Symbol> {
@@ -188,12 +714,13 @@ pub fn get_total_borrowed_by_token(env: Env, denom: Symbol) -> u128 {
 
     let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
 
-    let total_borrowed_amount_with_interest: u128 = calc_borrow_amount_with_interest(
-        total_borrow_data.total_borrowed_amount,
-        total_borrow_data.average_interest_rate,
-        (env.ledger().timestamp() - total_borrow_data.timestamp) as u128,
-        token_decimals,
-    );
+    let total_borrowed_amount_with_interest: u128 =
+        calc_amount_from_scaled(
+            env.clone(),
+            total_borrow_data.total_scaled_borrowed_amount,
+            get_current_borrow_index_ln(env.clone(), denom.clone()),
+            token_decimals,
+        ) + get_total_stable_borrowed_by_token(env, denom);
 
     total_borrowed_amount_with_interest
 }
@@ -209,25 +736,20 @@ pub fn get_user_max_allowed_borrow_amount_usd(env: Env, user: Address) -> u128 {
         if use_user_deposit_as_collateral {
             let user_deposit: u128 = get_deposit(env.clone(), user.clone(), token.clone());
 
-            let reserve_configuration: ReserveConfiguration = env
-                .storage()
-                .persistent()
-                .get::<DataKey, Map<Symbol, ReserveConfiguration>>(&DataKey::ReserveConfiguration)
-                .unwrap()
-                .get(token.clone())
-                .unwrap();
+            let reserve_configuration: ReserveConfiguration =
+                get_user_reserve_configuration(env.clone(), user.clone(), token.clone());
 
             let loan_to_value_ratio: u128 = reserve_configuration.loan_to_value_ratio;
 
             let token_decimals: u32 = get_token_decimal(env.clone(), token.clone());
 
-            let price: u128 = fetch_price_by_token(env.clone(), token.clone());
+            let price: u128 = fetch_user_price_by_token(env.clone(), user.clone(), token.clone());
 
             let user_deposit_usd: u128 =
                 Decimal::from_i128_with_scale(user_deposit as i128, token_decimals)
                     .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
                     .to_u128_with_decimals(USD_DECIMALS)
-                    .unwrap();
+                    .unwrap_or_overflow(&env);
 
             max_allowed_borrow_amount_usd +=
                 user_deposit_usd * loan_to_value_ratio / HUNDRED_PERCENT;
@@ -256,10 +778,36 @@ pub fn get_reserve_configuration(env: Env, denom: Symbol) -> ReserveConfiguratio
         .get(&DataKey::ReserveConfiguration)
         .unwrap_or(Map::new(&env))
         .get(denom.clone())
-        .unwrap();
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::UnknownDenom));
     reserve_configuration
 }
 
+pub fn get_supply_headroom(env: Env, denom: Symbol) -> u128 {
+    // amount of tokens that can still be deposited, u128::MAX without a supply cap
+    let supply_cap: u128 = get_reserve_configuration(env.clone(), denom.clone()).supply_cap;
+
+    if supply_cap == 0 {
+        return u128::MAX;
+    }
+
+    supply_cap
+        .checked_sub(get_total_reserves_by_token(env, denom))
+        .unwrap_or_default()
+}
+
+pub fn get_borrow_headroom(env: Env, denom: Symbol) -> u128 {
+    // amount of tokens that can still be borrowed, u128::MAX without a borrow cap
+    let borrow_cap: u128 = get_reserve_configuration(env.clone(), denom.clone()).borrow_cap;
+
+    if borrow_cap == 0 {
+        return u128::MAX;
+    }
+
+    borrow_cap
+        .checked_sub(get_total_borrowed_by_token(env, denom))
+        .unwrap_or_default()
+}
+
 pub fn get_user_utilization_rate(env: Env, user: Address) -> u128 {
     let sum_collateral_balance_usd: u128 = get_user_collateral_usd(env.clone(), user.clone());
 
@@ -273,6 +821,7 @@ pub fn get_user_utilization_rate(env: Env, user: Address) -> u128 {
 }
 
 pub fn calc_borrow_amount_with_interest(
+    env: Env,
     borrowed_amount: u128,
     interest_rate: u128,
     interval: u128,
@@ -292,180 +841,1636 @@ pub fn calc_borrow_amount_with_interest(
         Decimal::from_i128_with_scale(borrowed_amount as i128, token_decimals)
             .mul(base.powd(exponent))
             .to_u128_with_decimals(token_decimals)
-            .unwrap();
+            .unwrap_or_overflow(&env);
 
     borrow_amount_with_interest
 }
 
-pub fn get_user_borrowing_info(env: Env, user: Address, denom: Symbol) -> UserBorrowingInfo {
-    let user_borrowing_info: UserBorrowingInfo = env
-        .storage()
-        .persistent()
-        .get(&DataKey::UserBorrowingInfo(user.clone()))
-        .unwrap_or(Map::new(&env))
-        .get(denom.clone())
-        .unwrap_or_default();
+pub fn calc_scaled_amount(env: Env, amount: u128, index_ln: u128, token_decimals: u32) -> u128 {
+    // amount divided by the index, rounded to the token decimals
+    Decimal::from_i128_with_scale(amount as i128, token_decimals)
+        .div(Decimal::from_i128_with_scale(index_ln as i128, INTEREST_RATE_DECIMALS).exp())
+        .round_dp(token_decimals)
+        .to_u128_with_decimals(token_decimals)
+        .unwrap_or_overflow(&env)
+}
+
+pub fn calc_amount_from_scaled(
+    env: Env,
+    scaled_amount: u128,
+    index_ln: u128,
+    token_decimals: u32,
+) -> u128 {
+    // scaled amount multiplied by the index, rounded to the token decimals
+    Decimal::from_i128_with_scale(scaled_amount as i128, token_decimals)
+        .mul(Decimal::from_i128_with_scale(index_ln as i128, INTEREST_RATE_DECIMALS).exp())
+        .round_dp(token_decimals)
+        .to_u128_with_decimals(token_decimals)
+        .unwrap_or_overflow(&env)
+}
+
+pub fn get_user_scaled_borrowed_amount(env: Env, user: Address, denom: Symbol) -> u128 {
+    let scaled_borrowed_amount: Option<u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::UserScaledBorrowedAmount(user.clone()))
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom.clone());
+
+    if let Some(scaled_borrowed_amount) = scaled_borrowed_amount {
+        return scaled_borrowed_amount;
+    }
+
+    // a borrow of storage version 1 accrued its fixed rate up to the migration, it follows the
+    // borrow index from there like the total borrow it was migrated with
+    let legacy_borrowing_info: UserBorrowingInfoV1 = env
+        .storage()
+        .persistent()
+        .get(&DataKey::UserBorrowingInfo(user))
+        .unwrap_or(Map::new(&env))
+        .get(denom.clone())
+        .unwrap_or_default();
+
+    if legacy_borrowing_info.borrowed_amount == 0 {
+        return 0_u128;
+    }
+
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let migration_timestamp: u64 = get_migration_v1_timestamp(&env);
+
+    let legacy_borrow_amount_with_interest: u128 = calc_borrow_amount_with_interest(
+        env.clone(),
+        legacy_borrowing_info.borrowed_amount,
+        legacy_borrowing_info.average_interest_rate,
+        migration_timestamp
+            .checked_sub(legacy_borrowing_info.timestamp)
+            .unwrap_or_default() as u128,
+        token_decimals,
+    );
+
+    // the borrow index was 1 at the migration
+    calc_scaled_amount(
+        env,
+        legacy_borrow_amount_with_interest,
+        0_u128,
+        token_decimals,
+    )
+}
+
+pub fn set_user_scaled_borrowed_amount(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+    scaled_borrowed_amount: u128,
+) {
+    let mut scaled_borrowed_map: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::UserScaledBorrowedAmount(user.clone()))
+        .unwrap_or(Map::new(&env));
+    scaled_borrowed_map.set(denom.clone(), scaled_borrowed_amount);
+    env.storage().persistent().set(
+        &DataKey::UserScaledBorrowedAmount(user.clone()),
+        &scaled_borrowed_map,
+    );
+    env.storage().persistent().bump(
+        &DataKey::UserScaledBorrowedAmount(user.clone()),
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+
+    // dropping the converted borrow of storage version 1
+    let legacy_borrow_map: Option<Map<Symbol, UserBorrowingInfoV1>> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::UserBorrowingInfo(user.clone()));
+
+    if let Some(mut legacy_borrow_map) = legacy_borrow_map {
+        legacy_borrow_map.remove(denom);
+        if legacy_borrow_map.is_empty() {
+            env.storage()
+                .persistent()
+                .remove(&DataKey::UserBorrowingInfo(user));
+        } else {
+            env.storage()
+                .persistent()
+                .set(&DataKey::UserBorrowingInfo(user), &legacy_borrow_map);
+        }
+    }
+}
+
+pub fn get_user_variable_borrow_amount_with_interest(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+) -> u128 {
+    let scaled_borrowed_amount: u128 =
+        get_user_scaled_borrowed_amount(env.clone(), user.clone(), denom.clone());
+
+    let token_decimals = get_token_decimal(env.clone(), denom.clone());
+
+    let borrow_amount_with_interest = calc_amount_from_scaled(
+        env.clone(),
+        scaled_borrowed_amount,
+        get_current_borrow_index_ln(env.clone(), denom),
+        token_decimals,
+    );
+
+    borrow_amount_with_interest
+}
+
+pub fn get_user_stable_borrow_data(env: Env, user: Address, denom: Symbol) -> StableBorrowData {
+    env.storage()
+        .persistent()
+        .get(&DataKey::UserStableBorrowData(user))
+        .unwrap_or(Map::<Symbol, StableBorrowData>::new(&env))
+        .get(denom)
+        .unwrap_or_default()
+}
+
+pub fn set_user_stable_borrow_data(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+    stable_borrow_data: StableBorrowData,
+) {
+    let mut stable_borrow_map: Map<Symbol, StableBorrowData> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::UserStableBorrowData(user.clone()))
+        .unwrap_or(Map::new(&env));
+    stable_borrow_map.set(denom, stable_borrow_data);
+    env.storage().persistent().set(
+        &DataKey::UserStableBorrowData(user.clone()),
+        &stable_borrow_map,
+    );
+    env.storage().persistent().bump(
+        &DataKey::UserStableBorrowData(user),
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_user_stable_borrow_amount_with_interest(env: Env, user: Address, denom: Symbol) -> u128 {
+    let stable_borrow_data: StableBorrowData =
+        get_user_stable_borrow_data(env.clone(), user, denom.clone());
+
+    if stable_borrow_data.borrowed_amount == 0 {
+        return 0_u128;
+    }
+
+    calc_borrow_amount_with_interest(
+        env.clone(),
+        stable_borrow_data.borrowed_amount,
+        stable_borrow_data.interest_rate,
+        (env.ledger().timestamp() - stable_borrow_data.timestamp) as u128,
+        get_token_decimal(env, denom),
+    )
+}
+
+pub fn get_user_borrow_amount_with_interest(env: Env, user: Address, denom: Symbol) -> u128 {
+    // variable and stable borrow of the user
+    get_user_variable_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone())
+        + get_user_stable_borrow_amount_with_interest(env, user, denom)
+}
+
+pub fn get_total_reserves_by_token(env: Env, denom: Symbol) -> u128 {
+    let token_liquidity: u128 = get_available_liquidity_by_token(env.clone(), denom.clone());
+    let borrowed_by_token: u128 = get_total_borrowed_by_token(env.clone(), denom.clone());
+    token_liquidity + borrowed_by_token
+}
+
+pub fn get_liquidity_rate(env: Env, denom: Symbol) -> u128 {
+    // the deposit rate, the reserve factor share of the interest goes to the protocol reserves
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let total_stable_borrow_data: TotalStableBorrowData =
+        get_total_stable_borrow_data(env.clone(), denom.clone());
+
+    let expected_annual_interest_income: u128 = (Decimal::from_i128_with_scale(
+        get_indexed_variable_borrowed_by_token(env.clone(), denom.clone()) as i128,
+        token_decimals,
+    )
+    .mul(Decimal::from_i128_with_scale(
+        (get_interest_rate(env.clone(), denom.clone()) / HUNDRED) as i128,
+        INTEREST_RATE_DECIMALS,
+    ))
+    .add(
+        Decimal::from_i128_with_scale(
+            total_stable_borrow_data.total_borrowed_amount as i128,
+            token_decimals,
+        )
+        .mul(Decimal::from_i128_with_scale(
+            (total_stable_borrow_data.average_interest_rate / HUNDRED) as i128,
+            INTEREST_RATE_DECIMALS,
+        )),
+    ))
+    .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+    .unwrap_or_overflow(&env)
+        * (HUNDRED_PERCENT - get_reserve_factor(env.clone(), denom.clone()))
+        / HUNDRED_PERCENT;
+
+    let reserves_by_token: u128 = get_total_reserves_by_token(env.clone(), denom.clone())
+        .checked_sub(get_protocol_reserves(env.clone(), denom.clone()))
+        .unwrap_or_default();
+
+    if reserves_by_token == 0 {
+        0u128
+    } else {
+        let liquidity_rate: u128 = Decimal::from_i128_with_scale(
+            expected_annual_interest_income as i128,
+            INTEREST_RATE_DECIMALS,
+        )
+        .mul(Decimal::from_i128_with_scale(HUNDRED as i128, 0u32))
+        .div(Decimal::from_i128_with_scale(
+            reserves_by_token as i128,
+            token_decimals,
+        ))
+        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+        .unwrap_or_overflow(&env);
+
+        liquidity_rate
+    }
+}
+
+pub fn get_reserve_factor(env: Env, denom: Symbol) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::ReserveFactor)
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u128)
+}
+
+pub fn set_reserve_factor(env: Env, denom: Symbol, reserve_factor: u128) {
+    let mut reserve_factors: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::ReserveFactor)
+        .unwrap_or(Map::new(&env));
+    reserve_factors.set(denom, reserve_factor);
+    env.storage()
+        .persistent()
+        .set(&DataKey::ReserveFactor, &reserve_factors);
+    env.storage().persistent().bump(
+        &DataKey::ReserveFactor,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_protocol_reserves(env: Env, denom: Symbol) -> u128 {
+    // protocol reserves including the interest share accrued since the last index update
+    let protocol_reserves: u128 = env
+        .storage()
+        .persistent()
+        .get(&DataKey::ProtocolReserves)
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom.clone())
+        .unwrap_or(0_u128);
+
+    let reserve_factor: u128 = get_reserve_factor(env.clone(), denom.clone());
+
+    if reserve_factor == 0 {
+        return protocol_reserves;
+    }
+
+    // the reserve factor share of the borrow interest accrued since the last index update
+    let accrued_interest: u128 = get_total_borrowed_by_token(env.clone(), denom.clone())
+        .checked_sub(get_indexed_total_borrowed_by_token(
+            env.clone(),
+            denom.clone(),
+        ))
+        .unwrap_or_default();
+
+    protocol_reserves + accrued_interest * reserve_factor / HUNDRED_PERCENT
+}
+
+pub fn set_protocol_reserves(env: Env, denom: Symbol, protocol_reserves: u128) {
+    let mut protocol_reserves_map: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::ProtocolReserves)
+        .unwrap_or(Map::new(&env));
+    protocol_reserves_map.set(denom, protocol_reserves);
+    env.storage()
+        .persistent()
+        .set(&DataKey::ProtocolReserves, &protocol_reserves_map);
+    env.storage().persistent().bump(
+        &DataKey::ProtocolReserves,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_current_liquidity_index_ln(env: Env, denom: Symbol) -> u128 {
+    let liquidity_rate: u128 = get_liquidity_rate(env.clone(), denom.clone());
+    let liquidity_index_data: LiquidityIndexData = env
+        .storage()
+        .persistent()
+        .get(&DataKey::LiquidityIndexData)
+        .unwrap_or(Map::new(&env))
+        .get(denom.clone())
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::UnknownDenom));
+
+    let liquidity_index_last_update: u64 = liquidity_index_data.timestamp;
+
+    let liquidity_index_ln: u128 = liquidity_index_data.liquidity_index_ln;
+
+    let new_liquidity_index_ln: u128 = ((env.ledger().timestamp())
+        .checked_sub(liquidity_index_last_update)
+        .unwrap_or_default()) as u128
+        * Decimal::from_i128_with_scale(
+            (liquidity_rate / HUNDRED + INTEREST_RATE_MULTIPLIER) as i128,
+            INTEREST_RATE_DECIMALS,
+        )
+        .ln()
+        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+        .unwrap_or_overflow(&env)
+        / YEAR_IN_SECONDS
+        + liquidity_index_ln;
+
+    new_liquidity_index_ln
+}
+
+pub fn get_current_borrow_index_ln(env: Env, denom: Symbol) -> u128 {
+    let interest_rate: u128 = get_interest_rate(env.clone(), denom.clone());
+    let total_borrow_data: TotalBorrowData = get_total_borrow_data(env.clone(), denom.clone());
+
+    let new_borrow_index_ln: u128 = ((env.ledger().timestamp())
+        .checked_sub(total_borrow_data.timestamp)
+        .unwrap_or_default()) as u128
+        * Decimal::from_i128_with_scale(
+            (interest_rate / HUNDRED + INTEREST_RATE_MULTIPLIER) as i128,
+            INTEREST_RATE_DECIMALS,
+        )
+        .ln()
+        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+        .unwrap_or_overflow(&env)
+        / YEAR_IN_SECONDS
+        + total_borrow_data.borrow_index_ln;
+
+    new_borrow_index_ln
+}
+
+pub fn execute_update_liquidity_index_data(env: Env, denom: Symbol) {
+    // updates the liquidity index together with the borrow index
+    let current_liquidity_index_ln = get_current_liquidity_index_ln(env.clone(), denom.clone());
+
+    // accruing the protocol share up to the new index timestamp
+    if get_reserve_factor(env.clone(), denom.clone()) != 0 {
+        let protocol_reserves: u128 = get_protocol_reserves(env.clone(), denom.clone());
+        set_protocol_reserves(env.clone(), denom.clone(), protocol_reserves);
+    }
+
+    let new_liquidity_index_data = LiquidityIndexData {
+        denom: denom.clone(),
+        liquidity_index_ln: current_liquidity_index_ln,
+        timestamp: env.ledger().timestamp(),
+    };
+
+    let mut liquidity_map: Map<Symbol, LiquidityIndexData> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::LiquidityIndexData)
+        .unwrap_or(Map::new(&env));
+    liquidity_map.set(denom.clone(), new_liquidity_index_data);
+    env.storage()
+        .persistent()
+        .set(&DataKey::LiquidityIndexData, &liquidity_map);
+    env.storage().persistent().bump(
+        &DataKey::LiquidityIndexData,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+
+    let total_borrow_data: TotalBorrowData = get_total_borrow_data(env.clone(), denom.clone());
+    let total_stable_borrowed_amount: u128 =
+        get_total_stable_borrowed_by_token(env.clone(), denom.clone());
+    set_total_borrow_data(
+        env.clone(),
+        denom.clone(),
+        TotalBorrowData {
+            borrow_index_ln: get_current_borrow_index_ln(env.clone(), denom.clone()),
+            timestamp: env.ledger().timestamp(),
+            ..total_borrow_data
+        },
+    );
+
+    // the stable borrows are accrued up to the same timestamp
+    let total_stable_borrow_data: TotalStableBorrowData =
+        get_total_stable_borrow_data(env.clone(), denom.clone());
+    set_total_stable_borrow_data(
+        env.clone(),
+        denom,
+        TotalStableBorrowData {
+            total_borrowed_amount: total_stable_borrowed_amount,
+            timestamp: env.ledger().timestamp(),
+            ..total_stable_borrow_data
+        },
+    );
+}
+
+pub fn execute_borrow(env: Env, user: Address, denom: Symbol, amount: u128) {
+    // adds the amount to the user and total borrows at the updated borrow index
+    let total_borrow_data: TotalBorrowData = get_total_borrow_data(env.clone(), denom.clone());
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let scaled_amount: u128 = calc_scaled_amount(
+        env.clone(),
+        amount,
+        total_borrow_data.borrow_index_ln,
+        token_decimals,
+    );
+
+    let user_scaled_borrowed_amount: u128 =
+        get_user_scaled_borrowed_amount(env.clone(), user.clone(), denom.clone());
+    set_user_scaled_borrowed_amount(
+        env.clone(),
+        user,
+        denom.clone(),
+        user_scaled_borrowed_amount + scaled_amount,
+    );
+
+    set_total_borrow_data(
+        env,
+        denom,
+        TotalBorrowData {
+            total_scaled_borrowed_amount: total_borrow_data.total_scaled_borrowed_amount
+                + scaled_amount,
+            ..total_borrow_data
+        },
+    );
+}
+
+pub fn calc_weighted_interest_rate(
+    env: Env,
+    amount: u128,
+    interest_rate: u128,
+    added_amount: u128,
+    added_interest_rate: u128,
+    token_decimals: u32,
+) -> u128 {
+    // interest rate of the sum of two amounts, weighted by the amounts
+    if amount + added_amount == 0 {
+        return 0_u128;
+    }
+
+    Decimal::from_i128_with_scale(amount as i128, token_decimals)
+        .mul(Decimal::from_i128_with_scale(
+            interest_rate as i128,
+            INTEREST_RATE_DECIMALS,
+        ))
+        .add(
+            Decimal::from_i128_with_scale(added_amount as i128, token_decimals).mul(
+                Decimal::from_i128_with_scale(added_interest_rate as i128, INTEREST_RATE_DECIMALS),
+            ),
+        )
+        .div(Decimal::from_i128_with_scale(
+            (amount + added_amount) as i128,
+            token_decimals,
+        ))
+        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+        .unwrap_or_overflow(&env)
+}
+
+pub fn get_stable_rate_premium(env: Env, denom: Symbol) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::StableRatePremium)
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u128)
+}
+
+pub fn set_stable_rate_premium(env: Env, denom: Symbol, stable_rate_premium: u128) {
+    let mut stable_rate_premiums: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::StableRatePremium)
+        .unwrap_or(Map::new(&env));
+    stable_rate_premiums.set(denom, stable_rate_premium);
+    env.storage()
+        .persistent()
+        .set(&DataKey::StableRatePremium, &stable_rate_premiums);
+    env.storage().persistent().bump(
+        &DataKey::StableRatePremium,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_stable_interest_rate(env: Env, denom: Symbol) -> u128 {
+    // rate of a new stable borrow, the variable rate of the market plus its premium
+    get_interest_rate(env.clone(), denom.clone()) + get_stable_rate_premium(env, denom)
+}
+
+pub fn execute_stable_borrow(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+    amount: u128,
+    interest_rate: u128,
+) {
+    // adds the amount at the stable interest_rate to the user and total stable borrows
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let stable_borrow_data: StableBorrowData =
+        get_user_stable_borrow_data(env.clone(), user.clone(), denom.clone());
+    let user_stable_borrowed_amount: u128 =
+        get_user_stable_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
+
+    set_user_stable_borrow_data(
+        env.clone(),
+        user,
+        denom.clone(),
+        StableBorrowData {
+            borrowed_amount: user_stable_borrowed_amount + amount,
+            interest_rate: calc_weighted_interest_rate(
+                env.clone(),
+                user_stable_borrowed_amount,
+                stable_borrow_data.interest_rate,
+                amount,
+                interest_rate,
+                token_decimals,
+            ),
+            timestamp: env.ledger().timestamp(),
+        },
+    );
+
+    let total_stable_borrow_data: TotalStableBorrowData =
+        get_total_stable_borrow_data(env.clone(), denom.clone());
+    let total_stable_borrowed_amount: u128 =
+        get_total_stable_borrowed_by_token(env.clone(), denom.clone());
+
+    set_total_stable_borrow_data(
+        env.clone(),
+        denom.clone(),
+        TotalStableBorrowData {
+            denom,
+            total_borrowed_amount: total_stable_borrowed_amount + amount,
+            average_interest_rate: calc_weighted_interest_rate(
+                env.clone(),
+                total_stable_borrowed_amount,
+                total_stable_borrow_data.average_interest_rate,
+                amount,
+                interest_rate,
+                token_decimals,
+            ),
+            timestamp: env.ledger().timestamp(),
+        },
+    );
+}
+
+pub fn execute_repay_stable_borrow(env: Env, user: Address, denom: Symbol, repay_amount: u128) {
+    // removes the repaid amount at the user stable rate from the user and total stable borrows
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let stable_borrow_data: StableBorrowData =
+        get_user_stable_borrow_data(env.clone(), user.clone(), denom.clone());
+    let user_stable_borrowed_amount: u128 =
+        get_user_stable_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
+
+    let mut repaid_amount: u128 = repay_amount;
+    let mut interest_rate: u128 = stable_borrow_data.interest_rate;
+    if repaid_amount >= user_stable_borrowed_amount {
+        repaid_amount = user_stable_borrowed_amount;
+        interest_rate = 0_u128;
+    }
+
+    set_user_stable_borrow_data(
+        env.clone(),
+        user,
+        denom.clone(),
+        StableBorrowData {
+            borrowed_amount: user_stable_borrowed_amount - repaid_amount,
+            interest_rate,
+            timestamp: env.ledger().timestamp(),
+        },
+    );
+
+    let total_stable_borrow_data: TotalStableBorrowData =
+        get_total_stable_borrow_data(env.clone(), denom.clone());
+    let total_stable_borrowed_amount: u128 =
+        get_total_stable_borrowed_by_token(env.clone(), denom.clone());
+    let new_total_stable_borrowed_amount: u128 = total_stable_borrowed_amount
+        .checked_sub(repaid_amount)
+        .unwrap_or_default();
+
+    // the user rate is taken out of the average, the rounding drift can't make it negative
+    let mut average_interest_rate: u128 = 0_u128;
+    if new_total_stable_borrowed_amount != 0 {
+        average_interest_rate =
+            Decimal::from_i128_with_scale(total_stable_borrowed_amount as i128, token_decimals)
+                .mul(Decimal::from_i128_with_scale(
+                    total_stable_borrow_data.average_interest_rate as i128,
+                    INTEREST_RATE_DECIMALS,
+                ))
+                .checked_sub(
+                    Decimal::from_i128_with_scale(repaid_amount as i128, token_decimals).mul(
+                        Decimal::from_i128_with_scale(
+                            stable_borrow_data.interest_rate as i128,
+                            INTEREST_RATE_DECIMALS,
+                        ),
+                    ),
+                )
+                .unwrap_or_default()
+                .max(Decimal::ZERO)
+                .div(Decimal::from_i128_with_scale(
+                    new_total_stable_borrowed_amount as i128,
+                    token_decimals,
+                ))
+                .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+                .unwrap_or_overflow(&env);
+    }
+
+    set_total_stable_borrow_data(
+        env.clone(),
+        denom.clone(),
+        TotalStableBorrowData {
+            denom,
+            total_borrowed_amount: new_total_stable_borrowed_amount,
+            average_interest_rate,
+            timestamp: env.ledger().timestamp(),
+        },
+    );
+}
+
+pub fn execute_rebalance_stable_borrow(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+    interest_rate: u128,
+) {
+    // moves the user stable borrow to the new interest_rate
+    let user_stable_borrowed_amount: u128 =
+        get_user_stable_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
+
+    execute_repay_stable_borrow(
+        env.clone(),
+        user.clone(),
+        denom.clone(),
+        user_stable_borrowed_amount,
+    );
+    execute_stable_borrow(env, user, denom, user_stable_borrowed_amount, interest_rate);
+}
+
+pub fn execute_repay_borrow(env: Env, user: Address, denom: Symbol, repay_amount: u128) {
+    // repays the variable borrow first and the stable borrow with the rest,
+    // repay_amount must not exceed the borrow with interest
+    execute_reduce_isolated_debt(env.clone(), user.clone(), denom.clone(), repay_amount);
+
+    let variable_borrow_amount: u128 =
+        get_user_variable_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
+
+    if repay_amount > variable_borrow_amount {
+        execute_repay_variable_borrow(
+            env.clone(),
+            user.clone(),
+            denom.clone(),
+            variable_borrow_amount,
+        );
+        execute_repay_stable_borrow(env, user, denom, repay_amount - variable_borrow_amount);
+    } else {
+        execute_repay_variable_borrow(env, user, denom, repay_amount);
+    }
+}
+
+pub fn execute_repay_variable_borrow(env: Env, user: Address, denom: Symbol, repay_amount: u128) {
+    // updates user and total borrow data at the updated borrow index,
+    // repay_amount must not exceed the variable borrow with interest
+    let total_borrow_data: TotalBorrowData = get_total_borrow_data(env.clone(), denom.clone());
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let user_scaled_borrowed_amount: u128 =
+        get_user_scaled_borrowed_amount(env.clone(), user.clone(), denom.clone());
+
+    let user_borrow_amount_with_interest: u128 = calc_amount_from_scaled(
+        env.clone(),
+        user_scaled_borrowed_amount,
+        total_borrow_data.borrow_index_ln,
+        token_decimals,
+    );
+
+    let mut repaid_scaled_amount: u128 = calc_scaled_amount(
+        env.clone(),
+        repay_amount,
+        total_borrow_data.borrow_index_ln,
+        token_decimals,
+    );
+    if repay_amount >= user_borrow_amount_with_interest
+        || repaid_scaled_amount > user_scaled_borrowed_amount
+    {
+        repaid_scaled_amount = user_scaled_borrowed_amount;
+    }
+
+    set_user_scaled_borrowed_amount(
+        env.clone(),
+        user,
+        denom.clone(),
+        user_scaled_borrowed_amount - repaid_scaled_amount,
+    );
+
+    set_total_borrow_data(
+        env,
+        denom,
+        TotalBorrowData {
+            total_scaled_borrowed_amount: total_borrow_data
+                .total_scaled_borrowed_amount
+                .checked_sub(repaid_scaled_amount)
+                .unwrap_or_default(),
+            ..total_borrow_data
+        },
+    );
+}
+
+pub fn execute_distribute_income(env: Env, denom: Symbol, income: u128, total_reserves: u128) {
+    // raising the liquidity index so that the income is shared among all depositors
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let mut liquidity_map: Map<Symbol, LiquidityIndexData> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::LiquidityIndexData)
+        .unwrap_or(Map::new(&env));
+    let liquidity_index_data: LiquidityIndexData = liquidity_map
+        .get(denom.clone())
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::UnknownDenom));
+
+    let income_liquidity_index_ln: u128 =
+        Decimal::from_i128_with_scale((total_reserves + income) as i128, token_decimals)
+            .div(Decimal::from_i128_with_scale(
+                total_reserves as i128,
+                token_decimals,
+            ))
+            .ln()
+            .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+            .unwrap_or_overflow(&env);
+
+    liquidity_map.set(
+        denom.clone(),
+        LiquidityIndexData {
+            denom: denom.clone(),
+            liquidity_index_ln: liquidity_index_data.liquidity_index_ln + income_liquidity_index_ln,
+            timestamp: liquidity_index_data.timestamp,
+        },
+    );
+    env.storage()
+        .persistent()
+        .set(&DataKey::LiquidityIndexData, &liquidity_map);
+    env.storage().persistent().bump(
+        &DataKey::LiquidityIndexData,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_liquidity_loss_ln(env: Env, denom: Symbol) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::LiquidityLossLn)
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0)
+}
+
+pub fn set_liquidity_loss_ln(env: Env, denom: Symbol, liquidity_loss_ln: u128) {
+    let mut liquidity_loss_map: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::LiquidityLossLn)
+        .unwrap_or(Map::new(&env));
+    liquidity_loss_map.set(denom, liquidity_loss_ln);
+    env.storage()
+        .persistent()
+        .set(&DataKey::LiquidityLossLn, &liquidity_loss_map);
+    env.storage().persistent().bump(
+        &DataKey::LiquidityLossLn,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn execute_socialize_loss(env: Env, denom: Symbol, loss: u128, total_reserves: u128) -> u128 {
+    // lowering the mm token price so that the loss is shared among all depositors,
+    // the price is kept above ten minimal units per mm token, the part that doesn't fit is returned
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let min_reserves: u128 = get_total_mm_token_supply(env.clone(), denom.clone()) * 10
+        / 10_u128.pow(token_decimals)
+        + 1;
+
+    if total_reserves <= min_reserves {
+        return loss;
+    }
+
+    let mut socialized_loss: u128 = loss;
+    if socialized_loss > total_reserves - min_reserves {
+        socialized_loss = total_reserves - min_reserves;
+    }
+
+    if socialized_loss == 0 {
+        return loss;
+    }
+
+    let loss_liquidity_index_ln: u128 =
+        Decimal::from_i128_with_scale(total_reserves as i128, token_decimals)
+            .div(Decimal::from_i128_with_scale(
+                (total_reserves - socialized_loss) as i128,
+                token_decimals,
+            ))
+            .ln()
+            .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
+            .unwrap_or_overflow(&env);
+
+    set_liquidity_loss_ln(
+        env.clone(),
+        denom.clone(),
+        get_liquidity_loss_ln(env, denom) + loss_liquidity_index_ln,
+    );
+
+    loss - socialized_loss
+}
+
+pub fn get_bad_debt(env: Env, denom: Symbol) -> BadDebtData {
+    env.storage()
+        .persistent()
+        .get(&DataKey::BadDebt)
+        .unwrap_or(Map::<Symbol, BadDebtData>::new(&env))
+        .get(denom)
+        .unwrap_or_default()
+}
+
+pub fn set_bad_debt(env: Env, denom: Symbol, bad_debt: BadDebtData) {
+    let mut bad_debt_map: Map<Symbol, BadDebtData> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::BadDebt)
+        .unwrap_or(Map::new(&env));
+    bad_debt_map.set(denom, bad_debt);
+    env.storage()
+        .persistent()
+        .set(&DataKey::BadDebt, &bad_debt_map);
+    env.storage().persistent().bump(
+        &DataKey::BadDebt,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn execute_write_off_bad_debt(env: Env, user: Address, denom: Symbol) -> (u128, u128, u128) {
+    // writes off the user borrow, covering it from the protocol reserves first
+    // returns the bad debt, the part covered by the reserves and the socialized part
+    execute_update_liquidity_index_data(env.clone(), denom.clone());
+
+    let bad_debt: u128 =
+        get_user_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
+
+    if bad_debt == 0 {
+        return (0, 0, 0);
+    }
+
+    // the value of all the deposits before the write-off
+    let protocol_reserves: u128 = get_protocol_reserves(env.clone(), denom.clone());
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+    let depositors_reserves: u128 = Decimal::from_i128_with_scale(
+        get_total_mm_token_supply(env.clone(), denom.clone()) as i128,
+        token_decimals,
+    )
+    .mul(Decimal::from_i128_with_scale(
+        get_mm_token_price(env.clone(), denom.clone()) as i128,
+        token_decimals,
+    ))
+    .to_u128_with_decimals(token_decimals)
+    .unwrap_or_overflow(&env);
+
+    execute_repay_borrow(env.clone(), user, denom.clone(), bad_debt);
+
+    let mut covered_by_reserves: u128 = bad_debt;
+    if covered_by_reserves > protocol_reserves {
+        covered_by_reserves = protocol_reserves;
+    }
+    set_protocol_reserves(
+        env.clone(),
+        denom.clone(),
+        protocol_reserves - covered_by_reserves,
+    );
+
+    let outstanding_bad_debt: u128 = execute_socialize_loss(
+        env.clone(),
+        denom.clone(),
+        bad_debt - covered_by_reserves,
+        depositors_reserves,
+    );
+
+    let bad_debt_data: BadDebtData = get_bad_debt(env.clone(), denom.clone());
+    set_bad_debt(
+        env,
+        denom,
+        BadDebtData {
+            total_bad_debt: bad_debt_data.total_bad_debt + bad_debt,
+            outstanding_bad_debt: bad_debt_data.outstanding_bad_debt + outstanding_bad_debt,
+        },
+    );
+
+    (
+        bad_debt,
+        covered_by_reserves,
+        bad_debt - covered_by_reserves - outstanding_bad_debt,
+    )
+}
+
+pub fn get_mm_token_price(env: Env, denom: Symbol) -> u128 {
+    // number of tokens that correspond to one mmToken
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+
+    let current_liquidity_index_ln: u128 =
+        get_current_liquidity_index_ln(env.clone(), denom.clone());
+
+    // socialized losses can take the price below its initial value
+    let liquidity_loss_ln: u128 = get_liquidity_loss_ln(env.clone(), denom.clone());
+
+    let mm_token_price = Decimal::from_i128_with_scale(
+        current_liquidity_index_ln as i128 - liquidity_loss_ln as i128,
+        INTEREST_RATE_DECIMALS,
+    )
+    .exp()
+    .to_u128_with_decimals(token_decimals)
+    .unwrap_or_overflow(&env);
+
+    // deposits and redemptions divide by the price
+    if mm_token_price == 0 {
+        panic_with_error!(&env, LendingError::ZeroMMTokenPrice);
+    }
+
+    mm_token_price
+}
+
+pub fn emit_mm_token_mint(env: Env, denom: Symbol, to: Address, amount: u128) {
+    // published by the mm token contract, so that its balances can be followed from its events
+    if amount == 0 {
+        return;
+    }
+    if let Some(mm_token) = get_mm_token(env.clone(), denom) {
+        MMTokenClient::new(&env, &mm_token).emit_mint(&to, &(amount as i128));
+    }
+}
+
+pub fn emit_mm_token_burn(env: Env, denom: Symbol, from: Address, amount: u128) {
+    if amount == 0 {
+        return;
+    }
+    if let Some(mm_token) = get_mm_token(env.clone(), denom) {
+        MMTokenClient::new(&env, &mm_token).emit_burn(&from, &(amount as i128));
+    }
+}
+
+pub fn user_deposit_as_collateral(env: Env, user: Address, denom: Symbol) -> bool {
+    let use_user_deposit_as_collateral: bool = env
+        .storage()
+        .persistent()
+        .get(&DataKey::UserDepositAsCollateral(user.clone()))
+        .unwrap_or(Map::new(&env))
+        .get(denom.clone())
+        .unwrap_or(false);
+
+    // // POC: Only xlm is used as a collateral
+    // let mut use_user_deposit_as_collateral: bool = false;
+    // if denom == symbol_short!("xlm") {
+    //     use_user_deposit_as_collateral = true;
+    // }
+
+    use_user_deposit_as_collateral
+}
+
+pub fn get_market_flags(env: Env, denom: Symbol) -> MarketFlags {
+    env.storage()
+        .persistent()
+        .get(&DataKey::MarketFlags)
+        .unwrap_or(Map::<Symbol, MarketFlags>::new(&env))
+        .get(denom)
+        .unwrap_or_default()
+}
+
+pub fn set_market_flags(env: Env, denom: Symbol, market_flags: MarketFlags) {
+    let mut market_flags_map: Map<Symbol, MarketFlags> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::MarketFlags)
+        .unwrap_or(Map::new(&env));
+    market_flags_map.set(denom, market_flags);
+    env.storage()
+        .persistent()
+        .set(&DataKey::MarketFlags, &market_flags_map);
+    env.storage().persistent().bump(
+        &DataKey::MarketFlags,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_emode_category(env: Env, category_id: u32) -> Option<EModeCategory> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::EModeCategories)
+        .unwrap_or(Map::<u32, EModeCategory>::new(&env))
+        .get(category_id)
+}
+
+pub fn set_emode_category(env: Env, category_id: u32, emode_category: EModeCategory) {
+    let mut emode_categories: Map<u32, EModeCategory> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::EModeCategories)
+        .unwrap_or(Map::new(&env));
+    emode_categories.set(category_id, emode_category);
+    env.storage()
+        .persistent()
+        .set(&DataKey::EModeCategories, &emode_categories);
+    env.storage().persistent().bump(
+        &DataKey::EModeCategories,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_market_emode_category(env: Env, denom: Symbol) -> u32 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::MarketEModeCategory)
+        .unwrap_or(Map::<Symbol, u32>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u32)
+}
+
+pub fn set_market_emode_category(env: Env, denom: Symbol, category_id: u32) {
+    let mut market_emode_categories: Map<Symbol, u32> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::MarketEModeCategory)
+        .unwrap_or(Map::new(&env));
+    if category_id != 0 {
+        market_emode_categories.set(denom, category_id);
+    } else {
+        market_emode_categories.remove(denom);
+    }
+    env.storage()
+        .persistent()
+        .set(&DataKey::MarketEModeCategory, &market_emode_categories);
+    env.storage().persistent().bump(
+        &DataKey::MarketEModeCategory,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_user_emode_category(env: Env, user: Address) -> u32 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::UserEModeCategory(user))
+        .unwrap_or(0_u32)
+}
+
+pub fn set_user_emode_category(env: Env, user: Address, category_id: u32) {
+    if category_id == 0 {
+        env.storage()
+            .persistent()
+            .remove(&DataKey::UserEModeCategory(user));
+        return;
+    }
+
+    env.storage()
+        .persistent()
+        .set(&DataKey::UserEModeCategory(user.clone()), &category_id);
+    env.storage().persistent().bump(
+        &DataKey::UserEModeCategory(user),
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn get_user_market_emode_category(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+) -> Option<EModeCategory> {
+    // the e-mode category of the user, if the market belongs to it
+    let category_id: u32 = get_user_emode_category(env.clone(), user);
+
+    if category_id == 0 || get_market_emode_category(env.clone(), denom) != category_id {
+        return None;
+    }
+
+    get_emode_category(env, category_id)
+}
+
+pub fn get_user_reserve_configuration(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+) -> ReserveConfiguration {
+    // the reserve configuration with the e-mode ratios applied for the user
+    let reserve_configuration: ReserveConfiguration =
+        get_reserve_configuration(env.clone(), denom.clone());
+
+    match get_user_market_emode_category(env, user, denom) {
+        Some(emode_category) => ReserveConfiguration {
+            loan_to_value_ratio: emode_category.loan_to_value_ratio,
+            liquidation_threshold: emode_category.liquidation_threshold,
+            ..reserve_configuration
+        },
+        None => reserve_configuration,
+    }
+}
+
+pub fn fetch_user_price_by_token(env: Env, user: Address, denom: Symbol) -> u128 {
+    // the shared price source of the user e-mode category takes precedence over the market price
+    match get_user_market_emode_category(env.clone(), user, denom.clone())
+        .and_then(|emode_category| emode_category.price_source)
+    {
+        Some(price_source) => fetch_price_by_token(env, price_source),
+        None => fetch_price_by_token(env, denom),
+    }
+}
+
+pub fn get_borrow_allowance(
+    env: Env,
+    delegator: Address,
+    delegatee: Address,
+    denom: Symbol,
+) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::BorrowAllowance(delegator, delegatee))
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u128)
+}
+
+pub fn set_borrow_allowance(
+    env: Env,
+    delegator: Address,
+    delegatee: Address,
+    denom: Symbol,
+    amount: u128,
+) {
+    let key = DataKey::BorrowAllowance(delegator, delegatee);
+    let mut borrow_allowances: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or(Map::new(&env));
+    borrow_allowances.set(denom, amount);
+    env.storage().persistent().set(&key, &borrow_allowances);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_debt_ceiling(env: Env, denom: Symbol) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::DebtCeiling)
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u128)
+}
+
+pub fn set_debt_ceiling(env: Env, denom: Symbol, debt_ceiling: u128) {
+    let mut debt_ceilings: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::DebtCeiling)
+        .unwrap_or(Map::new(&env));
+    debt_ceilings.set(denom, debt_ceiling);
+    env.storage()
+        .persistent()
+        .set(&DataKey::DebtCeiling, &debt_ceilings);
+    env.storage().persistent().bump(
+        &DataKey::DebtCeiling,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn is_market_isolated(env: Env, denom: Symbol) -> bool {
+    get_debt_ceiling(env, denom) != 0
+}
+
+pub fn get_isolated_debt(env: Env, denom: Symbol) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::IsolatedDebt)
+        .unwrap_or(Map::<Symbol, u128>::new(&env))
+        .get(denom)
+        .unwrap_or(0_u128)
+}
+
+pub fn set_isolated_debt(env: Env, denom: Symbol, isolated_debt: u128) {
+    let mut isolated_debts: Map<Symbol, u128> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::IsolatedDebt)
+        .unwrap_or(Map::new(&env));
+    isolated_debts.set(denom, isolated_debt);
+    env.storage()
+        .persistent()
+        .set(&DataKey::IsolatedDebt, &isolated_debts);
+    env.storage().persistent().bump(
+        &DataKey::IsolatedDebt,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
+
+pub fn is_borrowable_in_isolation(env: Env, denom: Symbol) -> bool {
+    env.storage()
+        .persistent()
+        .get(&DataKey::BorrowableInIsolation)
+        .unwrap_or(Map::<Symbol, bool>::new(&env))
+        .get(denom)
+        .unwrap_or(false)
+}
+
+pub fn set_borrowable_in_isolation(env: Env, denom: Symbol, borrowable: bool) {
+    let mut borrowable_in_isolation: Map<Symbol, bool> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::BorrowableInIsolation)
+        .unwrap_or(Map::new(&env));
+    if borrowable {
+        borrowable_in_isolation.set(denom, true);
+    } else {
+        borrowable_in_isolation.remove(denom);
+    }
+    env.storage()
+        .persistent()
+        .set(&DataKey::BorrowableInIsolation, &borrowable_in_isolation);
+    env.storage().persistent().bump(
+        &DataKey::BorrowableInIsolation,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
 
-    let mut average_interest_rate: u128 = user_borrowing_info.average_interest_rate;
-    let mut timestamp: u64 = user_borrowing_info.timestamp;
-    if user_borrowing_info.borrowed_amount == 0_u128 {
-        let current_interest_rate = get_interest_rate(env.clone(), denom.clone());
+pub fn get_user_isolated_collateral(env: Env, user: Address) -> Option<Symbol> {
+    // the isolated market the user has enabled as collateral, if any
+    get_supported_tokens(env.clone()).iter().find(|token| {
+        is_market_isolated(env.clone(), token.clone())
+            && user_deposit_as_collateral(env.clone(), user.clone(), token.clone())
+    })
+}
 
-        average_interest_rate = current_interest_rate;
-        timestamp = env.ledger().timestamp();
-    }
+pub fn calc_usd_value(env: Env, denom: Symbol, amount: u128) -> u128 {
+    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+    let price: u128 = fetch_price_by_token(env.clone(), denom);
+
+    Decimal::from_i128_with_scale(amount as i128, token_decimals)
+        .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
+        .to_u128_with_decimals(USD_DECIMALS)
+        .unwrap_or_overflow(&env)
+}
+
+pub fn get_user_isolated_debt(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+) -> Option<UserIsolatedDebtData> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::UserIsolatedDebt(user))
+        .unwrap_or(Map::<Symbol, UserIsolatedDebtData>::new(&env))
+        .get(denom)
+}
 
-    UserBorrowingInfo {
-        borrowed_amount: user_borrowing_info.borrowed_amount,
-        average_interest_rate: average_interest_rate,
-        timestamp: timestamp,
+pub fn set_user_isolated_debt(
+    env: Env,
+    user: Address,
+    denom: Symbol,
+    user_isolated_debt: Option<UserIsolatedDebtData>,
+) {
+    let key = DataKey::UserIsolatedDebt(user);
+    let mut user_isolated_debts: Map<Symbol, UserIsolatedDebtData> = env
+        .storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or(Map::new(&env));
+    match user_isolated_debt {
+        Some(user_isolated_debt) => user_isolated_debts.set(denom, user_isolated_debt),
+        None => {
+            user_isolated_debts.remove(denom);
+        }
     }
+    env.storage().persistent().set(&key, &user_isolated_debts);
+    env.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
 }
 
-pub fn get_user_borrow_amount_with_interest(env: Env, user: Address, denom: Symbol) -> u128 {
-    let current_borrowing_info = get_user_borrowing_info(env.clone(), user.clone(), denom.clone());
+pub fn execute_add_isolated_debt(env: Env, user: Address, denom: Symbol, amount: u128) {
+    // an isolated collateral only backs whitelisted markets, up to its debt ceiling
+    let isolated_collateral: Symbol = match get_user_isolated_collateral(env.clone(), user.clone())
+    {
+        Some(isolated_collateral) => isolated_collateral,
+        None => return,
+    };
 
-    let token_decimals = get_token_decimal(env.clone(), denom.clone());
+    if !is_borrowable_in_isolation(env.clone(), denom.clone()) {
+        panic_with_error!(&env, LendingError::NotBorrowableInIsolation);
+    }
 
-    let borrow_amount_with_interest = calc_borrow_amount_with_interest(
-        current_borrowing_info.borrowed_amount,
-        current_borrowing_info.average_interest_rate,
-        (env.ledger().timestamp() - current_borrowing_info.timestamp) as u128,
-        token_decimals,
-    );
+    // the borrow stays charged to the collateral that backed it first
+    let mut user_debt_usd: u128 = 0_u128;
+    if let Some(user_isolated_debt) =
+        get_user_isolated_debt(env.clone(), user.clone(), denom.clone())
+    {
+        if user_isolated_debt.isolated_collateral != isolated_collateral {
+            panic_with_error!(&env, LendingError::IsolatedCollateral);
+        }
+        user_debt_usd = user_isolated_debt.debt_usd;
+    }
 
-    borrow_amount_with_interest
+    let borrow_usd: u128 = calc_usd_value(env.clone(), denom.clone(), amount);
+
+    let isolated_debt: u128 =
+        get_isolated_debt(env.clone(), isolated_collateral.clone()) + borrow_usd;
+
+    if isolated_debt > get_debt_ceiling(env.clone(), isolated_collateral.clone()) {
+        panic_with_error!(&env, LendingError::DebtCeilingExceeded);
+    }
+
+    set_isolated_debt(env.clone(), isolated_collateral.clone(), isolated_debt);
+    set_user_isolated_debt(
+        env,
+        user,
+        denom,
+        Some(UserIsolatedDebtData {
+            isolated_collateral,
+            debt_usd: user_debt_usd + borrow_usd,
+        }),
+    );
 }
 
-pub fn get_total_reserves_by_token(env: Env, denom: Symbol) -> u128 {
-    let token_liquidity: u128 = get_available_liquidity_by_token(env.clone(), denom.clone());
-    let borrowed_by_token: u128 = get_total_borrowed_by_token(env.clone(), denom.clone());
-    token_liquidity + borrowed_by_token
+pub fn execute_charge_isolated_debt(env: Env, user: Address) {
+    // borrows taken before the isolated collateral was enabled are checked and charged to it
+    let isolated_collateral: Symbol = match get_user_isolated_collateral(env.clone(), user.clone())
+    {
+        Some(isolated_collateral) => isolated_collateral,
+        None => return,
+    };
+
+    for denom in get_supported_tokens(env.clone()).iter() {
+        let charged: bool = get_user_isolated_debt(env.clone(), user.clone(), denom.clone())
+            .is_some_and(|user_isolated_debt| {
+                user_isolated_debt.isolated_collateral == isolated_collateral
+            });
+        if charged {
+            continue;
+        }
+
+        let borrow_amount: u128 =
+            get_user_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
+        if borrow_amount != 0 {
+            execute_add_isolated_debt(env.clone(), user.clone(), denom, borrow_amount);
+        }
+    }
 }
 
-pub fn get_liquidity_rate(env: Env, denom: Symbol) -> u128 {
-    let total_borrow_data: TotalBorrowData = get_total_borrow_data(env.clone(), denom.clone());
-    let expected_annual_interest_income: u128 = total_borrow_data.expected_annual_interest_income;
+pub fn execute_reduce_isolated_debt(env: Env, user: Address, denom: Symbol, amount: u128) {
+    // releases the share of the USD value charged at borrow time that matches the repaid share
+    let user_isolated_debt: UserIsolatedDebtData =
+        match get_user_isolated_debt(env.clone(), user.clone(), denom.clone()) {
+            Some(user_isolated_debt) => user_isolated_debt,
+            None => return,
+        };
 
-    let reserves_by_token: u128 = get_total_reserves_by_token(env.clone(), denom.clone());
+    let borrow_amount: u128 =
+        get_user_borrow_amount_with_interest(env.clone(), user.clone(), denom.clone());
 
-    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+    let mut released_usd: u128 = user_isolated_debt.debt_usd;
+    if amount < borrow_amount {
+        let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
 
-    if reserves_by_token == 0 {
-        0u128
+        released_usd = Decimal::from_i128_with_scale(amount as i128, token_decimals)
+            .div(Decimal::from_i128_with_scale(
+                borrow_amount as i128,
+                token_decimals,
+            ))
+            .mul(Decimal::from_i128_with_scale(
+                user_isolated_debt.debt_usd as i128,
+                USD_DECIMALS,
+            ))
+            .to_u128_with_decimals(USD_DECIMALS)
+            .unwrap_or_overflow(&env);
+    }
+
+    let isolated_debt: u128 =
+        get_isolated_debt(env.clone(), user_isolated_debt.isolated_collateral.clone())
+            .checked_sub(released_usd)
+            .unwrap_or_default();
+    set_isolated_debt(
+        env.clone(),
+        user_isolated_debt.isolated_collateral.clone(),
+        isolated_debt,
+    );
+
+    if released_usd == user_isolated_debt.debt_usd {
+        set_user_isolated_debt(env, user, denom, None);
     } else {
-        let liquidity_rate: u128 = Decimal::from_i128_with_scale(
-            expected_annual_interest_income as i128,
-            INTEREST_RATE_DECIMALS,
-        )
-        .mul(Decimal::from_i128_with_scale(HUNDRED as i128, 0u32))
-        .div(Decimal::from_i128_with_scale(
-            reserves_by_token as i128,
-            token_decimals,
-        ))
-        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-        .unwrap();
+        set_user_isolated_debt(
+            env,
+            user,
+            denom,
+            Some(UserIsolatedDebtData {
+                debt_usd: user_isolated_debt.debt_usd - released_usd,
+                ..user_isolated_debt
+            }),
+        );
+    }
+}
 
-        liquidity_rate
+pub fn is_market_deprecated(env: Env, denom: Symbol) -> bool {
+    env.storage()
+        .persistent()
+        .get(&DataKey::DeprecatedMarkets)
+        .unwrap_or(Map::<Symbol, bool>::new(&env))
+        .get(denom)
+        .unwrap_or(false)
+}
+
+pub fn set_market_deprecated(env: Env, denom: Symbol, deprecated: bool) {
+    let mut deprecated_markets: Map<Symbol, bool> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::DeprecatedMarkets)
+        .unwrap_or(Map::new(&env));
+    if deprecated {
+        deprecated_markets.set(denom, true);
+    } else {
+        deprecated_markets.remove(denom);
     }
+    env.storage()
+        .persistent()
+        .set(&DataKey::DeprecatedMarkets, &deprecated_markets);
+    env.storage().persistent().bump(
+        &DataKey::DeprecatedMarkets,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
 }
 
-pub fn get_current_liquidity_index_ln(env: Env, denom: Symbol) -> u128 {
-    let liquidity_rate: u128 = get_liquidity_rate(env.clone(), denom.clone());
-    let liquidity_index_data: LiquidityIndexData = env
+pub fn is_market_removed(env: Env, denom: Symbol) -> bool {
+    env.storage()
+        .persistent()
+        .get(&DataKey::RemovedMarkets)
+        .unwrap_or(Map::<Symbol, bool>::new(&env))
+        .get(denom)
+        .unwrap_or(false)
+}
+
+pub fn set_market_removed(env: Env, denom: Symbol) {
+    let mut removed_markets: Map<Symbol, bool> = env
         .storage()
         .persistent()
-        .get(&DataKey::LiquidityIndexData)
-        .unwrap_or(Map::new(&env))
-        .get(denom.clone())
-        .unwrap();
+        .get(&DataKey::RemovedMarkets)
+        .unwrap_or(Map::new(&env));
+    removed_markets.set(denom, true);
+    env.storage()
+        .persistent()
+        .set(&DataKey::RemovedMarkets, &removed_markets);
+    env.storage().persistent().bump(
+        &DataKey::RemovedMarkets,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
 
-    let liquidity_index_last_update: u64 = liquidity_index_data.timestamp;
+pub fn check_market_active(env: Env, denom: Symbol) -> MarketFlags {
+    // rejects paused protocol or market, the flags are returned for the freeze checks
+    if is_paused(&env) {
+        panic_with_error!(&env, LendingError::ProtocolPaused);
+    }
 
-    let liquidity_index_ln: u128 = liquidity_index_data.liquidity_index_ln;
+    let market_flags: MarketFlags = get_market_flags(env.clone(), denom);
 
-    let new_liquidity_index_ln: u128 = ((env.ledger().timestamp())
-        .checked_sub(liquidity_index_last_update)
-        .unwrap_or_default()) as u128
-        * Decimal::from_i128_with_scale(
-            (liquidity_rate / HUNDRED + INTEREST_RATE_MULTIPLIER) as i128,
-            INTEREST_RATE_DECIMALS,
-        )
-        .ln()
-        .to_u128_with_decimals(INTEREST_RATE_DECIMALS)
-        .unwrap()
-        / YEAR_IN_SECONDS
-        + liquidity_index_ln;
+    if market_flags.paused {
+        panic_with_error!(&env, LendingError::MarketPaused);
+    }
 
-    new_liquidity_index_ln
+    market_flags
 }
 
-pub fn execute_update_liquidity_index_data(env: Env, denom: Symbol) {
-    let current_liquidity_index_ln = get_current_liquidity_index_ln(env.clone(), denom.clone());
+pub fn get_price_oracle(env: Env, denom: Symbol) -> Option<Address> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::PriceOracles)
+        .unwrap_or(Map::<Symbol, Address>::new(&env))
+        .get(denom)
+}
 
-    let new_liquidity_index_data = LiquidityIndexData {
-        denom: denom.clone(),
-        liquidity_index_ln: current_liquidity_index_ln,
-        timestamp: env.ledger().timestamp(),
-    };
+pub fn set_price_oracle(env: Env, denom: Symbol, oracle: Address) {
+    let mut price_oracles: Map<Symbol, Address> = env
+        .storage()
+        .persistent()
+        .get(&DataKey::PriceOracles)
+        .unwrap_or(Map::new(&env));
+    price_oracles.set(denom, oracle);
+    env.storage()
+        .persistent()
+        .set(&DataKey::PriceOracles, &price_oracles);
+    env.storage().persistent().bump(
+        &DataKey::PriceOracles,
+        MONTH_LIFETIME_THRESHOLD,
+        MONTH_BUMP_AMOUNT,
+    );
+}
 
-    let mut liquidity_map: Map<Symbol, LiquidityIndexData> = env
+pub fn get_max_price_age(env: Env, denom: Symbol) -> u64 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::MaxPriceAge)
+        .unwrap_or(Map::<Symbol, u64>::new(&env))
+        .get(denom)
+        .unwrap_or(DEFAULT_MAX_PRICE_AGE)
+}
+
+pub fn set_max_price_age(env: Env, denom: Symbol, max_price_age: u64) {
+    let mut max_price_ages: Map<Symbol, u64> = env
         .storage()
         .persistent()
-        .get(&DataKey::LiquidityIndexData)
+        .get(&DataKey::MaxPriceAge)
         .unwrap_or(Map::new(&env));
-    liquidity_map.set(denom.clone(), new_liquidity_index_data);
+    max_price_ages.set(denom, max_price_age);
     env.storage()
         .persistent()
-        .set(&DataKey::LiquidityIndexData, &liquidity_map);
+        .set(&DataKey::MaxPriceAge, &max_price_ages);
     env.storage().persistent().bump(
-        &DataKey::LiquidityIndexData,
+        &DataKey::MaxPriceAge,
         MONTH_LIFETIME_THRESHOLD,
         MONTH_BUMP_AMOUNT,
     );
 }
 
-pub fn get_mm_token_price(env: Env, denom: Symbol) -> u128 {
-    // number of tokens that correspond to one mmToken
-    let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
+pub fn fetch_price_by_token(env: Env, denom: Symbol) -> u128 {
+    // price in USD with USD_DECIMALS, missing and stale prices are rejected
+    let token_price: TokenPrice = fetch_token_price(env.clone(), denom.clone());
 
-    let current_liquidity_index_ln: u128 =
-        get_current_liquidity_index_ln(env.clone(), denom.clone());
+    if token_price.price == 0 {
+        panic_with_error!(env, LendingError::PriceNotFound);
+    }
 
-    let mm_token_price =
-        Decimal::from_i128_with_scale(current_liquidity_index_ln as i128, INTEREST_RATE_DECIMALS)
-            .exp()
-            .to_u128_with_decimals(token_decimals)
-            .unwrap_or_default();
+    let max_price_age: u64 = get_max_price_age(env.clone(), denom);
+    let price_age: u64 = env
+        .ledger()
+        .timestamp()
+        .checked_sub(token_price.timestamp)
+        .unwrap_or_default();
 
-    mm_token_price
-}
+    if max_price_age != 0 && price_age > max_price_age {
+        panic_with_error!(env, LendingError::StalePrice);
+    }
 
-pub fn user_deposit_as_collateral(env: Env, user: Address, denom: Symbol) -> bool {
-    let use_user_deposit_as_collateral: bool = env
-        .storage()
-        .persistent()
-        .get(&DataKey::UserDepositAsCollateral(user.clone()))
-        .unwrap_or(Map::new(&env))
-        .get(denom.clone())
-        .unwrap_or(false);
+    token_price.price
+}
 
-    // // POC: Only xlm is used as a collateral
-    // let mut use_user_deposit_as_collateral: bool = false;
-    // if denom == symbol_short!("xlm") {
-    //     use_user_deposit_as_collateral = true;
-    // }
+pub fn fetch_token_price(env: Env, denom: Symbol) -> TokenPrice {
+    get_token_price(env.clone(), denom)
+        .unwrap_or_else(|| panic_with_error!(env, LendingError::PriceNotFound))
+}
 
-    use_user_deposit_as_collateral
+pub fn get_token_price(env: Env, denom: Symbol) -> Option<TokenPrice> {
+    // the market oracle takes precedence over the admin price, None when there is no price
+    match get_price_oracle(env.clone(), denom.clone()) {
+        Some(oracle) => get_oracle_price(env, denom, oracle),
+        None => env
+            .storage()
+            .persistent()
+            .get(&DataKey::Prices)
+            .unwrap_or(Map::<Symbol, TokenPrice>::new(&env))
+            .get(denom),
+    }
 }
 
-pub fn fetch_price_by_token(env: Env, denom: Symbol) -> u128 {
-    env.storage()
-        .persistent()
-        .get(&DataKey::Prices)
-        .unwrap_or(Map::new(&env))
-        .get(denom.clone())
-        .unwrap_or(0_u128)
+pub fn get_oracle_price(env: Env, denom: Symbol, oracle: Address) -> Option<TokenPrice> {
+    let oracle_client = PriceOracleClient::new(&env, &oracle);
+
+    let asset = Asset::Stellar(get_token_address(env.clone(), denom));
+    let price_data = oracle_client.lastprice(&asset)?;
+
+    if price_data.price <= 0 {
+        return None;
+    }
+
+    // converting the oracle precision into USD_DECIMALS
+    let price: u128 = Decimal::from_i128_with_scale(price_data.price, oracle_client.decimals())
+        .to_u128_with_decimals(USD_DECIMALS)
+        .unwrap_or_overflow(&env);
+
+    Some(TokenPrice {
+        price,
+        timestamp: price_data.timestamp,
+    })
 }
 
 pub fn get_user_deposited_usd(env: Env, user: Address) -> u128 {
@@ -474,6 +2479,10 @@ pub fn get_user_deposited_usd(env: Env, user: Address) -> u128 {
     for token in get_supported_tokens(env.clone()) {
         let user_deposit: u128 = get_deposit(env.clone(), user.clone(), token.clone());
 
+        if user_deposit == 0 {
+            continue;
+        }
+
         let token_decimals: u32 = get_token_decimal(env.clone(), token.clone());
 
         let price = fetch_price_by_token(env.clone(), token.clone());
@@ -481,12 +2490,19 @@ pub fn get_user_deposited_usd(env: Env, user: Address) -> u128 {
         user_deposited_usd += Decimal::from_i128_with_scale(user_deposit as i128, token_decimals)
             .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
             .to_u128_with_decimals(USD_DECIMALS)
-            .unwrap();
+            .unwrap_or_overflow(&env);
     }
 
     user_deposited_usd
 }
 
+pub fn user_has_deposits(env: Env, user: Address) -> bool {
+    // checks the balances only, without fetching any price
+    get_supported_tokens(env.clone())
+        .iter()
+        .any(|token| get_deposit(env.clone(), user.clone(), token) != 0)
+}
+
 pub fn get_user_collateral_usd(env: Env, user: Address) -> u128 {
     let mut user_collateral_usd = 0_u128;
 
@@ -499,13 +2515,13 @@ pub fn get_user_collateral_usd(env: Env, user: Address) -> u128 {
 
             let token_decimals = get_token_decimal(env.clone(), token.clone());
 
-            let price = fetch_price_by_token(env.clone(), token.clone());
+            let price = fetch_user_price_by_token(env.clone(), user.clone(), token.clone());
 
             user_collateral_usd +=
                 Decimal::from_i128_with_scale(user_deposit as i128, token_decimals)
                     .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
                     .to_u128_with_decimals(USD_DECIMALS)
-                    .unwrap()
+                    .unwrap_or_overflow(&env)
         }
     }
 
@@ -518,15 +2534,20 @@ pub fn get_user_borrowed_usd(env: Env, user: Address) -> u128 {
         let user_borrow_amount_with_interest =
             get_user_borrow_amount_with_interest(env.clone(), user.clone(), token.clone());
 
+        // markets without a borrow don't need a price
+        if user_borrow_amount_with_interest == 0 {
+            continue;
+        }
+
         let token_decimals = get_token_decimal(env.clone(), token.clone());
 
-        let price = fetch_price_by_token(env.clone(), token.clone());
+        let price = fetch_user_price_by_token(env.clone(), user.clone(), token.clone());
 
         user_borrowed_usd +=
             Decimal::from_i128_with_scale(user_borrow_amount_with_interest as i128, token_decimals)
                 .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
                 .to_u128_with_decimals(USD_DECIMALS)
-                .unwrap()
+                .unwrap_or_overflow(&env)
     }
 
     user_borrowed_usd
@@ -544,7 +2565,7 @@ pub fn get_available_to_borrow(env: Env, user: Address, denom: Symbol) -> u128 {
     if max_allowed_borrow_amount_usd > sum_user_borrow_balance_usd {
         let token_decimals = get_token_decimal(env.clone(), denom.clone());
 
-        let price = fetch_price_by_token(env.clone(), denom.clone());
+        let price = fetch_user_price_by_token(env.clone(), user.clone(), denom.clone());
 
         available_to_borrow = Decimal::from_i128_with_scale(
             (max_allowed_borrow_amount_usd - sum_user_borrow_balance_usd) as i128,
@@ -552,13 +2573,19 @@ pub fn get_available_to_borrow(env: Env, user: Address, denom: Symbol) -> u128 {
         )
         .div(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
         .to_u128_with_decimals(token_decimals)
-        .unwrap();
+        .unwrap_or_overflow(&env);
 
         let token_liquidity = get_available_liquidity_by_token(env.clone(), denom.clone());
 
         if available_to_borrow > token_liquidity {
             available_to_borrow = token_liquidity
         }
+
+        let borrow_headroom = get_borrow_headroom(env.clone(), denom.clone());
+
+        if available_to_borrow > borrow_headroom {
+            available_to_borrow = borrow_headroom
+        }
     }
 
     available_to_borrow
@@ -578,8 +2605,12 @@ pub fn get_available_to_redeem(env: Env, user: Address, denom: Symbol) -> u128 {
             let user_liquidation_threshold =
                 get_user_liquidation_threshold(env.clone(), user.clone());
 
-            let required_collateral_balance_usd =
-                sum_borrow_balance_usd * HUNDRED_PERCENT / user_liquidation_threshold;
+            // a collateral worth less than a USD unit backs nothing
+            let mut required_collateral_balance_usd: u128 = sum_borrow_balance_usd;
+            if user_liquidation_threshold != 0 {
+                required_collateral_balance_usd =
+                    sum_borrow_balance_usd * HUNDRED_PERCENT / user_liquidation_threshold;
+            }
 
             let token_liquidity: u128 =
                 get_available_liquidity_by_token(env.clone(), denom.clone());
@@ -587,7 +2618,8 @@ pub fn get_available_to_redeem(env: Env, user: Address, denom: Symbol) -> u128 {
             if sum_collateral_balance_usd >= required_collateral_balance_usd {
                 let token_decimals: u32 = get_token_decimal(env.clone(), denom.clone());
 
-                let price: u128 = fetch_price_by_token(env.clone(), denom.clone());
+                let price: u128 =
+                    fetch_user_price_by_token(env.clone(), user.clone(), denom.clone());
 
                 available_to_redeem = Decimal::from_i128_with_scale(
                     (sum_collateral_balance_usd - required_collateral_balance_usd) as i128,
@@ -595,7 +2627,7 @@ pub fn get_available_to_redeem(env: Env, user: Address, denom: Symbol) -> u128 {
                 )
                 .div(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
                 .to_u128_with_decimals(token_decimals)
-                .unwrap();
+                .unwrap_or_overflow(&env);
 
                 if available_to_redeem > user_token_balance {
                     available_to_redeem = user_token_balance;
@@ -625,24 +2657,19 @@ pub fn get_user_liquidation_threshold(env: Env, user: Address) -> u128 {
         if use_user_deposit_as_collateral {
             let user_deposit: u128 = get_deposit(env.clone(), user.clone(), token.clone());
 
-            let reserve_configuration: ReserveConfiguration = env
-                .storage()
-                .persistent()
-                .get::<DataKey, Map<Symbol, ReserveConfiguration>>(&DataKey::ReserveConfiguration)
-                .unwrap()
-                .get(token.clone())
-                .unwrap();
+            let reserve_configuration: ReserveConfiguration =
+                get_user_reserve_configuration(env.clone(), user.clone(), token.clone());
             let liquidation_threshold = reserve_configuration.liquidation_threshold;
 
             let token_decimals = get_token_decimal(env.clone(), token.clone());
 
-            let price = fetch_price_by_token(env.clone(), token.clone());
+            let price = fetch_user_price_by_token(env.clone(), user.clone(), token.clone());
 
             let user_deposit_usd =
                 Decimal::from_i128_with_scale(user_deposit as i128, token_decimals)
                     .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
                     .to_u128_with_decimals(USD_DECIMALS)
-                    .unwrap();
+                    .unwrap_or_overflow(&env);
 
             liquidation_threshold_borrow_amount_usd +=
                 user_deposit_usd * liquidation_threshold / HUNDRED_PERCENT;
@@ -650,9 +2677,172 @@ pub fn get_user_liquidation_threshold(env: Env, user: Address) -> u128 {
         }
     }
 
+    // no collateral, no threshold
+    if user_collateral_usd == 0 {
+        return 0;
+    }
+
     liquidation_threshold_borrow_amount_usd * HUNDRED_PERCENT / user_collateral_usd
 }
 
+pub fn get_account_snapshot(env: Env, user: Address) -> AccountSnapshot {
+    // the position of the user in every market, priced in a single pass
+    let mut markets: Vec<AccountMarketSnapshot> = Vec::new(&env);
+    let mut deposited_usd: u128 = 0_u128;
+    let mut collateral_usd: u128 = 0_u128;
+    let mut borrowed_usd: u128 = 0_u128;
+    let mut max_allowed_borrow_usd: u128 = 0_u128;
+    let mut liquidation_threshold_borrow_usd: u128 = 0_u128;
+
+    for token in get_supported_tokens(env.clone()) {
+        let mm_token_balance: u128 = get_mm_token_balance(env.clone(), user.clone(), token.clone());
+        let borrowed_amount: u128 =
+            get_user_borrow_amount_with_interest(env.clone(), user.clone(), token.clone());
+
+        // markets without a position don't need a price
+        if mm_token_balance == 0 && borrowed_amount == 0 {
+            continue;
+        }
+
+        let deposit: u128 = get_deposit(env.clone(), user.clone(), token.clone());
+        let use_as_collateral: bool =
+            user_deposit_as_collateral(env.clone(), user.clone(), token.clone());
+
+        let token_decimals: u32 = get_token_decimal(env.clone(), token.clone());
+        let price: u128 = fetch_user_price_by_token(env.clone(), user.clone(), token.clone());
+
+        let deposit_usd: u128 = Decimal::from_i128_with_scale(deposit as i128, token_decimals)
+            .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
+            .to_u128_with_decimals(USD_DECIMALS)
+            .unwrap_or_overflow(&env);
+        let market_borrowed_usd: u128 =
+            Decimal::from_i128_with_scale(borrowed_amount as i128, token_decimals)
+                .mul(Decimal::from_i128_with_scale(price as i128, USD_DECIMALS))
+                .to_u128_with_decimals(USD_DECIMALS)
+                .unwrap_or_overflow(&env);
+
+        deposited_usd += deposit_usd;
+        borrowed_usd += market_borrowed_usd;
+
+        if use_as_collateral {
+            let reserve_configuration: ReserveConfiguration =
+                get_user_reserve_configuration(env.clone(), user.clone(), token.clone());
+
+            collateral_usd += deposit_usd;
+            max_allowed_borrow_usd +=
+                deposit_usd * reserve_configuration.loan_to_value_ratio / HUNDRED_PERCENT;
+            liquidation_threshold_borrow_usd +=
+                deposit_usd * reserve_configuration.liquidation_threshold / HUNDRED_PERCENT;
+        }
+
+        markets.push_back(AccountMarketSnapshot {
+            denom: token,
+            deposit,
+            mm_token_balance,
+            borrowed_amount,
+            use_as_collateral,
+            deposit_usd,
+            borrowed_usd: market_borrowed_usd,
+        });
+    }
+
+    let mut liquidation_threshold: u128 = 0_u128;
+    let mut utilization_rate: u128 = 0_u128;
+    if collateral_usd != 0 {
+        liquidation_threshold = liquidation_threshold_borrow_usd * HUNDRED_PERCENT / collateral_usd;
+        utilization_rate = borrowed_usd * HUNDRED_PERCENT / collateral_usd;
+    }
+
+    let mut health_factor: u128 = u128::MAX;
+    if borrowed_usd != 0 {
+        health_factor = liquidation_threshold_borrow_usd * HUNDRED_PERCENT / borrowed_usd;
+    }
+
+    AccountSnapshot {
+        markets,
+        deposited_usd,
+        collateral_usd,
+        borrowed_usd,
+        max_allowed_borrow_usd,
+        liquidation_threshold,
+        utilization_rate,
+        health_factor,
+    }
+}
+
+pub fn get_market_overview(env: Env, denom: Symbol) -> MarketOverview {
+    let mut stable_interest_rate: u128 = 0_u128;
+    if get_stable_rate_premium(env.clone(), denom.clone()) != 0 {
+        stable_interest_rate = get_stable_interest_rate(env.clone(), denom.clone());
+    }
+
+    // a market listed before its price is set is still shown
+    let token_price: TokenPrice =
+        get_token_price(env.clone(), denom.clone()).unwrap_or(TokenPrice {
+            price: 0,
+            timestamp: 0,
+        });
+
+    MarketOverview {
+        token_info: get_token_info(env.clone(), denom.clone()),
+        reserve_configuration: get_reserve_configuration(env.clone(), denom.clone()),
+        market_flags: get_market_flags(env.clone(), denom.clone()),
+        interest_rate: get_interest_rate(env.clone(), denom.clone()),
+        stable_interest_rate,
+        liquidity_rate: get_liquidity_rate(env.clone(), denom.clone()),
+        utilization_rate: get_utilization_rate_by_token(env.clone(), denom.clone()),
+        total_reserves: get_total_reserves_by_token(env.clone(), denom.clone()),
+        total_borrowed: get_total_borrowed_by_token(env.clone(), denom.clone()),
+        price: token_price.price,
+        price_timestamp: token_price.timestamp,
+        mm_token_price: get_mm_token_price(env, denom),
+    }
+}
+
+pub fn get_markets_overview(env: Env, offset: u32, limit: u32) -> Vec<MarketOverview> {
+    // markets in the supported tokens order, a limit of 0 returns all the markets after the offset
+    let mut markets: Vec<MarketOverview> = Vec::new(&env);
+
+    for token in get_supported_tokens(env.clone())
+        .iter()
+        .skip(offset as usize)
+    {
+        if limit != 0 && markets.len() >= limit {
+            break;
+        }
+
+        markets.push_back(get_market_overview(env.clone(), token));
+    }
+
+    markets
+}
+
+pub fn is_user_healthy(env: Env, user: Address) -> bool {
+    // the user's borrow stays below the liquidation threshold of the collateral
+    let sum_borrow_balance_usd: u128 = get_user_borrowed_usd(env.clone(), user.clone());
+
+    if sum_borrow_balance_usd == 0 {
+        return true;
+    }
+
+    let sum_collateral_balance_usd: u128 = get_user_collateral_usd(env.clone(), user.clone());
+
+    if sum_collateral_balance_usd == 0 {
+        return false;
+    }
+
+    let user_liquidation_threshold: u128 =
+        get_user_liquidation_threshold(env.clone(), user.clone());
+
+    // collateral with a zero liquidation threshold backs nothing
+    if user_liquidation_threshold == 0 {
+        return false;
+    }
+
+    sum_borrow_balance_usd * HUNDRED_PERCENT / user_liquidation_threshold
+        < sum_collateral_balance_usd
+}
+
 pub fn move_token(env: &Env, token: &Address, from: &Address, to: &Address, transfer_amount: i128) {
     // new token interface
     let token_client = token::Client::new(&env, &token);
@@ -0,0 +1,13 @@
+use soroban_sdk::contracterror;
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum MMTokenError {
+    AlreadyInitialized = 1,
+    NegativeAmount = 2,
+    InsufficientAllowance = 3,
+    // The spender allowance doesn't cover the transferred amount
+    InvalidExpirationLedger = 4,
+    // A non-zero allowance can't expire before the current ledger
+}
@@ -0,0 +1,10 @@
+use soroban_sdk::{contractclient, Address, Env, Symbol};
+
+// Part of the lending contract interface used by the mm token.
+// The lending contract is the ledger of mm token balances, the mm token only exposes them
+// through the token interface.
+#[contractclient(name = "LendingClient")]
+pub trait Lending {
+    fn get_mm_token_balance(env: Env, user: Address, denom: Symbol) -> u128;
+    fn transfer_mm_token(env: Env, denom: Symbol, from: Address, to: Address, amount: u128);
+}
@@ -0,0 +1,146 @@
+#![no_std]
+
+use soroban_sdk::{
+    contract, contractimpl, panic_with_error, symbol_short, token, Address, Env, String, Symbol,
+};
+
+use crate::errors::MMTokenError;
+use crate::lending::LendingClient;
+use crate::storage::*;
+use crate::utilities::*;
+
+// Share token of a lending market.
+// Balances are kept by the lending contract, transfers go through it so that the health
+// of the sender is re-checked when the market is used as collateral.
+#[contract]
+pub struct MMToken;
+
+#[contractimpl]
+impl MMToken {
+    // Initializes the mm token of the `denom` market of the lending contract.
+    pub fn initialize(
+        env: Env,
+        lending_contract: Address,
+        denom: Symbol,
+        underlying: Address,
+        decimals: u32,
+    ) {
+        if has_lending_contract(&env) {
+            panic_with_error!(&env, MMTokenError::AlreadyInitialized);
+        }
+
+        let underlying_client = token::Client::new(&env, &underlying);
+
+        set_lending_contract(&env, &lending_contract);
+        set_denom(&env, &denom);
+        set_metadata(&env, DataKey::Decimals, &decimals);
+        set_metadata(
+            &env,
+            DataKey::Name,
+            &prefixed(&env, "mm", underlying_client.name()),
+        );
+        set_metadata(
+            &env,
+            DataKey::Symbol,
+            &prefixed(&env, "mm", underlying_client.symbol()),
+        );
+    }
+
+    pub fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+        get_allowance(&env, from, spender)
+    }
+
+    pub fn approve(
+        env: Env,
+        from: Address,
+        spender: Address,
+        amount: i128,
+        expiration_ledger: u32,
+    ) {
+        from.require_auth();
+
+        if amount < 0 {
+            panic_with_error!(&env, MMTokenError::NegativeAmount);
+        }
+
+        set_allowance(
+            &env,
+            from.clone(),
+            spender.clone(),
+            amount,
+            expiration_ledger,
+        );
+
+        env.events().publish(
+            (symbol_short!("approve"), from, spender),
+            (amount, expiration_ledger),
+        );
+    }
+
+    pub fn balance(env: Env, id: Address) -> i128 {
+        let lending_client = LendingClient::new(&env, &get_lending_contract(&env));
+        lending_client.get_mm_token_balance(&id, &get_denom(&env)) as i128
+    }
+
+    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+
+        move_mm_token(&env, from, to, amount);
+    }
+
+    pub fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+
+        spend_allowance(&env, from.clone(), spender, amount);
+        move_mm_token(&env, from, to, amount);
+    }
+
+    pub fn decimals(env: Env) -> u32 {
+        get_metadata(&env, DataKey::Decimals)
+    }
+
+    pub fn name(env: Env) -> String {
+        get_metadata(&env, DataKey::Name)
+    }
+
+    pub fn symbol(env: Env) -> String {
+        get_metadata(&env, DataKey::Symbol)
+    }
+
+    // Publishes the mint of mm tokens for a deposit, lending contract only.
+    pub fn emit_mint(env: Env, to: Address, amount: i128) {
+        let lending_contract: Address = get_lending_contract(&env);
+        lending_contract.require_auth();
+
+        env.events()
+            .publish((symbol_short!("mint"), lending_contract, to), amount);
+    }
+
+    // Publishes the burn of mm tokens for a redemption or a liquidation, lending contract only.
+    pub fn emit_burn(env: Env, from: Address, amount: i128) {
+        get_lending_contract(&env).require_auth();
+
+        env.events().publish((symbol_short!("burn"), from), amount);
+    }
+
+    pub fn get_lending_contract(env: Env) -> Address {
+        get_lending_contract(&env)
+    }
+}
+
+fn move_mm_token(env: &Env, from: Address, to: Address, amount: i128) {
+    if amount < 0 {
+        panic_with_error!(env, MMTokenError::NegativeAmount);
+    }
+
+    let lending_client = LendingClient::new(env, &get_lending_contract(env));
+    lending_client.transfer_mm_token(&get_denom(env), &from, &to, &(amount as u128));
+
+    env.events()
+        .publish((symbol_short!("transfer"), from, to), amount);
+}
+
+mod errors;
+mod lending;
+mod storage;
+mod utilities;
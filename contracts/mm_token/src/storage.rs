@@ -0,0 +1,28 @@
+use soroban_sdk::{contracttype, Address};
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    LendingContract,
+    // Address of the lending contract keeping the mm token balances
+    Denom,
+    // Denom of the lending market
+    Decimals,
+    Name,
+    Symbol,
+    Allowance(AllowanceDataKey),
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub struct AllowanceDataKey {
+    pub from: Address,
+    pub spender: Address,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
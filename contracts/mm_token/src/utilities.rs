@@ -0,0 +1,128 @@
+use soroban_sdk::{panic_with_error, Address, Env, IntoVal, String, Symbol, TryFromVal, Val};
+
+pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
+
+pub(crate) const MONTH_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub(crate) const MONTH_LIFETIME_THRESHOLD: u32 = MONTH_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+const MAX_NAME_LENGTH: usize = 64;
+
+use crate::errors::MMTokenError;
+use crate::storage::*;
+
+pub fn has_lending_contract(e: &Env) -> bool {
+    let key = DataKey::LendingContract;
+    e.storage().persistent().has(&key)
+}
+
+pub fn get_lending_contract(e: &Env) -> Address {
+    let key = DataKey::LendingContract;
+    e.storage().persistent().get(&key).unwrap()
+}
+
+pub fn set_lending_contract(e: &Env, lending_contract: &Address) {
+    let key = DataKey::LendingContract;
+    e.storage().persistent().set(&key, lending_contract);
+    e.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_denom(e: &Env) -> Symbol {
+    let key = DataKey::Denom;
+    e.storage().persistent().get(&key).unwrap()
+}
+
+pub fn set_denom(e: &Env, denom: &Symbol) {
+    let key = DataKey::Denom;
+    e.storage().persistent().set(&key, denom);
+    e.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_metadata<T: TryFromVal<Env, Val>>(e: &Env, key: DataKey) -> T {
+    e.storage().persistent().get(&key).unwrap()
+}
+
+pub fn set_metadata<T: IntoVal<Env, Val>>(e: &Env, key: DataKey, value: &T) {
+    e.storage().persistent().set(&key, value);
+    e.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_allowance(e: &Env, from: Address, spender: Address) -> i128 {
+    let key = DataKey::Allowance(AllowanceDataKey { from, spender });
+    match e
+        .storage()
+        .persistent()
+        .get::<DataKey, AllowanceValue>(&key)
+    {
+        Some(allowance) if allowance.expiration_ledger >= e.ledger().sequence() => allowance.amount,
+        _ => 0,
+    }
+}
+
+pub fn set_allowance(
+    e: &Env,
+    from: Address,
+    spender: Address,
+    amount: i128,
+    expiration_ledger: u32,
+) {
+    if amount > 0 && expiration_ledger < e.ledger().sequence() {
+        panic_with_error!(e, MMTokenError::InvalidExpirationLedger);
+    }
+
+    let key = DataKey::Allowance(AllowanceDataKey { from, spender });
+    e.storage().persistent().set(
+        &key,
+        &AllowanceValue {
+            amount,
+            expiration_ledger,
+        },
+    );
+    e.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn spend_allowance(e: &Env, from: Address, spender: Address, amount: i128) {
+    let key = DataKey::Allowance(AllowanceDataKey {
+        from: from.clone(),
+        spender: spender.clone(),
+    });
+    let allowance: AllowanceValue = e
+        .storage()
+        .persistent()
+        .get(&key)
+        .unwrap_or(AllowanceValue {
+            amount: 0,
+            expiration_ledger: 0,
+        });
+
+    if get_allowance(e, from.clone(), spender.clone()) < amount {
+        panic_with_error!(e, MMTokenError::InsufficientAllowance);
+    }
+
+    set_allowance(
+        e,
+        from,
+        spender,
+        allowance.amount - amount,
+        allowance.expiration_ledger,
+    );
+}
+
+pub fn prefixed(e: &Env, prefix: &str, s: String) -> String {
+    let len = prefix.len() + s.len() as usize;
+    if len > MAX_NAME_LENGTH {
+        return s;
+    }
+
+    let mut buffer = [0u8; MAX_NAME_LENGTH];
+    buffer[..prefix.len()].copy_from_slice(prefix.as_bytes());
+    s.copy_into_slice(&mut buffer[prefix.len()..len]);
+    String::from_bytes(e, &buffer[..len])
+}
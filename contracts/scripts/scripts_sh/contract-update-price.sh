@@ -4,5 +4,6 @@ soroban contract invoke \
   --rpc-url https://rpc-futurenet.stellar.org:443/ \
   --network-passphrase "Test SDF Future Network ; October 2022" \
   -- update_price\
+  --caller dmytro \
   --denom xlm \
   --price 11360000
\ No newline at end of file
@@ -0,0 +1,12 @@
+use soroban_sdk::contracterror;
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum VaultError {
+    AlreadyInitialized = 1,
+    StorageUpToDate = 2,
+    // The storage is already at the current version
+    NoPendingTransfer = 3,
+    // No admin transfer has been proposed
+}
@@ -1,7 +1,10 @@
 #![no_std]
 
-use soroban_sdk::{contract, contractimpl, Address, Env};
+use soroban_sdk::{
+    contract, contractimpl, panic_with_error, symbol_short, Address, BytesN, Env, Symbol,
+};
 
+use crate::errors::VaultError;
 use crate::utilities::*;
 
 #[contract]
@@ -17,12 +20,98 @@ impl VaultContract {
         admin: Address,
     ) {
         if has_admin(&env) {
-            panic!("already initialized")
+            panic_with_error!(&env, VaultError::AlreadyInitialized);
         }
 
         set_admin(&env, &admin);
         set_lending_contract(&env, &lending_contract);
         set_margin_contract(&env, &margin_contract);
+        set_storage_version(&env, STORAGE_VERSION);
+    }
+
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        env.deployer()
+            .update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.events()
+            .publish((symbol_short!("upgrade"),), new_wasm_hash);
+    }
+
+    pub fn migrate(env: Env) {
+        // Admin only, the vault storage layout hasn't changed since version 0
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        let storage_version: u32 = get_storage_version(&env);
+
+        if storage_version >= STORAGE_VERSION {
+            panic_with_error!(&env, VaultError::StorageUpToDate);
+        }
+
+        set_storage_version(&env, STORAGE_VERSION);
+
+        env.events().publish(
+            (symbol_short!("migrate"),),
+            (storage_version, STORAGE_VERSION),
+        );
+    }
+
+    pub fn get_storage_version(env: Env) -> u32 {
+        get_storage_version(&env)
+    }
+
+    pub fn get_admin(env: Env) -> Address {
+        get_admin(&env)
+    }
+
+    pub fn propose_admin(env: Env, new_admin: Address) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        set_pending_admin(&env, &new_admin);
+
+        env.events()
+            .publish((Symbol::new(&env, "propose_admin"), admin), new_admin);
+    }
+
+    pub fn accept_admin(env: Env) {
+        // Pending admin only
+        let pending_admin: Address = get_pending_admin(&env)
+            .unwrap_or_else(|| panic_with_error!(&env, VaultError::NoPendingTransfer));
+        pending_admin.require_auth();
+
+        let previous_admin: Address = get_admin(&env);
+
+        set_admin(&env, &pending_admin);
+        remove_pending_admin(&env);
+
+        env.events().publish(
+            (Symbol::new(&env, "accept_admin"), previous_admin),
+            pending_admin,
+        );
+    }
+
+    pub fn cancel_admin_transfer(env: Env) {
+        // Admin only
+        let admin: Address = get_admin(&env);
+        admin.require_auth();
+
+        let pending_admin: Address = get_pending_admin(&env)
+            .unwrap_or_else(|| panic_with_error!(&env, VaultError::NoPendingTransfer));
+
+        remove_pending_admin(&env);
+
+        env.events()
+            .publish((Symbol::new(&env, "cancel_admin_transfer"),), pending_admin);
+    }
+
+    pub fn get_pending_admin(env: Env) -> Option<Address> {
+        get_pending_admin(&env)
     }
 
     pub fn set_lending_contract(env: Env, lending_contract: Address) {
@@ -55,6 +144,15 @@ impl VaultContract {
         let lending_contract: Address = get_lending_contract(&env);
         lending_contract.require_auth();
 
+        env.events().publish(
+            (
+                symbol_short!("redeem"),
+                user_address.clone(),
+                token_address.clone(),
+            ),
+            amount,
+        );
+
         move_token(
             &env,
             &token_address,
@@ -94,6 +192,15 @@ impl VaultContract {
         let lending_contract: Address = get_lending_contract(&env);
         lending_contract.require_auth();
 
+        env.events().publish(
+            (
+                symbol_short!("borrow"),
+                user_address.clone(),
+                token_address.clone(),
+            ),
+            amount,
+        );
+
         move_token(
             &env,
             &token_address,
@@ -102,8 +209,37 @@ impl VaultContract {
             amount as i128,
         )
     }
-}
 
+    pub fn withdraw_reserves_from_vault(
+        env: Env,
+        to_address: Address,
+        token_address: Address,
+        amount: u128,
+    ) {
+        // Lending contract only
+        let lending_contract: Address = get_lending_contract(&env);
+        lending_contract.require_auth();
+
+        env.events().publish(
+            (
+                symbol_short!("reserves"),
+                to_address.clone(),
+                token_address.clone(),
+            ),
+            amount,
+        );
+
+        move_token(
+            &env,
+            &token_address,
+            &env.current_contract_address(),
+            &to_address,
+            amount as i128,
+        )
+    }
+}
 
+mod errors;
 mod storage;
+mod test;
 mod utilities;
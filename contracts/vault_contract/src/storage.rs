@@ -6,4 +6,6 @@ pub enum DataKey {
     LendingContract,
     MarginPositionsContract,
     Admin,
+    PendingAdmin,
+    StorageVersion,
 }
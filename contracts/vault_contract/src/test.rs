@@ -0,0 +1,141 @@
+#![cfg(test)]
+
+extern crate std;
+
+use crate::{VaultContract, VaultContractClient};
+
+use soroban_sdk::testutils::{Address as _, Events};
+use soroban_sdk::{symbol_short, Address, Env, String, Symbol, TryFromVal};
+
+mod token_contract {
+    soroban_sdk::contractimport!(file = "../lending/token/soroban_token_contract.optimized.wasm");
+}
+
+mod vault_contract {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/vault_contract.wasm"
+    );
+}
+
+fn create_token<'a>(env: &Env, admin: &Address) -> token_contract::Client<'a> {
+    let token_id = &env.register_contract_wasm(None, token_contract::WASM);
+    let token = token_contract::Client::new(env, token_id);
+    token.initialize(
+        admin,
+        &18,
+        &String::from_slice(env, "Eth"),
+        &String::from_slice(env, "eth"),
+    );
+    token
+}
+
+fn vault_setup<'a>() -> (Env, VaultContractClient<'a>, Address, Address) {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::random(&env);
+    let lending_contract = Address::random(&env);
+    let margin_contract = Address::random(&env);
+
+    let vault_contract_address = env.register_contract(None, VaultContract);
+    let vault_contract_client = VaultContractClient::new(&env, &vault_contract_address);
+    vault_contract_client.initialize(&lending_contract, &margin_contract, &admin);
+
+    (env, vault_contract_client, admin, lending_contract)
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #1)")]
+fn test_initialize_twice() {
+    let (env, vault_contract_client, admin, lending_contract) = vault_setup();
+
+    vault_contract_client.initialize(&lending_contract, &Address::random(&env), &admin);
+}
+
+#[test]
+fn test_admin_handover() {
+    let (env, vault_contract_client, admin, _lending_contract) = vault_setup();
+
+    let new_admin = Address::random(&env);
+
+    vault_contract_client.propose_admin(&new_admin);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(
+        vault_contract_client.get_pending_admin(),
+        Some(new_admin.clone())
+    );
+    assert_eq!(vault_contract_client.get_admin(), admin);
+
+    vault_contract_client.accept_admin();
+    assert_eq!(env.auths()[0].0, new_admin);
+    assert_eq!(vault_contract_client.get_admin(), new_admin);
+    assert_eq!(vault_contract_client.get_pending_admin(), None);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #3)")]
+fn test_accept_cancelled_admin_transfer() {
+    let (env, vault_contract_client, _admin, _lending_contract) = vault_setup();
+
+    vault_contract_client.propose_admin(&Address::random(&env));
+    vault_contract_client.cancel_admin_transfer();
+
+    vault_contract_client.accept_admin();
+}
+
+#[test]
+fn test_upgrade() {
+    let (env, vault_contract_client, admin, lending_contract) = vault_setup();
+
+    let new_wasm_hash = env.deployer().upload_contract_wasm(vault_contract::WASM);
+    vault_contract_client.upgrade(&new_wasm_hash);
+
+    let (_, topics, _) = env
+        .events()
+        .all()
+        .iter()
+        .filter(|event| event.0 == vault_contract_client.address)
+        .last()
+        .unwrap();
+    let action = Symbol::try_from_val(&env, &topics.get(0).unwrap()).unwrap();
+
+    assert_eq!(action, symbol_short!("upgrade"));
+
+    // the storage is kept by the new code
+    assert_eq!(vault_contract_client.get_admin(), admin);
+    assert_eq!(
+        vault_contract_client.get_lending_contract(),
+        lending_contract
+    );
+    assert_eq!(vault_contract_client.get_storage_version(), 1);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #2)")]
+fn test_migrate_up_to_date_storage() {
+    let (_env, vault_contract_client, _admin, _lending_contract) = vault_setup();
+
+    vault_contract_client.migrate();
+}
+
+#[test]
+fn test_withdraw_reserves_from_vault() {
+    const VAULT_BALANCE: i128 = 1000 * 10i128.pow(18); // 1000 ETH
+    const RESERVES_AMOUNT: u128 = 10 * 10u128.pow(18); // 10 ETH
+
+    let (env, vault_contract_client, admin, lending_contract) = vault_setup();
+
+    let token = create_token(&env, &admin);
+    token.mint(&vault_contract_client.address, &VAULT_BALANCE);
+
+    let treasury = Address::random(&env);
+    vault_contract_client.withdraw_reserves_from_vault(&treasury, &token.address, &RESERVES_AMOUNT);
+
+    // only the lending contract moves the reserves
+    assert_eq!(env.auths()[0].0, lending_contract);
+    assert_eq!(token.balance(&treasury), RESERVES_AMOUNT as i128);
+    assert_eq!(
+        token.balance(&vault_contract_client.address),
+        VAULT_BALANCE - RESERVES_AMOUNT as i128
+    );
+}
@@ -7,6 +7,8 @@ pub(crate) const WEEK_LIFETIME_THRESHOLD: u32 = WEEK_BUMP_AMOUNT - DAY_IN_LEDGER
 pub(crate) const MONTH_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
 pub(crate) const MONTH_LIFETIME_THRESHOLD: u32 = MONTH_BUMP_AMOUNT - DAY_IN_LEDGERS;
 
+pub(crate) const STORAGE_VERSION: u32 = 1;
+
 use crate::storage::*;
 
 pub fn has_admin(e: &Env) -> bool {
@@ -27,6 +29,37 @@ pub fn set_admin(e: &Env, admin: &Address) {
         .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
 }
 
+pub fn get_storage_version(e: &Env) -> u32 {
+    let key = DataKey::StorageVersion;
+    e.storage().persistent().get(&key).unwrap_or(0)
+}
+
+pub fn set_storage_version(e: &Env, storage_version: u32) {
+    let key = DataKey::StorageVersion;
+    e.storage().persistent().set(&key, &storage_version);
+    e.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn get_pending_admin(e: &Env) -> Option<Address> {
+    let key = DataKey::PendingAdmin;
+    e.storage().persistent().get(&key)
+}
+
+pub fn set_pending_admin(e: &Env, pending_admin: &Address) {
+    let key = DataKey::PendingAdmin;
+    e.storage().persistent().set(&key, pending_admin);
+    e.storage()
+        .persistent()
+        .bump(&key, MONTH_LIFETIME_THRESHOLD, MONTH_BUMP_AMOUNT);
+}
+
+pub fn remove_pending_admin(e: &Env) {
+    let key = DataKey::PendingAdmin;
+    e.storage().persistent().remove(&key);
+}
+
 pub fn get_lending_contract(e: &Env) -> Address {
     let key = DataKey::LendingContract;
     e.storage().persistent().get(&key).unwrap()